@@ -0,0 +1,12 @@
+use std::env;
+
+// cm:start greet
+fn greet(name: &str) -> String {
+    format!("Hello, {name}!")
+}
+// cm:end
+
+fn main() {
+    let name = env::args().nth(1).unwrap_or_else(|| "world".to_string());
+    println!("{}", greet(&name));
+}
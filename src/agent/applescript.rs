@@ -1,6 +1,65 @@
 use anyhow::Result;
 use std::process::Command;
 
+use super::typewriter::execute_typewriter;
+use super::{ActionExecutor, perform_each};
+use crate::parser::types::{Directive, SlideAction};
+
+/// Performs actions on macOS through `osascript`.
+pub struct AppleScriptExecutor;
+
+impl ActionExecutor for AppleScriptExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        perform_each(actions, |action| {
+            execute_action(action, typing_speed, typing_variance)
+        })
+    }
+}
+
+fn execute_action(action: &Directive, typing_speed: u64, typing_variance: u64) -> Result<()> {
+    match action {
+        Directive::Focus(app) => {
+            let script = focus_app_script(app);
+            run_applescript(&script)?;
+        }
+        Directive::Type(typed) => {
+            execute_typewriter(
+                &typed.segments,
+                typed.speed.unwrap_or(typing_speed),
+                typed.variance.unwrap_or(typing_variance),
+            )?;
+        }
+        Directive::Run => {
+            let script = keystroke_script("return");
+            run_applescript(&script)?;
+        }
+        Directive::Slide(slide_action) => {
+            let script = match slide_action {
+                SlideAction::Next => slide_next_script(),
+                SlideAction::Prev => slide_prev_script(),
+                SlideAction::GoTo(n) => slide_goto_script(*n),
+            };
+            run_applescript(&script)?;
+        }
+        Directive::Key(combo) => {
+            let script = keystroke_script(combo);
+            run_applescript(&script)?;
+        }
+        Directive::Clear => {
+            let script = clear_script();
+            run_applescript(&script)?;
+        }
+        // Waits, [EXEC] and client-side directives are handled by perform_action
+        _ => {}
+    }
+    Ok(())
+}
+
 pub fn focus_app_script(app_name: &str) -> String {
     let escaped = app_name.replace('\\', "\\\\").replace('"', "\\\"");
     format!("tell application \"{escaped}\" to activate")
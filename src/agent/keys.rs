@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+/// A modifier held down for a key combo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Modifier {
+    Cmd,
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl Modifier {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "cmd" | "command" => Modifier::Cmd,
+            "ctrl" | "control" => Modifier::Ctrl,
+            "shift" => Modifier::Shift,
+            "alt" | "opt" | "option" => Modifier::Alt,
+            _ => return None,
+        })
+    }
+}
+
+/// A key pressed by name rather than by the character it types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NamedKey {
+    Return,
+    Tab,
+    Space,
+    /// `delete` on a Mac keyboard.
+    Backspace,
+    /// The key that deletes forwards, `fn+delete` on a Mac keyboard.
+    ForwardDelete,
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// F1 to F12.
+    F(u8),
+}
+
+impl NamedKey {
+    /// The key for a name in any case, such as `Esc` or `pageup`.
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "return" | "enter" => NamedKey::Return,
+            "tab" => NamedKey::Tab,
+            "space" => NamedKey::Space,
+            "delete" | "backspace" => NamedKey::Backspace,
+            "forwarddelete" => NamedKey::ForwardDelete,
+            "escape" | "esc" => NamedKey::Escape,
+            "left" => NamedKey::Left,
+            "right" => NamedKey::Right,
+            "up" => NamedKey::Up,
+            "down" => NamedKey::Down,
+            "home" => NamedKey::Home,
+            "end" => NamedKey::End,
+            "pageup" => NamedKey::PageUp,
+            "pagedown" => NamedKey::PageDown,
+            function => match function.strip_prefix('f')?.parse().ok()? {
+                n @ 1..=12 => NamedKey::F(n),
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// The key at the end of a combo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Key<'a> {
+    Named(NamedKey),
+    /// A single character, in the case it was written.
+    Char(char),
+    /// A name this table does not know, which a tool may still, such as xdotool's
+    /// `Page_Up`.
+    Other(&'a str),
+}
+
+/// A combo such as `ctrl+c` or `alt+Left`, as the linux, tmux and pty executors press it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Combo<'a> {
+    pub modifiers: Vec<Modifier>,
+    pub key: Key<'a>,
+    /// The key as it was written, for errors.
+    pub name: &'a str,
+}
+
+impl<'a> Combo<'a> {
+    /// Split `combo` at each `+`. Names match in any case; `ctrl++` presses the plus key.
+    pub fn parse(combo: &'a str) -> Result<Self> {
+        let (names, key) = match combo.rsplit_once('+') {
+            Some((names, key)) if !key.is_empty() => (names.split('+').collect(), key),
+            // The plus key itself, as in `ctrl++`
+            Some((names, "")) if names.ends_with('+') => {
+                (names[..names.len() - 1].split('+').collect(), "+")
+            }
+            _ => (Vec::new(), combo),
+        };
+        let modifiers = names
+            .into_iter()
+            .map(|name| {
+                Modifier::parse(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown modifier '{name}' in '{combo}'"))
+            })
+            .collect::<Result<_>>()?;
+        let mut chars = key.chars();
+        let name = key;
+        let key = match (chars.next(), chars.next()) {
+            (Some(ch), None) => Key::Char(ch),
+            _ => NamedKey::parse(name).map_or(Key::Other(name), Key::Named),
+        };
+        Ok(Self {
+            modifiers,
+            key,
+            name,
+        })
+    }
+
+    pub fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_combo() {
+        let combo = Combo::parse("Ctrl+Shift+Esc").unwrap();
+        assert_eq!(combo.modifiers, [Modifier::Ctrl, Modifier::Shift]);
+        assert_eq!(combo.key, Key::Named(NamedKey::Escape));
+
+        let key = |combo| Combo::parse(combo).unwrap().key;
+        assert_eq!(key("ESC"), Key::Named(NamedKey::Escape));
+        assert_eq!(key("Backspace"), Key::Named(NamedKey::Backspace));
+        assert_eq!(key("PageDown"), Key::Named(NamedKey::PageDown));
+        assert_eq!(key("F12"), Key::Named(NamedKey::F(12)));
+        assert_eq!(key("f13"), Key::Other("f13"));
+        assert_eq!(key("A"), Key::Char('A'));
+        assert_eq!(key("+"), Key::Char('+'));
+        assert_eq!(key("ctrl++"), Key::Char('+'));
+        assert_eq!(
+            Combo::parse("hyper+a").unwrap_err().to_string(),
+            "Unknown modifier 'hyper' in 'hyper+a'"
+        );
+    }
+}
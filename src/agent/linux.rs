@@ -0,0 +1,482 @@
+use anyhow::Result;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use super::keys::{Combo, Key, Modifier, NamedKey};
+use super::typewriter::keystroke_delay;
+use super::{ActionExecutor, perform_each};
+use crate::parser::keys::TypeSegment;
+use crate::parser::types::{Directive, SlideAction};
+
+/// The program that sends input to a Linux desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputTool {
+    /// xdotool, for X11 sessions.
+    Xdotool,
+    /// ydotool, for Wayland sessions. It writes to `/dev/uinput`, so `ydotoold` must be
+    /// running.
+    Ydotool,
+}
+
+impl InputTool {
+    /// ydotool in a Wayland session, going by `WAYLAND_DISPLAY`, and xdotool otherwise.
+    pub fn detect() -> Self {
+        Self::for_session(std::env::var_os("WAYLAND_DISPLAY").is_some())
+    }
+
+    pub fn for_session(wayland: bool) -> Self {
+        if wayland {
+            InputTool::Ydotool
+        } else {
+            InputTool::Xdotool
+        }
+    }
+}
+
+/// Performs actions on a Linux desktop through xdotool (X11) or ydotool (Wayland).
+pub struct LinuxExecutor {
+    tool: InputTool,
+}
+
+impl LinuxExecutor {
+    pub fn new(tool: InputTool) -> Self {
+        Self { tool }
+    }
+}
+
+impl ActionExecutor for LinuxExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        perform_each(actions, |action| {
+            execute_linux_action(action, self.tool, typing_speed, typing_variance)
+        })
+    }
+}
+
+fn execute_linux_action(
+    action: &Directive,
+    tool: InputTool,
+    typing_speed: u64,
+    typing_variance: u64,
+) -> Result<()> {
+    match action {
+        Directive::Focus(app) => {
+            run_command(&focus_command(app))?;
+        }
+        Directive::Type(typed) => {
+            let commands = type_commands(
+                tool,
+                &typed.segments,
+                typed.speed.unwrap_or(typing_speed),
+                typed.variance.unwrap_or(typing_variance),
+            )?;
+            for (command, delay) in commands {
+                run_command(&command)?;
+                thread::sleep(Duration::from_millis(delay));
+            }
+        }
+        Directive::Run => {
+            run_command(&key_command(tool, "return")?)?;
+        }
+        Directive::Slide(slide_action) => {
+            for command in slide_commands(tool, slide_action)? {
+                run_command(&command)?;
+            }
+        }
+        Directive::Key(combo) => {
+            run_command(&key_command(tool, combo)?)?;
+        }
+        Directive::Clear => {
+            run_command(&clear_command(tool)?)?;
+        }
+        // Waits, [EXEC] and client-side directives are handled by perform_action
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A command line: the program followed by its arguments.
+pub type Argv = Vec<String>;
+
+fn argv(parts: &[&str]) -> Argv {
+    parts.iter().map(|part| part.to_string()).collect()
+}
+
+/// Activate the first visible window whose class matches `app`, a regular expression as
+/// xdotool takes it. ydotool cannot see windows, so this uses xdotool under Wayland too,
+/// which reaches applications running on XWayland.
+pub fn focus_command(app: &str) -> Argv {
+    argv(&[
+        "xdotool",
+        "search",
+        "--onlyvisible",
+        "--class",
+        app,
+        "windowactivate",
+        "--sync",
+    ])
+}
+
+/// Press a combo such as `ctrl+shift+t`. Modifiers are translated from the macOS names
+/// the scripts use, so `cmd` is the Super key.
+pub fn key_command(tool: InputTool, combo: &str) -> Result<Argv> {
+    let Combo {
+        modifiers,
+        key,
+        name,
+    } = Combo::parse(combo)?;
+    match tool {
+        InputTool::Xdotool => {
+            let mut keys: Vec<String> = modifiers.iter().map(|m| modifier(*m).0.into()).collect();
+            keys.push(keysym(key));
+            Ok(argv(&[
+                "xdotool",
+                "key",
+                "--clearmodifiers",
+                &keys.join("+"),
+            ]))
+        }
+        InputTool::Ydotool => {
+            let code =
+                keycode(key).ok_or_else(|| anyhow::anyhow!("Unknown key '{name}' in '{combo}'"))?;
+            let mut codes: Vec<u16> = modifiers.iter().map(|m| modifier(*m).1).collect();
+            codes.push(code);
+            // Press each key in order, then release them in reverse
+            let mut args = argv(&["ydotool", "key"]);
+            args.extend(codes.iter().map(|code| format!("{code}:1")));
+            args.extend(codes.iter().rev().map(|code| format!("{code}:0")));
+            Ok(args)
+        }
+    }
+}
+
+/// Type `text` literally in one go.
+fn type_text_command(tool: InputTool, text: &str) -> Argv {
+    match tool {
+        InputTool::Xdotool => argv(&["xdotool", "type", "--delay", "0", "--", text]),
+        InputTool::Ydotool => argv(&["ydotool", "type", "--key-delay", "0", "--", text]),
+    }
+}
+
+/// One command per keystroke, with the delay to wait after it. Key presses go in between
+/// the typed characters.
+pub fn type_commands(
+    tool: InputTool,
+    segments: &[TypeSegment],
+    speed_ms: u64,
+    variance_ms: u64,
+) -> Result<Vec<(Argv, u64)>> {
+    let mut commands = Vec::new();
+    for segment in segments {
+        match segment {
+            TypeSegment::Text(run) => commands.extend(
+                run.chars()
+                    .map(|ch| type_text_command(tool, ch.encode_utf8(&mut [0; 4]))),
+            ),
+            TypeSegment::Key { combo, repeat } => {
+                let command = key_command(tool, combo)?;
+                commands.extend(std::iter::repeat_n(command, *repeat as usize));
+            }
+        }
+    }
+    Ok(commands
+        .into_iter()
+        .map(|command| (command, keystroke_delay(speed_ms, variance_ms)))
+        .collect())
+}
+
+/// Clear the terminal with ctrl+l.
+pub fn clear_command(tool: InputTool) -> Result<Argv> {
+    key_command(tool, "ctrl+l")
+}
+
+/// Move through the slides of whichever presentation has focus: the arrow keys step, and
+/// typing a number then Return jumps, as in LibreOffice Impress and most browser decks.
+pub fn slide_commands(tool: InputTool, action: &SlideAction) -> Result<Vec<Argv>> {
+    Ok(match action {
+        SlideAction::Next => vec![key_command(tool, "right")?],
+        SlideAction::Prev => vec![key_command(tool, "left")?],
+        SlideAction::GoTo(n) => vec![
+            type_text_command(tool, &n.to_string()),
+            key_command(tool, "return")?,
+        ],
+    })
+}
+
+/// Run a command, returning what it printed.
+pub fn run_command(command: &[String]) -> Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("empty command"))?;
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("cannot run {program}: {e} (is it installed?)"))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        anyhow::bail!("{program} error: {err}");
+    }
+}
+
+/// The X keysym and the input event code for a modifier.
+fn modifier(modifier: Modifier) -> (&'static str, u16) {
+    match modifier {
+        Modifier::Cmd => ("super", 125),
+        Modifier::Ctrl => ("ctrl", 29),
+        Modifier::Shift => ("shift", 42),
+        Modifier::Alt => ("alt", 56),
+    }
+}
+
+/// The X keysym for a key, e.g. `BackSpace` for `delete`. Names xdotool already knows,
+/// such as `Page_Up`, pass through.
+fn keysym(key: Key) -> String {
+    let name = match key {
+        Key::Named(NamedKey::F(n)) => return format!("F{n}"),
+        Key::Named(named) => match named {
+            NamedKey::Return => "Return",
+            NamedKey::Tab => "Tab",
+            NamedKey::Space => "space",
+            NamedKey::Backspace => "BackSpace",
+            NamedKey::ForwardDelete => "Delete",
+            NamedKey::Escape => "Escape",
+            NamedKey::Left => "Left",
+            NamedKey::Right => "Right",
+            NamedKey::Up => "Up",
+            NamedKey::Down => "Down",
+            NamedKey::Home => "Home",
+            NamedKey::End => "End",
+            NamedKey::PageUp => "Page_Up",
+            NamedKey::PageDown => "Page_Down",
+            NamedKey::F(_) => unreachable!("function keys are named above"),
+        },
+        Key::Char(ch) => match ch {
+            ' ' => "space",
+            '/' => "slash",
+            '\\' => "backslash",
+            '.' => "period",
+            ',' => "comma",
+            '-' => "minus",
+            '=' => "equal",
+            ';' => "semicolon",
+            '\'' => "apostrophe",
+            '`' => "grave",
+            '[' => "bracketleft",
+            ']' => "bracketright",
+            '+' => "plus",
+            ch => return ch.to_string(),
+        },
+        Key::Other(name) => name,
+    };
+    name.to_string()
+}
+
+/// The Linux input event code for a key, as ydotool takes it.
+fn keycode(key: Key) -> Option<u16> {
+    const LETTERS: [u16; 26] = [
+        30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17,
+        45, 21, 44,
+    ];
+    let code = match key {
+        Key::Char(ch) => match ch.to_ascii_lowercase() {
+            ch @ 'a'..='z' => LETTERS[ch as usize - 'a' as usize],
+            '0' => 11,
+            ch @ '1'..='9' => ch as u16 - '1' as u16 + 2,
+            ' ' => 57,
+            '-' => 12,
+            '=' => 13,
+            '[' => 26,
+            ']' => 27,
+            ';' => 39,
+            '\'' => 40,
+            '`' => 41,
+            '\\' => 43,
+            ',' => 51,
+            '.' => 52,
+            '/' => 53,
+            _ => return None,
+        },
+        Key::Named(named) => match named {
+            NamedKey::Return => 28,
+            NamedKey::Tab => 15,
+            NamedKey::Space => 57,
+            NamedKey::Backspace => 14,
+            NamedKey::ForwardDelete => 111,
+            NamedKey::Escape => 1,
+            NamedKey::Left => 105,
+            NamedKey::Right => 106,
+            NamedKey::Up => 103,
+            NamedKey::Down => 108,
+            NamedKey::Home => 102,
+            NamedKey::End => 107,
+            NamedKey::PageUp => 104,
+            NamedKey::PageDown => 109,
+            NamedKey::F(11) => 87,
+            NamedKey::F(12) => 88,
+            // F1 to F10 are numbered in a run
+            NamedKey::F(n) => 58 + u16::from(n),
+        },
+        Key::Other(_) => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::types::TypeText;
+
+    fn line(command: &[String]) -> String {
+        command.join(" ")
+    }
+
+    #[test]
+    fn test_detect_tool() {
+        assert_eq!(InputTool::for_session(false), InputTool::Xdotool);
+        assert_eq!(InputTool::for_session(true), InputTool::Ydotool);
+    }
+
+    #[test]
+    fn test_focus_command() {
+        assert_eq!(
+            line(&focus_command("firefox")),
+            "xdotool search --onlyvisible --class firefox windowactivate --sync"
+        );
+        // Spaces stay inside the one argument
+        assert_eq!(focus_command("Visual Studio Code")[4], "Visual Studio Code");
+    }
+
+    #[test]
+    fn test_xdotool_keys() {
+        let key = |combo| line(&key_command(InputTool::Xdotool, combo).unwrap());
+        assert_eq!(key("return"), "xdotool key --clearmodifiers Return");
+        assert_eq!(key("ctrl+c"), "xdotool key --clearmodifiers ctrl+c");
+        assert_eq!(
+            key("cmd+shift+s"),
+            "xdotool key --clearmodifiers super+shift+s"
+        );
+        assert_eq!(key("opt+left"), "xdotool key --clearmodifiers alt+Left");
+        assert_eq!(key("ctrl+/"), "xdotool key --clearmodifiers ctrl+slash");
+        assert_eq!(key("F5"), "xdotool key --clearmodifiers F5");
+        assert_eq!(key("+"), "xdotool key --clearmodifiers plus");
+        assert_eq!(key("ctrl++"), "xdotool key --clearmodifiers ctrl+plus");
+        assert_eq!(key("Return"), "xdotool key --clearmodifiers Return");
+        assert_eq!(
+            key("shift+Escape"),
+            "xdotool key --clearmodifiers shift+Escape"
+        );
+        assert_eq!(key("A"), "xdotool key --clearmodifiers A");
+        assert_eq!(key("ESC"), "xdotool key --clearmodifiers Escape");
+        assert_eq!(key("PageUp"), "xdotool key --clearmodifiers Page_Up");
+        assert_eq!(key("Page_Down"), "xdotool key --clearmodifiers Page_Down");
+    }
+
+    #[test]
+    fn test_ydotool_keys() {
+        let key = |combo| line(&key_command(InputTool::Ydotool, combo).unwrap());
+        assert_eq!(key("return"), "ydotool key 28:1 28:0");
+        assert_eq!(key("ctrl+c"), "ydotool key 29:1 46:1 46:0 29:0");
+        assert_eq!(
+            key("cmd+shift+T"),
+            "ydotool key 125:1 42:1 20:1 20:0 42:0 125:0"
+        );
+        assert_eq!(key("f5"), "ydotool key 63:1 63:0");
+        assert_eq!(key("1"), "ydotool key 2:1 2:0");
+        assert_eq!(key("0"), "ydotool key 11:1 11:0");
+        assert_eq!(key("Backspace"), "ydotool key 14:1 14:0");
+        assert_eq!(key("home"), "ydotool key 102:1 102:0");
+        assert_eq!(
+            key_command(InputTool::Ydotool, "f13")
+                .unwrap_err()
+                .to_string(),
+            "Unknown key 'f13' in 'f13'"
+        );
+    }
+
+    #[test]
+    fn test_unknown_modifier() {
+        let err = key_command(InputTool::Xdotool, "hyper+a").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown modifier 'hyper' in 'hyper+a'");
+    }
+
+    #[test]
+    fn test_type_commands() {
+        let commands = type_commands(
+            InputTool::Xdotool,
+            &TypeText::from("ls{tab}\n").segments,
+            40,
+            0,
+        )
+        .unwrap();
+        let lines: Vec<String> = commands.iter().map(|(c, _)| line(c)).collect();
+        assert_eq!(
+            lines,
+            [
+                "xdotool type --delay 0 -- l",
+                "xdotool type --delay 0 -- s",
+                "xdotool key --clearmodifiers Tab",
+                "xdotool key --clearmodifiers Return",
+            ]
+        );
+        assert!(commands.iter().all(|(_, delay)| *delay == 40));
+
+        let commands =
+            type_commands(InputTool::Ydotool, &TypeText::from("-x").segments, 40, 10).unwrap();
+        assert_eq!(
+            commands[0].0,
+            argv(&["ydotool", "type", "--key-delay", "0", "--", "-"])
+        );
+        assert!(commands.iter().all(|(_, delay)| (40..=50).contains(delay)));
+    }
+
+    #[test]
+    fn test_clear_command() {
+        assert_eq!(
+            line(&clear_command(InputTool::Ydotool).unwrap()),
+            "ydotool key 29:1 38:1 38:0 29:0"
+        );
+    }
+
+    #[test]
+    fn test_slide_commands() {
+        let slide = |action| -> Vec<String> {
+            slide_commands(InputTool::Xdotool, &action)
+                .unwrap()
+                .iter()
+                .map(|c| line(c))
+                .collect()
+        };
+        assert_eq!(
+            slide(SlideAction::Next),
+            ["xdotool key --clearmodifiers Right"]
+        );
+        assert_eq!(
+            slide(SlideAction::Prev),
+            ["xdotool key --clearmodifiers Left"]
+        );
+        assert_eq!(
+            slide(SlideAction::GoTo(12)),
+            [
+                "xdotool type --delay 0 -- 12",
+                "xdotool key --clearmodifiers Return"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_command_reports_missing_program() {
+        let err = run_command(&argv(&["code-monkey-no-such-tool"])).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("cannot run code-monkey-no-such-tool")
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::{ActionExecutor, ActionFailed, run_exec};
+use crate::parser::types::Directive;
+
+/// Where an executor writes its lines, shared by the connections it serves.
+type Output = Mutex<Box<dyn Write + Send>>;
+
+/// Hand `actions` to `inner` one at a time, calling `after` with each action, how long it took
+/// and what came of it. A failure stops the batch and keeps its index in `actions`.
+fn each_action(
+    inner: &dyn ActionExecutor,
+    actions: &[Directive],
+    typing_speed: u64,
+    typing_variance: u64,
+    mut after: impl FnMut(&Directive, Duration, Result<(), &anyhow::Error>),
+) -> Result<()> {
+    for (index, action) in actions.iter().enumerate() {
+        let started = Instant::now();
+        let result = inner.execute(std::slice::from_ref(action), typing_speed, typing_variance);
+        after(action, started.elapsed(), result.as_ref().map(|_| ()));
+        result.map_err(|e| ActionFailed::new(index, e))?;
+    }
+    Ok(())
+}
+
+/// Prints each action and how long it took before passing on its result.
+pub struct LoggingExecutor {
+    inner: Box<dyn ActionExecutor>,
+    output: Output,
+}
+
+impl LoggingExecutor {
+    pub fn new(inner: Box<dyn ActionExecutor>) -> Self {
+        Self {
+            inner,
+            output: Mutex::new(Box::new(std::io::stderr())),
+        }
+    }
+
+    /// Log to `output` instead of stderr.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Mutex::new(Box::new(output));
+        self
+    }
+}
+
+impl ActionExecutor for LoggingExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        each_action(
+            self.inner.as_ref(),
+            actions,
+            typing_speed,
+            typing_variance,
+            |action, elapsed, result| {
+                let secs = elapsed.as_secs_f64();
+                let _ = match result {
+                    Ok(()) => writeln!(output, "{action} ({secs:.3}s)"),
+                    Err(e) => writeln!(output, "{action} failed after {secs:.3}s: {e:#}"),
+                };
+            },
+        )
+    }
+}
+
+/// A line of a `--record` file.
+#[derive(Serialize)]
+struct Record<'a> {
+    /// When the action finished, in milliseconds since the Unix epoch.
+    time_ms: u128,
+    action: &'a Directive,
+    duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Writes each action the inner executor runs to a JSON Lines file, with its timing and
+/// any error, so a rehearsal can be checked or replayed afterwards.
+pub struct RecordingExecutor {
+    inner: Box<dyn ActionExecutor>,
+    output: Output,
+}
+
+impl RecordingExecutor {
+    /// Record to `path`, replacing any earlier recording there.
+    pub fn create(inner: Box<dyn ActionExecutor>, path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Cannot write {}: {e}", path.display()))?;
+        Ok(Self::with_output(inner, BufWriter::new(file)))
+    }
+
+    pub fn with_output(
+        inner: Box<dyn ActionExecutor>,
+        output: impl Write + Send + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            output: Mutex::new(Box::new(output)),
+        }
+    }
+}
+
+impl ActionExecutor for RecordingExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        let mut written: Result<()> = Ok(());
+        let executed = each_action(
+            self.inner.as_ref(),
+            actions,
+            typing_speed,
+            typing_variance,
+            |action, elapsed, result| {
+                let record = Record {
+                    time_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis()),
+                    action,
+                    duration_ms: elapsed.as_millis(),
+                    error: result.err().map(|e| format!("{e:#}")),
+                };
+                written = std::mem::replace(&mut written, Ok(())).and_then(|()| {
+                    let line = serde_json::to_string(&record)?;
+                    writeln!(output, "{line}")?;
+                    Ok(())
+                });
+            },
+        );
+        // Flush per batch so the file is complete whenever the agent is stopped
+        written.and_then(|()| Ok(output.flush()?))?;
+        executed
+    }
+}
+
+/// Runs `[EXEC]` commands with `shell -c` instead of `sh -c`, as `executor.shell` in the
+/// front matter asks, and hands every other action to the inner executor.
+pub struct ShellExecutor {
+    inner: Box<dyn ActionExecutor>,
+    shell: String,
+}
+
+impl ShellExecutor {
+    pub fn new(inner: Box<dyn ActionExecutor>, shell: impl Into<String>) -> Self {
+        Self {
+            inner,
+            shell: shell.into(),
+        }
+    }
+}
+
+impl ActionExecutor for ShellExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        for (index, action) in actions.iter().enumerate() {
+            let result = match action {
+                Directive::Exec(exec) => run_exec(exec, &self.shell),
+                action => {
+                    let action = std::slice::from_ref(action);
+                    self.inner.execute(action, typing_speed, typing_variance)
+                }
+            };
+            result.map_err(|e| ActionFailed::new(index, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Performs nothing: prints the actions it would run and succeeds, for rehearsing a script
+/// on a machine that cannot run it.
+pub struct DryRunExecutor {
+    output: Output,
+}
+
+impl DryRunExecutor {
+    pub fn new() -> Self {
+        Self {
+            output: Mutex::new(Box::new(std::io::stdout())),
+        }
+    }
+
+    /// Print to `output` instead of stdout.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Mutex::new(Box::new(output));
+        self
+    }
+}
+
+impl Default for DryRunExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActionExecutor for DryRunExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(
+            output,
+            "Would run {} actions, typing at {typing_speed} ms ± {typing_variance} ms:",
+            actions.len()
+        )?;
+        for action in actions {
+            writeln!(output, "  {action}")?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::SharedBuffer;
+    use crate::parser::types::ExecCommand;
+    use std::sync::Arc;
+
+    /// Fails on `[FOCUS]` and records everything else it is given.
+    struct FocusFails(Arc<Mutex<Vec<Directive>>>);
+
+    impl ActionExecutor for FocusFails {
+        fn execute(
+            &self,
+            actions: &[Directive],
+            _typing_speed: u64,
+            _typing_variance: u64,
+        ) -> Result<()> {
+            for (index, action) in actions.iter().enumerate() {
+                if let Directive::Focus(app) = action {
+                    let error = anyhow::anyhow!("no window named {app}");
+                    return Err(ActionFailed::new(index, error).into());
+                }
+                self.0.lock().unwrap().push(action.clone());
+            }
+            Ok(())
+        }
+    }
+
+    fn actions() -> Vec<Directive> {
+        vec![
+            Directive::Type("ls".into()),
+            Directive::Run,
+            Directive::Focus("Nowhere".into()),
+            Directive::Clear,
+        ]
+    }
+
+    #[test]
+    fn test_logging_executor() {
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let log = SharedBuffer::default();
+        let executor =
+            LoggingExecutor::new(Box::new(FocusFails(executed.clone()))).with_output(log.clone());
+        let err = executor.execute(&actions(), 40, 0).unwrap_err();
+        assert_eq!(err.downcast_ref::<ActionFailed>().unwrap().index, 2);
+        assert_eq!(err.to_string(), "no window named Nowhere");
+        assert_eq!(executed.lock().unwrap().len(), 2);
+
+        let text = log.text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{text}");
+        assert!(lines[0].starts_with("[TYPE] ls ("), "{text}");
+        assert!(lines[1].starts_with("[RUN] ("), "{text}");
+        assert!(
+            lines[2].starts_with("[FOCUS] Nowhere failed after "),
+            "{text}"
+        );
+        assert!(lines[2].ends_with("s: no window named Nowhere"), "{text}");
+    }
+
+    #[test]
+    fn test_recording_executor() {
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let file = SharedBuffer::default();
+        let executor =
+            RecordingExecutor::with_output(Box::new(FocusFails(executed.clone())), file.clone());
+        executor.execute(&actions()[..2], 40, 0).unwrap();
+        executor.execute(&actions()[2..], 40, 0).unwrap_err();
+
+        let records: Vec<serde_json::Value> = file
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        let action: Directive = serde_json::from_value(records[1]["action"].clone()).unwrap();
+        assert_eq!(action, Directive::Run);
+        assert!(records[0]["time_ms"].as_u64().unwrap() > 0);
+        assert!(records[0]["duration_ms"].is_u64());
+        assert!(records[0].get("error").is_none());
+        assert_eq!(records[2]["error"], "no window named Nowhere");
+    }
+
+    #[test]
+    fn test_wrappers_stack() {
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let (log, file) = (SharedBuffer::default(), SharedBuffer::default());
+        let recording =
+            RecordingExecutor::with_output(Box::new(FocusFails(executed.clone())), file.clone());
+        let executor = LoggingExecutor::new(Box::new(recording)).with_output(log.clone());
+        executor.execute(&actions()[..2], 40, 0).unwrap();
+        assert_eq!(log.text().lines().count(), 2);
+        assert_eq!(file.text().lines().count(), 2);
+        assert_eq!(*executed.lock().unwrap(), actions()[..2]);
+    }
+
+    #[test]
+    fn test_shell_executor() {
+        let exec = Directive::Exec(ExecCommand {
+            command: "exit 0".into(),
+            timeout: Some(5),
+        });
+        let actions = [Directive::Type("ls".into()), exec, Directive::Run];
+
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let executor = ShellExecutor::new(Box::new(FocusFails(executed.clone())), "sh");
+        executor.execute(&actions, 40, 0).unwrap();
+        assert_eq!(
+            *executed.lock().unwrap(),
+            [actions[0].clone(), Directive::Run]
+        );
+
+        // `false -c ...` fails whatever the command, so this shows which shell ran it
+        let executed = Arc::new(Mutex::new(Vec::new()));
+        let executor = ShellExecutor::new(Box::new(FocusFails(executed.clone())), "false");
+        let err = executor.execute(&actions, 40, 0).unwrap_err();
+        assert_eq!(err.downcast_ref::<ActionFailed>().unwrap().index, 1);
+        assert_eq!(*executed.lock().unwrap(), [actions[0].clone()]);
+    }
+
+    #[test]
+    fn test_dry_run_executor() {
+        let out = SharedBuffer::default();
+        let executor = DryRunExecutor::new().with_output(out.clone());
+        executor.execute(&actions(), 40, 10).unwrap();
+        assert_eq!(
+            out.text(),
+            "Would run 4 actions, typing at 40 ms ± 10 ms:\n  [TYPE] ls\n  [RUN]\n  \
+             [FOCUS] Nowhere\n  [CLEAR]\n"
+        );
+    }
+}
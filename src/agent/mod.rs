@@ -1,8 +1,15 @@
 pub mod applescript;
+mod keys;
+pub mod linux;
+pub mod middleware;
+pub mod pty;
+pub mod tmux;
 pub mod typewriter;
 
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
@@ -10,67 +17,124 @@ use socket2::{SockRef, TcpKeepalive};
 
 use anyhow::Result;
 
-use crate::parser::types::{Directive, SlideAction};
+use crate::parser::types::{Directive, ExecCommand, TypeSource, TypeText};
 use crate::protocol::codec::{decode_message, encode_message};
 use crate::protocol::messages::{AckStatus, Message};
 
+pub use applescript::AppleScriptExecutor;
+pub use linux::LinuxExecutor;
+pub use pty::PtyExecutor;
+pub use tmux::TmuxExecutor;
+
+/// Raised by an [`ActionExecutor`] to say which of the actions it was given failed, so the
+/// presenter can point at the line in the script.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ActionFailed {
+    pub index: usize,
+    pub message: String,
+}
+
+impl ActionFailed {
+    pub fn new(index: usize, error: anyhow::Error) -> Self {
+        Self {
+            index,
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+/// Bytes written from one place and read from another, such as what a
+/// [`PtySession`](pty::PtySession) prints or an executor's log.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap_or_else(|e| e.into_inner())).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut bytes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 pub trait ActionExecutor: Send {
     fn execute(&self, actions: &[Directive], typing_speed: u64, typing_variance: u64)
     -> Result<()>;
 }
 
-pub struct AppleScriptExecutor;
-
-impl ActionExecutor for AppleScriptExecutor {
-    fn execute(
-        &self,
-        actions: &[Directive],
-        typing_speed: u64,
-        typing_variance: u64,
-    ) -> Result<()> {
-        for action in actions {
-            match action {
-                Directive::Focus(app) => {
-                    let script = applescript::focus_app_script(app);
-                    applescript::run_applescript(&script)?;
-                }
-                Directive::Type(text) => {
-                    typewriter::execute_typewriter(text, typing_speed, typing_variance)?;
-                }
-                Directive::Run => {
-                    let script = applescript::keystroke_script("return");
-                    applescript::run_applescript(&script)?;
-                }
-                Directive::Slide(slide_action) => {
-                    let script = match slide_action {
-                        SlideAction::Next => applescript::slide_next_script(),
-                        SlideAction::Prev => applescript::slide_prev_script(),
-                        SlideAction::GoTo(n) => applescript::slide_goto_script(*n),
-                    };
-                    applescript::run_applescript(&script)?;
-                }
-                Directive::Key(combo) => {
-                    let script = applescript::keystroke_script(combo);
-                    applescript::run_applescript(&script)?;
-                }
-                Directive::Clear => {
-                    let script = applescript::clear_script();
-                    applescript::run_applescript(&script)?;
-                }
-                Directive::Wait(secs) => {
-                    thread::sleep(Duration::from_secs(*secs));
-                }
-                Directive::Exec(cmd) => {
-                    std::process::Command::new("sh")
-                        .arg("-c")
-                        .arg(cmd)
-                        .spawn()?;
-                }
-                // Say, Pause, Section are client-side only
-                Directive::Say(_) | Directive::Pause(_) | Directive::Section(_) => {}
+/// Perform `action` if every executor does it the same way: wait out `[WAIT]`, run an
+/// `[EXEC]` command with `sh` or pass over a client-side directive. Anything else goes to
+/// `perform`. [`ShellExecutor`](middleware::ShellExecutor) runs `[EXEC]` with another
+/// shell before it gets here.
+pub(crate) fn perform_action(
+    action: &Directive,
+    perform: impl FnOnce(&Directive) -> Result<()>,
+) -> Result<()> {
+    match action {
+        Directive::Wait(secs) => {
+            thread::sleep(Duration::from_secs(*secs));
+            Ok(())
+        }
+        Directive::Exec(exec) => run_exec(exec, "sh"),
+        action if action.is_client_side() => Ok(()),
+        action => perform(action),
+    }
+}
+
+/// [`perform_action`] for each of `actions` in order. A failure stops the batch and keeps
+/// its index in `actions`.
+fn perform_each(
+    actions: &[Directive],
+    mut perform: impl FnMut(&Directive) -> Result<()>,
+) -> Result<()> {
+    for (index, action) in actions.iter().enumerate() {
+        perform_action(action, &mut perform).map_err(|e| ActionFailed::new(index, e))?;
+    }
+    Ok(())
+}
+
+/// Start an `[EXEC]` command with `shell -c`, waiting for it only when it has a timeout.
+pub(crate) fn run_exec(exec: &ExecCommand, shell: &str) -> Result<()> {
+    run_exec_with(exec, &mut std::process::Command::new(shell))
+}
+
+/// [`run_exec`] with `shell`, a shell command whose output may have been redirected.
+pub(crate) fn run_exec_with(exec: &ExecCommand, shell: &mut std::process::Command) -> Result<()> {
+    let mut child = shell.arg("-c").arg(&exec.command).spawn()?;
+    if let Some(timeout) = exec.timeout {
+        wait_with_timeout(&mut child, Duration::from_secs(timeout))?;
+    }
+    Ok(())
+}
+
+/// Wait for an `[EXEC timeout=...]` command, killing it if it runs too long. A non-zero
+/// exit is an error.
+fn wait_with_timeout(child: &mut std::process::Child, timeout: Duration) -> Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                anyhow::bail!("command exited with {status}");
             }
+            return Ok(());
         }
-        Ok(())
+        if std::time::Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("command timed out after {}s", timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(50));
     }
 }
 
@@ -79,6 +143,10 @@ pub struct Agent {
     port: u16,
     read_timeout_secs: u64,
     max_idle_timeouts: u32,
+    /// Each `[TYPE file=...]` as the agent parsed it from its copy of the files.
+    preloaded: Option<HashMap<TypeSource, TypeText>>,
+    /// Whether to keep connection messages off the terminal, which the pty mirror owns.
+    quiet: bool,
 }
 
 impl Agent {
@@ -88,9 +156,24 @@ impl Agent {
             port,
             read_timeout_secs: 60,
             max_idle_timeouts: 10, // 10 * 60s = 10 minutes max idle
+            preloaded: None,
+            quiet: false,
         }
     }
 
+    /// Type the agent's own copy of each `[TYPE file=...]` instead of the text the
+    /// presenter sends, so the files on the demo machine are the ones shown.
+    pub fn with_preloaded(mut self, preloaded: HashMap<TypeSource, TypeText>) -> Self {
+        self.preloaded = Some(preloaded);
+        self
+    }
+
+    /// Print nothing about connections, for an executor that draws on the agent's terminal.
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
     pub fn with_idle_timeout(mut self, read_timeout_secs: u64, max_idle_timeouts: u32) -> Self {
         assert!(
             read_timeout_secs >= 1,
@@ -107,16 +190,28 @@ impl Agent {
 
     pub fn run(&self) -> Result<()> {
         let listener = TcpListener::bind(("0.0.0.0", self.port))?;
-        println!("Agent listening on 0.0.0.0:{}", self.port);
+        self.log(&format!("Agent listening on 0.0.0.0:{}", self.port));
 
         loop {
             let (stream, addr) = listener.accept()?;
-            println!("Client connected from {addr}");
+            self.log(&format!("Client connected from {addr}"));
 
             if let Err(e) = self.handle_connection(stream) {
-                eprintln!("Connection error: {e}");
+                self.log_error(&format!("Connection error: {e}"));
             }
-            println!("Client disconnected. Waiting for new connection...");
+            self.log("Client disconnected. Waiting for new connection...");
+        }
+    }
+
+    fn log(&self, message: &str) {
+        if !self.quiet {
+            println!("{message}");
+        }
+    }
+
+    fn log_error(&self, message: &str) {
+        if !self.quiet {
+            eprintln!("{message}");
         }
     }
 
@@ -139,12 +234,13 @@ impl Agent {
                     idle_timeouts = 0;
                     n
                 }
-                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut
-                    || e.kind() == std::io::ErrorKind::WouldBlock =>
+                Err(ref e)
+                    if e.kind() == std::io::ErrorKind::TimedOut
+                        || e.kind() == std::io::ErrorKind::WouldBlock =>
                 {
                     idle_timeouts += 1;
                     if idle_timeouts >= self.max_idle_timeouts {
-                        eprintln!("Client idle too long, closing connection");
+                        self.log_error("Client idle too long, closing connection");
                         return Ok(());
                     }
                     continue;
@@ -168,29 +264,54 @@ impl Agent {
     fn handle_message(&self, msg: Message) -> Message {
         match msg {
             Message::Execute {
-                actions,
+                mut actions,
                 typing_speed,
                 typing_variance,
-            } => match self
-                .executor
-                .execute(&actions, typing_speed, typing_variance)
-            {
+            } => match self.apply_preloaded(&mut actions).and_then(|()| {
+                self.executor
+                    .execute(&actions, typing_speed, typing_variance)
+            }) {
                 Ok(()) => Message::Ack {
                     status: AckStatus::Ok,
                     message: None,
+                    failed_action: None,
                 },
                 Err(e) => Message::Ack {
                     status: AckStatus::Error,
                     message: Some(e.to_string()),
+                    failed_action: e.downcast_ref::<ActionFailed>().map(|f| f.index),
                 },
             },
             Message::Ping => Message::Pong,
             _ => Message::Ack {
                 status: AckStatus::Error,
                 message: Some("Unexpected message type".into()),
+                failed_action: None,
             },
         }
     }
+
+    /// In preload mode, swap the text of each `[TYPE file=...]` for the agent's copy.
+    fn apply_preloaded(&self, actions: &mut [Directive]) -> Result<()> {
+        let Some(preloaded) = &self.preloaded else {
+            return Ok(());
+        };
+        for (index, action) in actions.iter_mut().enumerate() {
+            if let Directive::Type(typed) = action
+                && let Some(source) = &typed.source
+            {
+                let own = preloaded.get(source).ok_or_else(|| {
+                    ActionFailed::new(
+                        index,
+                        anyhow::anyhow!("[TYPE {source}] is not in the agent's script"),
+                    )
+                })?;
+                typed.text = own.text.clone();
+                typed.segments = own.segments.clone();
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -240,6 +361,20 @@ mod tests {
         }
     }
 
+    /// Fails on the second action it is given.
+    struct SecondActionFails;
+
+    impl ActionExecutor for SecondActionFails {
+        fn execute(
+            &self,
+            _actions: &[Directive],
+            _typing_speed: u64,
+            _typing_variance: u64,
+        ) -> Result<()> {
+            Err(ActionFailed::new(1, anyhow::anyhow!("app not found")).into())
+        }
+    }
+
     fn start_agent(executor: Box<dyn ActionExecutor>) -> (u16, std::thread::JoinHandle<()>) {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let port = listener.local_addr().unwrap().port();
@@ -287,6 +422,7 @@ mod tests {
             Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             }
         );
 
@@ -343,14 +479,110 @@ mod tests {
         let (response, _) = decode_message(&buf[..n]).unwrap().unwrap();
 
         match response {
-            Message::Ack { status, message } => {
+            Message::Ack {
+                status,
+                message,
+                failed_action,
+            } => {
                 assert_eq!(status, AckStatus::Error);
+                assert_eq!(failed_action, None);
                 assert!(message.unwrap().contains("mock failure"));
             }
             _ => panic!("Expected Ack"),
         }
     }
 
+    #[test]
+    fn test_agent_reports_failed_action() {
+        let agent = Agent::new(Box::new(SecondActionFails), 0);
+        let response = agent.handle_message(Message::Execute {
+            actions: vec![Directive::Run, Directive::Focus("Keynote".into())],
+            typing_speed: 40,
+            typing_variance: 15,
+        });
+        assert_eq!(
+            response,
+            Message::Ack {
+                status: AckStatus::Error,
+                message: Some("app not found".into()),
+                failed_action: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn test_agent_types_preloaded_files() {
+        let (executor, calls) = MockExecutor::new();
+        let source = TypeSource {
+            path: "src/main.rs".into(),
+            lines: Some((1, 3)),
+            region: None,
+        };
+        let type_text = |text: &str, source: &TypeSource| TypeText {
+            source: Some(source.clone()),
+            ..TypeText::from(text)
+        };
+        let typed = |text: &str, source: &TypeSource| Directive::Type(type_text(text, source));
+        let agent = Agent::new(Box::new(executor), 0).with_preloaded(HashMap::from([(
+            source.clone(),
+            type_text("agent copy", &source),
+        )]));
+
+        let response = agent.handle_message(Message::Execute {
+            actions: vec![typed("presenter copy", &source), Directive::Run],
+            typing_speed: 40,
+            typing_variance: 15,
+        });
+        assert!(matches!(
+            response,
+            Message::Ack {
+                status: AckStatus::Ok,
+                ..
+            }
+        ));
+        assert_eq!(calls.lock().unwrap()[0][0], typed("agent copy", &source));
+
+        let other = TypeSource {
+            region: Some("setup".into()),
+            lines: None,
+            ..source.clone()
+        };
+        let response = agent.handle_message(Message::Execute {
+            actions: vec![Directive::Run, typed("x", &other)],
+            typing_speed: 40,
+            typing_variance: 15,
+        });
+        assert_eq!(
+            response,
+            Message::Ack {
+                status: AckStatus::Error,
+                message: Some(
+                    "[TYPE file=src/main.rs region=setup] is not in the agent's script".into()
+                ),
+                failed_action: Some(1),
+            }
+        );
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wait_with_timeout() {
+        let spawn = |cmd: &str| {
+            std::process::Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .spawn()
+                .unwrap()
+        };
+        assert!(wait_with_timeout(&mut spawn("true"), Duration::from_secs(5)).is_ok());
+
+        let err = wait_with_timeout(&mut spawn("exit 3"), Duration::from_secs(5)).unwrap_err();
+        assert!(err.to_string().contains("exit"), "{err}");
+
+        let err = wait_with_timeout(&mut spawn("sleep 5"), Duration::from_millis(100)).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+    }
+
     #[test]
     fn test_agent_accepts_reconnect() {
         let (executor, _calls) = MockExecutor::new();
@@ -404,8 +636,7 @@ mod tests {
         let port = listener.local_addr().unwrap().port();
 
         let handle = std::thread::spawn(move || {
-            let agent = Agent::new(Box::new(executor), 0)
-                .with_idle_timeout(1, 3); // 1s read timeout, 3 timeouts = 3s max idle
+            let agent = Agent::new(Box::new(executor), 0).with_idle_timeout(1, 3); // 1s read timeout, 3 timeouts = 3s max idle
             if let Ok((stream, _)) = listener.accept() {
                 let _ = agent.handle_connection(stream);
             }
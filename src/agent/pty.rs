@@ -0,0 +1,439 @@
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize, native_pty_system};
+
+use super::keys::{Combo, Key, Modifier, NamedKey};
+use super::typewriter::keystroke_delay;
+use super::{ActionExecutor, perform_each};
+use crate::parser::keys::TypeSegment;
+use crate::parser::types::Directive;
+
+/// A shell running in a pseudo-terminal that the agent owns. Everything the shell prints is
+/// copied to an output, such as the agent's own terminal.
+pub struct PtySession {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    reader: Option<thread::JoinHandle<()>>,
+}
+
+impl PtySession {
+    /// Start `command` with `sh -c`, or the user's login shell when it is `None`, in a
+    /// terminal of `cols` by `rows`. The output is copied to `output` until the shell exits.
+    pub fn spawn(
+        command: Option<&str>,
+        cols: u16,
+        rows: u16,
+        mut output: impl Write + Send + 'static,
+    ) -> Result<Self> {
+        let pair = native_pty_system().openpty(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        let mut builder = match command {
+            Some(command) => {
+                let mut builder = CommandBuilder::new("sh");
+                builder.args(["-c", command]);
+                builder
+            }
+            None => CommandBuilder::new_default_prog(),
+        };
+        builder.cwd(std::env::current_dir()?);
+        if builder.get_env("TERM").is_none() {
+            builder.env("TERM", "xterm-256color");
+        }
+        let child = pair.slave.spawn_command(builder)?;
+        // Only the child keeps the terminal open, so reads end when it exits
+        drop(pair.slave);
+
+        let mut reader = pair.master.try_clone_reader()?;
+        let writer = pair.master.take_writer()?;
+        let reader = thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || output.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+                let _ = output.flush();
+            }
+        });
+        Ok(Self {
+            master: pair.master,
+            writer,
+            child,
+            reader: Some(reader),
+        })
+    }
+
+    /// Send bytes to the shell as if they were typed.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(status) = self.child.try_wait()? {
+            anyhow::bail!("the shell in the terminal has exited ({status})");
+        }
+        self.writer.write_all(bytes)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Tell the shell the terminal is now `cols` by `rows`.
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.master.resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+    }
+}
+
+impl Drop for PtySession {
+    /// End the shell and wait for the last of its output to be copied.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        // A background job can hold the terminal open after the shell exits, so give up on
+        // the copy after a moment rather than hang
+        let deadline = Instant::now() + Duration::from_secs(1);
+        if let Some(reader) = self.reader.take() {
+            while !reader.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if reader.is_finished() {
+                let _ = reader.join();
+            }
+        }
+    }
+}
+
+/// How a session waits between keystrokes and after actions.
+pub(crate) trait Pacing {
+    /// Wait out the typewriter delay after a keystroke.
+    fn keystroke(&mut self, delay: Duration);
+
+    /// Wait after `[RUN]`, `[KEY]` or `[CLEAR]` before the next action.
+    fn settle(&mut self);
+}
+
+/// Sleeps through typewriter delays and moves straight on after each action.
+pub(crate) struct RealTime;
+
+impl Pacing for RealTime {
+    fn keystroke(&mut self, delay: Duration) {
+        thread::sleep(delay);
+    }
+
+    fn settle(&mut self) {}
+}
+
+/// Types into a shell in a pseudo-terminal the agent owns, optionally mirrored full-screen
+/// on the agent's own terminal, so a terminal demo needs no GUI automation.
+pub struct PtyExecutor {
+    session: Mutex<PtySession>,
+    /// Whether the session draws on the agent's terminal, which then follows its size.
+    mirror: Option<Mutex<(u16, u16)>>,
+}
+
+impl PtyExecutor {
+    pub fn new(session: PtySession) -> Self {
+        Self {
+            session: Mutex::new(session),
+            mirror: None,
+        }
+    }
+
+    /// Start `command`, or the user's login shell, in a terminal that fills the agent's own
+    /// screen. The agent's terminal switches to its alternate screen until the executor is
+    /// dropped.
+    pub fn mirror(command: Option<&str>) -> Result<Self> {
+        use crossterm::ExecutableCommand;
+
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        let mut stdout = std::io::stdout();
+        stdout.execute(crossterm::terminal::EnterAlternateScreen)?;
+        stdout.execute(crossterm::terminal::Clear(
+            crossterm::terminal::ClearType::All,
+        ))?;
+        stdout.execute(crossterm::cursor::MoveTo(0, 0))?;
+        let session = PtySession::spawn(command, cols, rows, std::io::stdout());
+        let session = session.inspect_err(|_| {
+            let _ = stdout.execute(crossterm::terminal::LeaveAlternateScreen);
+        })?;
+        Ok(Self {
+            session: Mutex::new(session),
+            mirror: Some(Mutex::new((cols, rows))),
+        })
+    }
+
+    /// Resize the session if the agent's terminal changed size since the last actions.
+    fn follow_terminal_size(&self, session: &PtySession) -> Result<()> {
+        let Some(mirror) = &self.mirror else {
+            return Ok(());
+        };
+        let mut size = mirror.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(current) = crossterm::terminal::size()
+            && current != *size
+        {
+            session.resize(current.0, current.1)?;
+            *size = current;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PtyExecutor {
+    fn drop(&mut self) {
+        if self.mirror.is_some() {
+            use crossterm::ExecutableCommand;
+            let _ = std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen);
+        }
+    }
+}
+
+impl ActionExecutor for PtyExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        self.follow_terminal_size(&session)?;
+        perform_each(actions, |action| {
+            execute_pty_action(
+                action,
+                &mut session,
+                typing_speed,
+                typing_variance,
+                &mut RealTime,
+            )
+        })
+    }
+}
+
+/// Perform one action in `session`, waiting as `pacing` says.
+pub(crate) fn execute_pty_action(
+    action: &Directive,
+    session: &mut PtySession,
+    typing_speed: u64,
+    typing_variance: u64,
+    pacing: &mut impl Pacing,
+) -> Result<()> {
+    match action {
+        Directive::Type(typed) => {
+            let keystrokes = type_bytes(
+                &typed.segments,
+                typed.speed.unwrap_or(typing_speed),
+                typed.variance.unwrap_or(typing_variance),
+            )?;
+            for (bytes, delay) in keystrokes {
+                session.write(&bytes)?;
+                pacing.keystroke(Duration::from_millis(delay));
+            }
+        }
+        Directive::Run => {
+            session.write(b"\r")?;
+            pacing.settle();
+        }
+        Directive::Key(combo) => {
+            session.write(&key_bytes(combo)?)?;
+            pacing.settle();
+        }
+        Directive::Clear => {
+            session.write(&key_bytes("ctrl+l")?)?;
+            pacing.settle();
+        }
+        Directive::Slide(_) => {
+            anyhow::bail!("the pty executor drives a terminal and has no slides to change");
+        }
+        // The pty is the only window, so there is nothing to focus
+        Directive::Focus(_) => {}
+        // Waits, [EXEC] and client-side directives are handled by perform_action
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The bytes a terminal sends for a combo such as `ctrl+c` or `alt+left`.
+pub fn key_bytes(combo: &str) -> Result<Vec<u8>> {
+    let parsed = Combo::parse(combo)?;
+    if parsed.has(Modifier::Cmd) {
+        anyhow::bail!("A terminal has no Command key, so the pty cannot press '{combo}'");
+    }
+    let (ctrl, alt, shift) = (
+        parsed.has(Modifier::Ctrl),
+        parsed.has(Modifier::Alt),
+        parsed.has(Modifier::Shift),
+    );
+    // xterm's modifier parameter for cursor and function keys: 1 + shift + 2*alt + 4*ctrl
+    let modifiers = 1 + u8::from(shift) + 2 * u8::from(alt) + 4 * u8::from(ctrl);
+    let csi = |code: &str, end: char| -> Vec<u8> {
+        match (modifiers, code) {
+            (1, _) => format!("\x1b[{code}{end}"),
+            (_, "") => format!("\x1b[1;{modifiers}{end}"),
+            _ => format!("\x1b[{code};{modifiers}{end}"),
+        }
+        .into_bytes()
+    };
+    let ss3 = |end: char| -> Vec<u8> {
+        if modifiers == 1 {
+            format!("\x1bO{end}").into_bytes()
+        } else {
+            format!("\x1b[1;{modifiers}{end}").into_bytes()
+        }
+    };
+
+    let bytes = match parsed.key {
+        Key::Named(NamedKey::Up) => csi("", 'A'),
+        Key::Named(NamedKey::Down) => csi("", 'B'),
+        Key::Named(NamedKey::Right) => csi("", 'C'),
+        Key::Named(NamedKey::Left) => csi("", 'D'),
+        Key::Named(NamedKey::Home) => csi("", 'H'),
+        Key::Named(NamedKey::End) => csi("", 'F'),
+        Key::Named(NamedKey::PageUp) => csi("5", '~'),
+        Key::Named(NamedKey::PageDown) => csi("6", '~'),
+        Key::Named(NamedKey::ForwardDelete) => csi("3", '~'),
+        Key::Named(NamedKey::F(n @ 1..=4)) => ss3(['P', 'Q', 'R', 'S'][usize::from(n) - 1]),
+        Key::Named(NamedKey::F(n)) => {
+            let code = ["15", "17", "18", "19", "20", "21", "23", "24"][usize::from(n) - 5];
+            csi(code, '~')
+        }
+        Key::Named(NamedKey::Tab) if shift => b"\x1b[Z".to_vec(),
+        key => {
+            let mut bytes = plain_key_bytes(key, ctrl, shift)
+                .ok_or_else(|| anyhow::anyhow!("Unknown key '{}' in '{combo}'", parsed.name))?;
+            if alt {
+                // Meta sends escape first
+                bytes.insert(0, 0x1b);
+            }
+            bytes
+        }
+    };
+    Ok(bytes)
+}
+
+/// The bytes for a key that is not a cursor or function key, with ctrl and shift applied.
+fn plain_key_bytes(key: Key, ctrl: bool, shift: bool) -> Option<Vec<u8>> {
+    let byte = match key {
+        Key::Named(NamedKey::Return) => b'\r',
+        Key::Named(NamedKey::Tab) => b'\t',
+        Key::Named(NamedKey::Space) if ctrl => 0,
+        Key::Named(NamedKey::Space) => b' ',
+        Key::Named(NamedKey::Backspace) => 0x7f,
+        Key::Named(NamedKey::Escape) => 0x1b,
+        Key::Char(ch) => {
+            if !ch.is_ascii() {
+                return (!ctrl).then(|| ch.to_string().into_bytes());
+            }
+            let byte = ch as u8;
+            if ctrl {
+                // ctrl+a is 1, ctrl+[ is escape, ctrl+? is delete
+                return match byte {
+                    b'?' => Some(vec![0x7f]),
+                    b'@'..=b'_' | b'a'..=b'z' => Some(vec![byte.to_ascii_uppercase() & 0x1f]),
+                    _ => None,
+                };
+            }
+            if shift {
+                byte.to_ascii_uppercase()
+            } else {
+                byte
+            }
+        }
+        _ => return None,
+    };
+    Some(vec![byte])
+}
+
+/// The bytes for each keystroke, with the delay to wait after it. Key presses go in between
+/// the typed characters, and a newline presses return.
+pub fn type_bytes(
+    segments: &[TypeSegment],
+    speed_ms: u64,
+    variance_ms: u64,
+) -> Result<Vec<(Vec<u8>, u64)>> {
+    let mut keystrokes = Vec::new();
+    for segment in segments {
+        match segment {
+            TypeSegment::Text(run) => keystrokes.extend(run.chars().map(|ch| match ch {
+                '\n' => vec![b'\r'],
+                ch => ch.to_string().into_bytes(),
+            })),
+            TypeSegment::Key { combo, repeat } => {
+                let bytes = key_bytes(combo)?;
+                keystrokes.extend(std::iter::repeat_n(bytes, *repeat as usize));
+            }
+        }
+    }
+    Ok(keystrokes
+        .into_iter()
+        .map(|bytes| (bytes, keystroke_delay(speed_ms, variance_ms)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::types::TypeText;
+
+    #[test]
+    fn test_key_bytes() {
+        assert_eq!(key_bytes("return").unwrap(), b"\r");
+        assert_eq!(key_bytes("ctrl+c").unwrap(), [3]);
+        assert_eq!(key_bytes("ctrl+shift+a").unwrap(), [1]);
+        assert_eq!(key_bytes("ctrl+[").unwrap(), [0x1b]);
+        assert_eq!(key_bytes("ctrl+space").unwrap(), [0]);
+        assert_eq!(key_bytes("shift+a").unwrap(), b"A");
+        assert_eq!(key_bytes("alt+b").unwrap(), b"\x1bb");
+        assert_eq!(key_bytes("shift+tab").unwrap(), b"\x1b[Z");
+        assert_eq!(key_bytes("backspace").unwrap(), [0x7f]);
+        assert_eq!(key_bytes("escape").unwrap(), [0x1b]);
+        assert_eq!(key_bytes("Return").unwrap(), b"\r");
+        assert_eq!(key_bytes("Enter").unwrap(), b"\r");
+        assert_eq!(key_bytes("ESC").unwrap(), [0x1b]);
+        assert_eq!(key_bytes("alt+Tab").unwrap(), b"\x1b\t");
+        assert_eq!(key_bytes("A").unwrap(), b"A");
+        assert!(key_bytes("ctrl+é").is_err());
+        assert!(key_bytes("hyper+a").is_err());
+        assert!(key_bytes("return2").is_err());
+        assert_eq!(
+            key_bytes("cmd+s").unwrap_err().to_string(),
+            "A terminal has no Command key, so the pty cannot press 'cmd+s'"
+        );
+    }
+
+    #[test]
+    fn test_cursor_and_function_keys() {
+        assert_eq!(key_bytes("up").unwrap(), b"\x1b[A");
+        assert_eq!(key_bytes("left").unwrap(), b"\x1b[D");
+        assert_eq!(key_bytes("ctrl+left").unwrap(), b"\x1b[1;5D");
+        assert_eq!(key_bytes("alt+right").unwrap(), b"\x1b[1;3C");
+        assert_eq!(key_bytes("ctrl+shift+up").unwrap(), b"\x1b[1;6A");
+        assert_eq!(key_bytes("home").unwrap(), b"\x1b[H");
+        assert_eq!(key_bytes("pagedown").unwrap(), b"\x1b[6~");
+        assert_eq!(key_bytes("shift+pageup").unwrap(), b"\x1b[5;2~");
+        assert_eq!(key_bytes("F1").unwrap(), b"\x1bOP");
+        assert_eq!(key_bytes("ctrl+f1").unwrap(), b"\x1b[1;5P");
+        assert_eq!(key_bytes("F5").unwrap(), b"\x1b[15~");
+        assert_eq!(key_bytes("F12").unwrap(), b"\x1b[24~");
+        assert_eq!(key_bytes("shift+Tab").unwrap(), b"\x1b[Z");
+        assert_eq!(key_bytes("PageUp").unwrap(), b"\x1b[5~");
+    }
+
+    #[test]
+    fn test_type_bytes() {
+        let segments = TypeText::from("né\n{up}{ctrl+c}").segments;
+        let keystrokes = type_bytes(&segments, 40, 0).unwrap();
+        let bytes: Vec<&[u8]> = keystrokes.iter().map(|(b, _)| b.as_slice()).collect();
+        assert_eq!(
+            bytes,
+            [b"n".as_slice(), "é".as_bytes(), b"\r", b"\x1b[A", &[3]]
+        );
+        assert!(keystrokes.iter().all(|(_, delay)| *delay == 40));
+    }
+}
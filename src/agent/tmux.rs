@@ -0,0 +1,351 @@
+use anyhow::Result;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::keys::{Combo, Key, Modifier, NamedKey};
+use super::linux::{Argv, run_command};
+use super::typewriter::keystroke_delay;
+use super::{ActionExecutor, perform_each};
+use crate::parser::keys::TypeSegment;
+use crate::parser::types::Directive;
+
+/// A tmux server and the pane that actions go to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tmux {
+    /// Name of the server socket, as given to `tmux -L`; `None` for the default server.
+    pub socket: Option<String>,
+    /// The session the demo runs in.
+    pub session: String,
+    /// The window or `window.pane` within the session that `[FOCUS]` last chose, or
+    /// `None` for the session's active pane.
+    pub focus: Option<String>,
+}
+
+impl Tmux {
+    /// Parse a target such as `demo`, `demo:editor` or `demo:editor.1`.
+    pub fn new(target: &str) -> Self {
+        let (session, focus) = match target.split_once(':') {
+            Some((session, focus)) => (session, Some(focus.to_string())),
+            None => (target, None),
+        };
+        Self {
+            socket: None,
+            session: session.to_string(),
+            focus,
+        }
+    }
+
+    pub fn with_socket(mut self, socket: impl Into<String>) -> Self {
+        self.socket = Some(socket.into());
+        self
+    }
+
+    /// The `-t` argument for the focused pane.
+    pub fn target(&self) -> String {
+        match &self.focus {
+            Some(focus) => format!("{}:{focus}", self.session),
+            None => self.session.clone(),
+        }
+    }
+
+    /// `tmux` with the socket option, followed by `args`.
+    fn command(&self, args: &[&str]) -> Argv {
+        let mut command = vec!["tmux".to_string()];
+        if let Some(socket) = &self.socket {
+            command.extend(["-L".to_string(), socket.clone()]);
+        }
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        command
+    }
+
+    /// Type `text` into the focused pane as it is, without looking up key names.
+    fn send_literal(&self, text: &str) -> Argv {
+        // tmux ends a command at an argument ending in ';' unless it is escaped
+        let text = match text.strip_suffix(';') {
+            Some(rest) => format!("{rest}\\;"),
+            None => text.to_string(),
+        };
+        self.command(&["send-keys", "-t", &self.target(), "-l", "--", &text])
+    }
+
+    /// Press a combo such as `ctrl+c` in the focused pane.
+    pub fn key_command(&self, combo: &str) -> Result<Argv> {
+        let key = key_name(combo)?;
+        Ok(self.command(&["send-keys", "-t", &self.target(), &key]))
+    }
+
+    /// One command per keystroke, with the delay to wait after it. Key presses go in
+    /// between the typed characters.
+    pub fn type_commands(
+        &self,
+        segments: &[TypeSegment],
+        speed_ms: u64,
+        variance_ms: u64,
+    ) -> Result<Vec<(Argv, u64)>> {
+        let mut commands = Vec::new();
+        for segment in segments {
+            match segment {
+                TypeSegment::Text(run) => commands.extend(
+                    run.chars()
+                        .map(|ch| self.send_literal(ch.encode_utf8(&mut [0; 4]))),
+                ),
+                TypeSegment::Key { combo, repeat } => {
+                    let command = self.key_command(combo)?;
+                    commands.extend(std::iter::repeat_n(command, *repeat as usize));
+                }
+            }
+        }
+        Ok(commands
+            .into_iter()
+            .map(|command| (command, keystroke_delay(speed_ms, variance_ms)))
+            .collect())
+    }
+
+    /// Clear the screen with ctrl+l, then the scrollback, as Cmd+K does in a macOS
+    /// terminal.
+    pub fn clear_commands(&self) -> Vec<Argv> {
+        let target = self.target();
+        vec![
+            self.command(&["send-keys", "-t", &target, "C-l"]),
+            self.command(&["clear-history", "-t", &target]),
+        ]
+    }
+
+    /// Switch to window `name`, or to a pane with `window.pane`, and aim the actions that
+    /// follow at it.
+    pub fn focus_commands(&mut self, name: &str) -> Vec<Argv> {
+        let window = name.split_once('.').map_or(name, |(window, _)| window);
+        let window_target = format!("{}:{window}", self.session);
+        self.focus = Some(name.to_string());
+        let mut commands = vec![self.command(&["select-window", "-t", &window_target])];
+        if window != name {
+            commands.push(self.command(&["select-pane", "-t", &self.target()]));
+        }
+        commands
+    }
+
+    /// The text shown in the focused pane, for checking what a demo typed.
+    pub fn capture_pane_command(&self) -> Argv {
+        self.command(&["capture-pane", "-p", "-t", &self.target()])
+    }
+}
+
+/// Performs actions in a tmux pane with `send-keys`, so terminal demos run headless.
+pub struct TmuxExecutor {
+    /// The server and pane, which `[FOCUS]` changes.
+    tmux: Mutex<Tmux>,
+}
+
+impl TmuxExecutor {
+    pub fn new(tmux: Tmux) -> Self {
+        Self {
+            tmux: Mutex::new(tmux),
+        }
+    }
+}
+
+impl ActionExecutor for TmuxExecutor {
+    fn execute(
+        &self,
+        actions: &[Directive],
+        typing_speed: u64,
+        typing_variance: u64,
+    ) -> Result<()> {
+        let mut tmux = self.tmux.lock().unwrap_or_else(|e| e.into_inner());
+        perform_each(actions, |action| {
+            execute_tmux_action(action, &mut tmux, typing_speed, typing_variance)
+        })
+    }
+}
+
+fn execute_tmux_action(
+    action: &Directive,
+    tmux: &mut Tmux,
+    typing_speed: u64,
+    typing_variance: u64,
+) -> Result<()> {
+    match action {
+        Directive::Focus(name) => {
+            for command in tmux.focus_commands(name) {
+                run_command(&command)?;
+            }
+        }
+        Directive::Type(typed) => {
+            let commands = tmux.type_commands(
+                &typed.segments,
+                typed.speed.unwrap_or(typing_speed),
+                typed.variance.unwrap_or(typing_variance),
+            )?;
+            for (command, delay) in commands {
+                run_command(&command)?;
+                thread::sleep(Duration::from_millis(delay));
+            }
+        }
+        Directive::Run => {
+            run_command(&tmux.key_command("return")?)?;
+        }
+        Directive::Key(combo) => {
+            run_command(&tmux.key_command(combo)?)?;
+        }
+        Directive::Clear => {
+            for command in tmux.clear_commands() {
+                run_command(&command)?;
+            }
+        }
+        Directive::Slide(_) => {
+            anyhow::bail!("the tmux executor drives a terminal and has no slides to change");
+        }
+        // Waits, [EXEC] and client-side directives are handled by perform_action
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The tmux name for a combo: `C-c` for `ctrl+c`, `M-Left` for `alt+left`.
+fn key_name(combo: &str) -> Result<String> {
+    let parsed = Combo::parse(combo)?;
+    if parsed.has(Modifier::Cmd) {
+        anyhow::bail!("A terminal has no Command key, so tmux cannot press '{combo}'");
+    }
+    let mut prefix = String::new();
+    if parsed.has(Modifier::Ctrl) {
+        prefix.push_str("C-");
+    }
+    if parsed.has(Modifier::Alt) {
+        prefix.push_str("M-");
+    }
+    let shift = parsed.has(Modifier::Shift);
+    let key = match parsed.key {
+        Key::Named(NamedKey::Tab) if shift => return Ok(format!("{prefix}BTab")),
+        // A shifted character is the character itself
+        Key::Char(ch) if shift => return Ok(format!("{prefix}{}", ch.to_ascii_uppercase())),
+        Key::Char(ch) => ch.to_string(),
+        Key::Named(NamedKey::F(n)) => format!("F{n}"),
+        Key::Named(named) => match named {
+            NamedKey::Return => "Enter",
+            NamedKey::Tab => "Tab",
+            NamedKey::Space => "Space",
+            NamedKey::Backspace => "BSpace",
+            NamedKey::ForwardDelete => "DC",
+            NamedKey::Escape => "Escape",
+            NamedKey::Left => "Left",
+            NamedKey::Right => "Right",
+            NamedKey::Up => "Up",
+            NamedKey::Down => "Down",
+            NamedKey::Home => "Home",
+            NamedKey::End => "End",
+            NamedKey::PageUp => "PPage",
+            NamedKey::PageDown => "NPage",
+            NamedKey::F(_) => unreachable!("function keys are named above"),
+        }
+        .to_string(),
+        // Names tmux knows itself, such as `IC` for insert
+        Key::Other(name) => name.to_string(),
+    };
+    let shift = if shift { "S-" } else { "" };
+    Ok(format!("{prefix}{shift}{key}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::types::TypeText;
+
+    fn line(command: &[String]) -> String {
+        command.join(" ")
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let tmux = Tmux::new("demo");
+        assert_eq!((tmux.session.as_str(), tmux.focus), ("demo", None));
+        let tmux = Tmux::new("demo:editor.1");
+        assert_eq!(tmux.session, "demo");
+        assert_eq!(tmux.target(), "demo:editor.1");
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_name("return").unwrap(), "Enter");
+        assert_eq!(key_name("ctrl+c").unwrap(), "C-c");
+        assert_eq!(key_name("alt+left").unwrap(), "M-Left");
+        assert_eq!(key_name("ctrl+shift+up").unwrap(), "C-S-Up");
+        assert_eq!(key_name("shift+tab").unwrap(), "BTab");
+        assert_eq!(key_name("shift+a").unwrap(), "A");
+        assert_eq!(key_name("F5").unwrap(), "F5");
+        assert_eq!(key_name("Esc").unwrap(), "Escape");
+        assert_eq!(key_name("ESC").unwrap(), "Escape");
+        assert_eq!(key_name("Backspace").unwrap(), "BSpace");
+        assert_eq!(key_name("Ctrl+c").unwrap(), "C-c");
+        assert_eq!(key_name("home").unwrap(), "Home");
+        assert_eq!(key_name("end").unwrap(), "End");
+        assert_eq!(key_name("PageUp").unwrap(), "PPage");
+        assert_eq!(key_name("shift+pagedown").unwrap(), "S-NPage");
+        assert_eq!(
+            key_name("cmd+s").unwrap_err().to_string(),
+            "A terminal has no Command key, so tmux cannot press 'cmd+s'"
+        );
+        assert!(key_name("hyper+a").is_err());
+    }
+
+    #[test]
+    fn test_type_commands() {
+        let tmux = Tmux::new("demo").with_socket("test");
+        let commands = tmux
+            .type_commands(&TypeText::from("a;{return}").segments, 40, 0)
+            .unwrap();
+        let lines: Vec<String> = commands.iter().map(|(c, _)| line(c)).collect();
+        assert_eq!(
+            lines,
+            [
+                "tmux -L test send-keys -t demo -l -- a",
+                "tmux -L test send-keys -t demo -l -- \\;",
+                "tmux -L test send-keys -t demo Enter",
+            ]
+        );
+        assert!(commands.iter().all(|(_, delay)| *delay == 40));
+    }
+
+    #[test]
+    fn test_clear_commands() {
+        let lines: Vec<String> = Tmux::new("demo")
+            .clear_commands()
+            .iter()
+            .map(|c| line(c))
+            .collect();
+        assert_eq!(
+            lines,
+            ["tmux send-keys -t demo C-l", "tmux clear-history -t demo"]
+        );
+    }
+
+    #[test]
+    fn test_focus_commands() {
+        let mut tmux = Tmux::new("demo");
+        let lines: Vec<String> = tmux
+            .focus_commands("editor")
+            .iter()
+            .map(|c| line(c))
+            .collect();
+        assert_eq!(lines, ["tmux select-window -t demo:editor"]);
+        assert_eq!(tmux.target(), "demo:editor");
+
+        let lines: Vec<String> = tmux
+            .focus_commands("shell.1")
+            .iter()
+            .map(|c| line(c))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "tmux select-window -t demo:shell",
+                "tmux select-pane -t demo:shell.1"
+            ]
+        );
+        assert_eq!(
+            line(&tmux.key_command("ctrl+c").unwrap()),
+            "tmux send-keys -t demo:shell.1 C-c"
+        );
+    }
+}
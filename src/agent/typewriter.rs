@@ -2,28 +2,41 @@ use anyhow::Result;
 use std::thread;
 use std::time::Duration;
 
-use super::applescript::{run_applescript, type_char_script};
+use super::applescript::{keystroke_script, run_applescript, type_char_script};
+use crate::parser::keys::TypeSegment;
 
+/// One AppleScript per keystroke, with the delay to wait after it. Typed runs become one
+/// keystroke per character, and key presses go in between them.
 pub fn typewriter_to_applescript(
-    text: &str,
+    segments: &[TypeSegment],
     speed_ms: u64,
     variance_ms: u64,
 ) -> Vec<(String, u64)> {
-    text.chars()
-        .map(|ch| {
-            let script = type_char_script(ch);
-            let delay = if variance_ms > 0 {
-                speed_ms + fastrand::u64(0..=variance_ms)
-            } else {
-                speed_ms
-            };
-            (script, delay)
-        })
+    let scripts = segments.iter().flat_map(|segment| -> Vec<String> {
+        match segment {
+            TypeSegment::Text(run) => run.chars().map(type_char_script).collect(),
+            TypeSegment::Key { combo, repeat } => {
+                vec![keystroke_script(combo); *repeat as usize]
+            }
+        }
+    });
+
+    scripts
+        .map(|script| (script, keystroke_delay(speed_ms, variance_ms)))
         .collect()
 }
 
-pub fn execute_typewriter(text: &str, speed_ms: u64, variance_ms: u64) -> Result<()> {
-    for (script, delay) in typewriter_to_applescript(text, speed_ms, variance_ms) {
+/// How long to wait after a keystroke: `speed_ms` plus up to `variance_ms` of jitter.
+pub fn keystroke_delay(speed_ms: u64, variance_ms: u64) -> u64 {
+    if variance_ms > 0 {
+        speed_ms + fastrand::u64(0..=variance_ms)
+    } else {
+        speed_ms
+    }
+}
+
+pub fn execute_typewriter(segments: &[TypeSegment], speed_ms: u64, variance_ms: u64) -> Result<()> {
+    for (script, delay) in typewriter_to_applescript(segments, speed_ms, variance_ms) {
         run_applescript(&script)?;
         thread::sleep(Duration::from_millis(delay));
     }
@@ -33,10 +46,15 @@ pub fn execute_typewriter(text: &str, speed_ms: u64, variance_ms: u64) -> Result
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::types::TypeText;
+
+    fn typewriter(text: &str, speed_ms: u64, variance_ms: u64) -> Vec<(String, u64)> {
+        typewriter_to_applescript(&TypeText::from(text).segments, speed_ms, variance_ms)
+    }
 
     #[test]
     fn test_typewriter_generates_per_char_scripts() {
-        let pairs = typewriter_to_applescript("hello", 40, 0);
+        let pairs = typewriter("hello", 40, 0);
         assert_eq!(pairs.len(), 5);
         for (script, delay) in &pairs {
             assert!(script.contains("keystroke"));
@@ -46,21 +64,47 @@ mod tests {
 
     #[test]
     fn test_typewriter_empty_string() {
-        let pairs = typewriter_to_applescript("", 40, 0);
+        let pairs = typewriter("", 40, 0);
         assert!(pairs.is_empty());
     }
 
     #[test]
     fn test_typewriter_special_chars() {
-        let pairs = typewriter_to_applescript("a b!", 40, 0);
+        let pairs = typewriter("a b!", 40, 0);
         assert_eq!(pairs.len(), 4);
     }
 
+    #[test]
+    fn test_typewriter_newline_presses_return() {
+        let pairs = typewriter("a\nb", 40, 0);
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs[1].0.contains("key code 36"));
+    }
+
     #[test]
     fn test_typewriter_variance_range() {
-        let pairs = typewriter_to_applescript("test", 40, 10);
+        let pairs = typewriter("test", 40, 10);
         for (_, delay) in &pairs {
             assert!(*delay >= 40 && *delay <= 50, "delay {delay} out of range");
         }
     }
+
+    #[test]
+    fn test_typewriter_key_tokens() {
+        let pairs = typewriter(r"Vec::wi{tab}(){left x2}\\n", 40, 0);
+        let scripts: Vec<&str> = pairs.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(scripts.len(), 7 + 1 + 2 + 2 + 2);
+        assert!(scripts[7].contains("key code 48"));
+        assert!(scripts[10].contains("key code 123"));
+        assert!(scripts[11].contains("key code 123"));
+        assert_eq!(scripts[12], type_char_script('\\'));
+        assert_eq!(scripts[13], type_char_script('n'));
+    }
+
+    #[test]
+    fn test_typewriter_escaped_newline_presses_return() {
+        let pairs = typewriter(r"ls\n", 40, 0);
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs[2].0.contains("key code 36"));
+    }
 }
@@ -6,7 +6,10 @@ use socket2::{SockRef, TcpKeepalive};
 
 use anyhow::Result;
 
-use crate::grouper::{ActionBlock, BlockType, group_into_blocks};
+use crate::grouper::{
+    ActionBlock, BlockType, JumpTarget, block_index_for_label, block_index_for_line,
+    group_into_blocks, jump_targets,
+};
 use crate::parser::types::{FrontMatter, Script};
 use crate::protocol::codec::{decode_message, encode_message};
 use crate::protocol::messages::{AckStatus, Message};
@@ -42,6 +45,13 @@ impl Presenter {
         }
     }
 
+    /// Narrate in `lang`, falling back to the untagged text of blocks with no translation.
+    pub fn set_language(&mut self, lang: &str) {
+        for block in &mut self.blocks {
+            block.localize(lang);
+        }
+    }
+
     pub fn connect(&mut self) -> Result<()> {
         let mut stream = TcpStream::connect_timeout(&self.agent_addr, Duration::from_secs(5))?;
         stream.set_nodelay(true)?;
@@ -102,6 +112,47 @@ impl Presenter {
         }
     }
 
+    /// Move to the first block at or after `line_number` of the script. Returns `false`,
+    /// leaving the position unchanged, if no block is that far down.
+    pub fn start_at_line(&mut self, line_number: usize) -> bool {
+        match block_index_for_line(&self.blocks, line_number) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move to the block that `[LABEL label]` marks. Returns `false`, leaving the position
+    /// unchanged, if there is no such label.
+    pub fn jump_to_label(&mut self, label: &str) -> bool {
+        match block_index_for_label(&self.blocks, label) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The labels and sections of the script, for a "go to" picker.
+    pub fn jump_targets(&self) -> Vec<JumpTarget> {
+        jump_targets(&self.blocks)
+    }
+
+    /// Move to a target from [`Presenter::jump_targets`].
+    pub fn jump_to(&mut self, target: &JumpTarget) {
+        self.current = target.block.min(self.blocks.len());
+    }
+
+    /// Take the current block's `[SKIP_TO]` branch instead of running it. Returns the
+    /// label jumped to, or `None` if the block has no branch.
+    pub fn take_branch(&mut self) -> Option<String> {
+        let label = self.current_block()?.skip_to.clone()?;
+        self.jump_to_label(&label).then_some(label)
+    }
+
     pub fn step(&mut self) -> Result<StepResult> {
         let block = match self.blocks.get(self.current) {
             Some(b) => b.clone(),
@@ -140,9 +191,11 @@ impl Presenter {
                     Ok(Message::Ack {
                         status: AckStatus::Error,
                         message,
-                    }) => StepResult::AgentError(
-                        message.unwrap_or_else(|| "Unknown agent error".into()),
-                    ),
+                        failed_action,
+                    }) => {
+                        let message = message.unwrap_or_else(|| "Unknown agent error".into());
+                        StepResult::AgentError(describe_failure(&block, failed_action, &message))
+                    }
                     Ok(_) => StepResult::AgentError("Unexpected response from agent".into()),
                     Err(_) => {
                         self.connection = None;
@@ -182,24 +235,34 @@ impl Presenter {
     }
 }
 
+/// Prefix an agent error with where the failing action lives, e.g.
+/// `line 42: [FOCUS] Keynote failed: ...`. Falls back to the block's lines when the agent
+/// did not say which action failed.
+fn describe_failure(block: &ActionBlock, failed_action: Option<usize>, message: &str) -> String {
+    let failed =
+        failed_action.and_then(|i| Some((block.actions.get(i)?, block.action_lines.get(i)?)));
+    match (failed, block.lines) {
+        (Some((action, location)), _) => format!("{location}: {action} failed: {message}"),
+        (None, Some(lines)) => format!("{lines}: {message}"),
+        (None, None) => message.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::types::{Directive, FrontMatter, ParsedLine};
+    use crate::parser::types::{Directive, ParsedLine};
     use std::net::TcpListener;
     use std::thread;
 
     fn make_test_script(directives: Vec<Directive>) -> Script {
         Script {
-            front_matter: FrontMatter::default(),
             lines: directives
                 .into_iter()
                 .enumerate()
-                .map(|(i, directive)| ParsedLine {
-                    line_number: i + 1,
-                    directive,
-                })
+                .map(|(i, directive)| ParsedLine::new(i + 1, directive))
                 .collect(),
+            ..Default::default()
         }
     }
 
@@ -294,6 +357,7 @@ mod tests {
         let (addr, handle) = start_mock_server(vec![Message::Ack {
             status: AckStatus::Ok,
             message: None,
+            failed_action: None,
         }]);
 
         let script = make_test_script(vec![Directive::Focus("Terminal".into()), Directive::Run]);
@@ -314,6 +378,7 @@ mod tests {
         let (addr, _handle) = start_mock_server(vec![Message::Ack {
             status: AckStatus::Error,
             message: Some("no accessibility".into()),
+            failed_action: None,
         }]);
 
         let script = make_test_script(vec![Directive::Run]);
@@ -329,20 +394,93 @@ mod tests {
         assert_eq!(presenter.progress(), (0, 1));
     }
 
+    #[test]
+    fn test_client_error_names_failing_line() {
+        let (addr, _handle) = start_mock_server(vec![Message::Ack {
+            status: AckStatus::Error,
+            message: Some("app not found".into()),
+            failed_action: Some(1),
+        }]);
+
+        let script = make_test_script(vec![
+            Directive::Say("Switch over".into()),
+            Directive::Type("ls".into()),
+            Directive::Focus("Keynote".into()),
+        ]);
+        let mut presenter = Presenter::new(script, addr);
+        presenter.connect().unwrap();
+
+        assert_eq!(
+            presenter.step().unwrap(),
+            StepResult::AgentError("line 3: [FOCUS] Keynote failed: app not found".into())
+        );
+    }
+
+    #[test]
+    fn test_start_at_line() {
+        let script = make_test_script(vec![
+            Directive::Say("one".into()),
+            Directive::Run,
+            Directive::Pause(None),
+            Directive::Say("two".into()),
+            Directive::Run,
+        ]);
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let mut presenter = Presenter::new(script, addr);
+
+        assert!(presenter.start_at_line(4));
+        assert_eq!(presenter.progress(), (2, 3));
+        assert!(presenter.start_at_line(3));
+        assert_eq!(presenter.progress(), (1, 3));
+        assert!(!presenter.start_at_line(9));
+        assert_eq!(presenter.progress(), (1, 3));
+    }
+
+    #[test]
+    fn test_jump_to_label() {
+        let script = make_test_script(vec![
+            Directive::Say("one".into()),
+            Directive::SkipTo("backup".into()),
+            Directive::Run,
+            Directive::Section("Live".into()),
+            Directive::Run,
+            Directive::Label("backup".into()),
+            Directive::Say("two".into()),
+            Directive::Run,
+        ]);
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let mut presenter = Presenter::new(script, addr);
+
+        assert!(!presenter.jump_to_label("nope"));
+        assert_eq!(presenter.take_branch().as_deref(), Some("backup"));
+        assert_eq!(presenter.progress(), (2, 3));
+        assert_eq!(presenter.take_branch(), None);
+
+        let targets = presenter.jump_targets();
+        assert_eq!(targets.len(), 2);
+        presenter.jump_to(&targets[0]);
+        assert_eq!(presenter.progress(), (1, 3));
+        assert!(presenter.jump_to_label("backup"));
+        assert_eq!(presenter.progress(), (2, 3));
+    }
+
     #[test]
     fn test_client_tracks_block_progress() {
         let responses = vec![
             Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             },
             Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             },
             Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             },
         ];
         let (addr, _handle) = start_mock_server(responses);
@@ -424,6 +562,7 @@ mod tests {
             let mut responses = vec![Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             }]
             .into_iter();
             serve_connection(&mut stream, &mut responses);
@@ -464,6 +603,7 @@ mod tests {
             let mut responses = vec![Message::Ack {
                 status: AckStatus::Ok,
                 message: None,
+                failed_action: None,
             }]
             .into_iter();
             serve_connection(&mut stream, &mut responses);
@@ -0,0 +1,312 @@
+use std::fmt::Write as _;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use crossterm::style::{StyledContent, Stylize};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::parser::lexer::{ParseError, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem in a script, ready to be rendered compiler-style with the offending line.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The lint rule that raised it, shown as `warning[rule-id]`.
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub line_number: usize,
+    pub line_content: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: error.message.clone(),
+            file: error.file.clone(),
+            line_number: error.line_number,
+            line_content: error.line_content.clone(),
+            span: error.span,
+            help: error.help.as_deref().map(str::to_string),
+        }
+    }
+}
+
+impl Diagnostic {
+    /// A non-fatal problem, such as an unknown front matter key.
+    pub fn warning(error: &ParseError) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::from(error)
+        }
+    }
+
+    /// Render as:
+    ///
+    /// ```text
+    /// error: Unknown directive: [SLDIE]
+    ///  --> demo.cm:12:2
+    ///    |
+    /// 12 | [SLDIE] next
+    ///    |  ^^^^^
+    ///    = help: did you mean SLIDE?
+    /// ```
+    ///
+    /// Only the first line of a multi-line `line_content` is shown.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |text: &str, style: fn(&str) -> StyledContent<&str>| {
+            if color {
+                style(text).to_string()
+            } else {
+                text.to_string()
+            }
+        };
+        let accent: fn(&str) -> StyledContent<&str> = match self.severity {
+            Severity::Error => |s| s.red().bold(),
+            Severity::Warning => |s| s.yellow().bold(),
+        };
+        let gutter_style: fn(&str) -> StyledContent<&str> = |s| s.blue().bold();
+
+        let source = self.source_line();
+        let span = self.first_line_span();
+        let column = self.column();
+        let file = self
+            .file
+            .as_ref()
+            .map_or("<script>".to_string(), |f| f.display().to_string());
+
+        let number = self.line_number.to_string();
+        let pad = " ".repeat(number.len());
+        let bar = paint("|", gutter_style);
+
+        let label = match self.code {
+            Some(code) => format!("{}[{code}]", self.severity.label()),
+            None => self.severity.label().to_string(),
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{} {}",
+            paint(&label, accent),
+            paint(":", |s| s.bold()),
+            paint(&self.message, |s| s.bold())
+        );
+        let _ = writeln!(
+            out,
+            "{pad}{} {file}:{}:{column}",
+            paint("-->", gutter_style),
+            self.line_number
+        );
+        let _ = writeln!(out, "{pad} {bar}");
+        let _ = writeln!(out, "{} {bar} {source}", paint(&number, gutter_style));
+        if let Some(span) = span {
+            // Keep tabs so the carets line up under the source
+            let indent: String = source[..span.start]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = source[span.start..span.end].chars().count().max(1);
+            let _ = writeln!(
+                out,
+                "{pad} {bar} {indent}{}",
+                paint(&"^".repeat(width), accent)
+            );
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{pad} {} {help}", paint("= help:", |s| s.bold()));
+        }
+        out
+    }
+
+    fn source_line(&self) -> &str {
+        self.line_content.lines().next().unwrap_or("")
+    }
+
+    /// The span, if it lies within the first line. Spans past it (or malformed ones) get
+    /// no caret.
+    pub fn first_line_span(&self) -> Option<Span> {
+        let source = self.source_line();
+        self.span
+            .filter(|s| s.start <= s.end && s.end <= source.len())
+            .filter(|s| source.is_char_boundary(s.start) && source.is_char_boundary(s.end))
+    }
+
+    /// The 1-based column, in characters, where the span starts.
+    pub fn column(&self) -> usize {
+        self.first_line_span()
+            .map_or(1, |s| self.source_line()[..s.start].chars().count() + 1)
+    }
+}
+
+/// Serialized for `--format json`, with the column worked out for editors.
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Diagnostic", 9)?;
+        state.serialize_field("severity", self.severity.label())?;
+        state.serialize_field("code", &self.code)?;
+        state.serialize_field("message", &self.message)?;
+        state.serialize_field("file", &self.file)?;
+        state.serialize_field("line", &self.line_number)?;
+        state.serialize_field("column", &self.column())?;
+        state.serialize_field("span", &self.first_line_span())?;
+        state.serialize_field("line_content", &self.line_content)?;
+        state.serialize_field("help", &self.help)?;
+        state.end()
+    }
+}
+
+/// Whether diagnostics written to stderr should be coloured: only on a terminal, and never
+/// when `NO_COLOR` is set.
+pub fn stderr_supports_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// The candidate closest to `word` (ignoring case), if it is close enough to be a likely
+/// typo.
+pub fn closest_match<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let word = word.to_lowercase();
+    let limit = (word.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(&word, &c.to_lowercase()), *c))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Levenshtein distance, counting an adjacent transposition as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between a[..i] and b[..j]
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_script;
+
+    fn render_first_error(input: &str) -> String {
+        let err = parse_script(input).unwrap_err();
+        Diagnostic::from(&err).render(false)
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let names = ["SAY", "TYPE", "SLIDE", "PAUSE"];
+        assert_eq!(closest_match("SLDIE", &names), Some("SLIDE"));
+        assert_eq!(closest_match("tpye", &names), Some("TYPE"));
+        assert_eq!(closest_match("PAUS", &names), Some("PAUSE"));
+        assert_eq!(closest_match("BOGUS", &names), None);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("slide", "slide"), 0);
+        assert_eq!(edit_distance("sldie", "slide"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_render_unknown_directive() {
+        let out = render_first_error("[SAY] hi\n[SLDIE] next");
+        assert_eq!(
+            out,
+            "error: Unknown directive: [SLDIE]\n \
+             --> <script>:2:2\n  \
+             |\n\
+             2 | [SLDIE] next\n  \
+             |  ^^^^^\n  \
+             = help: did you mean SLIDE?\n"
+        );
+    }
+
+    #[test]
+    fn test_render_lists_directives_when_nothing_is_close() {
+        let out = render_first_error("[BOGUS]");
+        assert!(out.contains("valid directives are SAY, TYPE"), "{out}");
+    }
+
+    #[test]
+    fn test_render_points_at_argument() {
+        let out = render_first_error("  [WAIT] soon");
+        assert!(out.contains(":1:10\n"), "{out}");
+        assert!(out.contains("|          ^^^^\n"), "{out}");
+    }
+
+    #[test]
+    fn test_render_without_span_has_no_caret() {
+        let err = ParseError::new(3, "[DEFINE a]", "Macro 'a' is already defined");
+        let out = Diagnostic::from(&err).render(false);
+        assert!(!out.contains('^'), "{out}");
+        assert!(out.contains(":3:1\n"), "{out}");
+    }
+
+    #[test]
+    fn test_render_warning() {
+        let err = ParseError::new(2, "colour: red", "Unknown front matter key 'colour'");
+        let out = Diagnostic::warning(&err).render(false);
+        assert!(out.starts_with("warning: Unknown"), "{out}");
+    }
+
+    #[test]
+    fn test_diagnostic_json() {
+        let err = parse_script("[SAY] ok\n  [SLDIE] next").unwrap_err();
+        let json = serde_json::to_value(Diagnostic::from(&err)).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["column"], 4);
+        assert_eq!(json["span"]["start"], 3);
+        assert_eq!(json["help"], "did you mean SLIDE?");
+        assert!(json["code"].is_null());
+    }
+
+    #[test]
+    fn test_render_color() {
+        let out = render_first_error("[BOGUS]");
+        assert!(!out.contains('\u{1b}'));
+        let err = parse_script("[BOGUS]").unwrap_err();
+        assert!(Diagnostic::from(&err).render(true).contains('\u{1b}'));
+    }
+}
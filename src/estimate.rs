@@ -0,0 +1,353 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::grouper::{ActionBlock, BlockType};
+use crate::parser::keys::TypeSegment;
+use crate::parser::types::{Directive, FrontMatter, Heading};
+
+/// Milliseconds charged for each action the agent runs as a single AppleScript:
+/// `[FOCUS]`, `[RUN]`, `[KEY]`, `[CLEAR]` and `[SLIDE]`.
+pub const APPLESCRIPT_COST_MS: u64 = 250;
+
+/// Reading rate for `[SAY]` narration, in words per minute.
+pub const DEFAULT_WPM: u64 = 150;
+
+/// Predicted time for a run of blocks under the same headings, in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SectionEstimate {
+    /// The innermost heading, or `None` for the blocks before the first one.
+    pub name: Option<String>,
+    /// The chapter, section and step, outermost first.
+    pub headings: Vec<Heading>,
+    /// Reading the `[SAY]` narration aloud.
+    pub narration_ms: u64,
+    /// Typing `[TYPE]` text.
+    pub typing_ms: u64,
+    /// `[WAIT n]` and `[PAUSE n]`. A `[PAUSE]` without a timeout counts as nothing.
+    pub waits_ms: u64,
+    /// Other actions, at [`APPLESCRIPT_COST_MS`] each. `[EXEC]` commands are not counted.
+    pub actions_ms: u64,
+    pub total_ms: u64,
+}
+
+/// What `code-monkey estimate` reports.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Estimate {
+    pub sections: Vec<SectionEstimate>,
+    pub total_ms: u64,
+    /// The front matter `duration:`, if any.
+    pub duration_ms: Option<u64>,
+    /// How far the total goes past `duration_ms`.
+    pub over_duration_ms: Option<u64>,
+}
+
+/// Estimate how long presenting `blocks` takes, reading narration at `wpm` words per
+/// minute. Each `[TYPE]` character costs the typing speed plus half the variance.
+pub fn estimate(blocks: &[ActionBlock], front_matter: &FrontMatter, wpm: u64) -> Estimate {
+    let mut sections: Vec<SectionEstimate> = Vec::new();
+    for block in blocks {
+        if sections.last().is_none_or(|s| s.headings != block.headings) {
+            sections.push(SectionEstimate {
+                name: block.headings.last().map(|h| h.name.clone()),
+                headings: block.headings.clone(),
+                ..Default::default()
+            });
+        }
+        let Some(section) = sections.last_mut() else {
+            continue;
+        };
+
+        if let Some(narration) = &block.narration {
+            let words = narration.split_whitespace().count() as u64;
+            let ms = words.saturating_mul(60_000) / wpm.max(1);
+            section.narration_ms = section.narration_ms.saturating_add(ms);
+        }
+        if let BlockType::Pause(Some(secs)) = block.block_type {
+            section.waits_ms = section.waits_ms.saturating_add(secs.saturating_mul(1000));
+        }
+        for action in &block.actions {
+            match action {
+                Directive::Type(typed) => {
+                    let speed = typed.speed.unwrap_or(front_matter.typing_speed);
+                    let variance = typed.variance.unwrap_or(front_matter.typing_variance);
+                    // Huge speeds or waits are only a typo away, so nothing here may overflow
+                    let per_two_keys = speed.saturating_mul(2).saturating_add(variance);
+                    let ms = keystrokes(&typed.segments).saturating_mul(per_two_keys) / 2;
+                    section.typing_ms = section.typing_ms.saturating_add(ms);
+                }
+                Directive::Wait(secs) => {
+                    section.waits_ms = section.waits_ms.saturating_add(secs.saturating_mul(1000));
+                }
+                Directive::Focus(_)
+                | Directive::Run
+                | Directive::Key(_)
+                | Directive::Clear
+                | Directive::Slide(_) => {
+                    section.actions_ms = section.actions_ms.saturating_add(APPLESCRIPT_COST_MS);
+                }
+                Directive::Exec(_)
+                | Directive::Say(_)
+                | Directive::SayIn(_)
+                | Directive::Note(_)
+                | Directive::NoteIn(_)
+                | Directive::Pause(_)
+                | Directive::Speed(_)
+                | Directive::Chapter(_)
+                | Directive::Section(_)
+                | Directive::Step(_)
+                | Directive::Label(_)
+                | Directive::SkipTo(_) => {}
+            }
+        }
+    }
+
+    for section in &mut sections {
+        section.total_ms = [section.typing_ms, section.waits_ms, section.actions_ms]
+            .into_iter()
+            .fold(section.narration_ms, u64::saturating_add);
+    }
+    let total_ms = sections
+        .iter()
+        .map(|s| s.total_ms)
+        .fold(0, u64::saturating_add);
+    let duration_ms = front_matter.duration.map(|secs| secs.saturating_mul(1000));
+    Estimate {
+        sections,
+        total_ms,
+        duration_ms,
+        over_duration_ms: duration_ms
+            .filter(|&duration| total_ms > duration)
+            .map(|duration| total_ms - duration),
+    }
+}
+
+/// Key presses needed to type `segments`: one per character, and one per repeat of a key
+/// token such as `{enter}`.
+fn keystrokes(segments: &[TypeSegment]) -> u64 {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            TypeSegment::Text(run) => run.chars().count() as u64,
+            TypeSegment::Key { repeat, .. } => u64::from(*repeat),
+        })
+        .fold(0, u64::saturating_add)
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour up, rounded to the nearest second.
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms.saturating_add(500) / 1000;
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+impl SectionEstimate {
+    fn times(&self) -> [u64; 5] {
+        [
+            self.narration_ms,
+            self.typing_ms,
+            self.waits_ms,
+            self.actions_ms,
+            self.total_ms,
+        ]
+    }
+}
+
+impl Estimate {
+    /// A row per heading, indented by depth, with the time of everything under it. A
+    /// heading that comes back after another counts as a new row.
+    fn rows(&self) -> Vec<(String, [u64; 5])> {
+        let mut rows = Vec::new();
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.headings.is_empty() {
+                rows.push(("(before sections)".to_string(), section.times()));
+                continue;
+            }
+            for depth in 1..=section.headings.len() {
+                let prefix = &section.headings[..depth];
+                if i > 0 && self.sections[i - 1].headings.starts_with(prefix) {
+                    continue;
+                }
+                let mut times = [0u64; 5];
+                for under in self.sections[i..]
+                    .iter()
+                    .take_while(|s| s.headings.starts_with(prefix))
+                {
+                    for (sum, time) in times.iter_mut().zip(under.times()) {
+                        *sum = (*sum).saturating_add(time);
+                    }
+                }
+                let name = format!("{}{}", "  ".repeat(depth - 1), prefix[depth - 1].name);
+                rows.push((name, times));
+            }
+        }
+        rows
+    }
+}
+
+/// A table with a row per heading and a total.
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.rows();
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .chain(["Section".len(), "Total".len()])
+            .max()
+            .unwrap_or_default();
+        let row = |f: &mut fmt::Formatter<'_>, label: &str, times: [String; 5]| {
+            let [narration, typing, waits, actions, total] = times;
+            writeln!(
+                f,
+                "{label:<width$}  {narration:>9}  {typing:>7}  {waits:>7}  {actions:>7}  {total:>7}"
+            )
+        };
+
+        row(
+            f,
+            "Section",
+            ["Narration", "Typing", "Waits", "Actions", "Total"].map(String::from),
+        )?;
+        for (name, times) in &rows {
+            row(f, name, times.map(format_duration))?;
+        }
+        let sum = |part: fn(&SectionEstimate) -> u64| {
+            format_duration(self.sections.iter().map(part).fold(0, u64::saturating_add))
+        };
+        row(
+            f,
+            "Total",
+            [
+                sum(|s| s.narration_ms),
+                sum(|s| s.typing_ms),
+                sum(|s| s.waits_ms),
+                sum(|s| s.actions_ms),
+                format_duration(self.total_ms),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouper::group_into_blocks;
+    use crate::parser::parse_script;
+
+    fn estimate_script(input: &str) -> Estimate {
+        let script = parse_script(input).unwrap();
+        let blocks = group_into_blocks(&script);
+        estimate(&blocks, &script.front_matter, DEFAULT_WPM)
+    }
+
+    #[test]
+    fn test_estimate_sections() {
+        let estimate = estimate_script(
+            "---\ntyping_speed: 100\ntyping_variance: 20\n---\n\
+             [SAY] one two three four five\n[PAUSE]\n\
+             ## Section: Build\n\
+             [FOCUS] Terminal\n[TYPE] ls{enter}\n[RUN]\n[WAIT 2]\n[PAUSE 3]\n\
+             ## Section: Test\n[TYPE speed=10 variance=0] abc\n[EXEC make]",
+        );
+        assert_eq!(estimate.sections.len(), 3);
+
+        let intro = &estimate.sections[0];
+        assert_eq!(intro.name, None);
+        // 5 words at 150 wpm
+        assert_eq!(intro.narration_ms, 2000);
+
+        let build = &estimate.sections[1];
+        assert_eq!(build.name.as_deref(), Some("Build"));
+        // 3 keystrokes at 100 + 20/2 ms
+        assert_eq!(build.typing_ms, 330);
+        assert_eq!(build.waits_ms, 5000);
+        assert_eq!(build.actions_ms, 2 * APPLESCRIPT_COST_MS);
+        assert_eq!(build.total_ms, 330 + 5000 + 500);
+
+        let test = &estimate.sections[2];
+        assert_eq!(test.typing_ms, 30);
+        assert_eq!(test.actions_ms, 0);
+        assert_eq!(estimate.total_ms, 2000 + 5830 + 30);
+        assert_eq!(estimate.duration_ms, None);
+        assert_eq!(estimate.over_duration_ms, None);
+    }
+
+    #[test]
+    fn test_estimate_speed_applies_to_the_section() {
+        let estimate = estimate_script("[SPEED 10]\n[TYPE] abcd");
+        // 10 ms plus half the default variance of 15, for each of 4 keystrokes
+        assert_eq!(estimate.sections[0].typing_ms, 70);
+    }
+
+    #[test]
+    fn test_estimate_ignores_notes() {
+        let estimate = estimate_script("[SAY] one two\n[NOTE] three four five six\n[WAIT 1]");
+        // 2 words at 150 wpm, then the wait
+        assert_eq!(estimate.sections[0].narration_ms, 800);
+        assert_eq!(estimate.total_ms, 1800);
+    }
+
+    #[test]
+    fn test_estimate_huge_values_saturate() {
+        let estimate = estimate_script("[WAIT 18446744073709551615]\n[PAUSE 18446744073709551615]");
+        assert_eq!(estimate.total_ms, u64::MAX);
+        assert!(estimate.to_string().contains("Total"));
+    }
+
+    #[test]
+    fn test_estimate_over_duration() {
+        let estimate = estimate_script("---\nduration: 1s\n---\n[WAIT 3]");
+        assert_eq!(estimate.duration_ms, Some(1000));
+        assert_eq!(estimate.over_duration_ms, Some(2000));
+
+        let estimate = estimate_script("---\nduration: 5s\n---\n[WAIT 3]");
+        assert_eq!(estimate.over_duration_ms, None);
+    }
+
+    #[test]
+    fn test_estimate_table_by_heading() {
+        let estimate = estimate_script(
+            "# Chapter: Basics\n## Section: Build\n### Step: Compile\n[WAIT 60]\n\
+             ### Step: Test\n[WAIT 30]\n## Section: Ship\n[WAIT 10]\n\
+             # Chapter: Extra\n[WAIT 5]",
+        );
+        assert_eq!(estimate.sections.len(), 4);
+        assert_eq!(estimate.sections[1].name.as_deref(), Some("Test"));
+        assert_eq!(estimate.sections[1].headings.len(), 3);
+        assert_eq!(
+            estimate.to_string(),
+            "Section      Narration   Typing    Waits  Actions    Total\n\
+             Basics            0:00     0:00     1:40     0:00     1:40\n  \
+             Build           0:00     0:00     1:30     0:00     1:30\n    \
+             Compile       0:00     0:00     1:00     0:00     1:00\n    \
+             Test          0:00     0:00     0:30     0:00     0:30\n  \
+             Ship            0:00     0:00     0:10     0:00     0:10\n\
+             Extra             0:00     0:00     0:05     0:00     0:05\n\
+             Total             0:00     0:00     1:45     0:00     1:45\n"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0:00");
+        assert_eq!(format_duration(1499), "0:01");
+        assert_eq!(format_duration(61_500), "1:02");
+        assert_eq!(format_duration(3_725_000), "1:02:05");
+    }
+
+    #[test]
+    fn test_estimate_table() {
+        let estimate = estimate_script("[SAY] hi there\n[PAUSE]\n## Section: Build\n[WAIT 90]");
+        assert_eq!(
+            estimate.to_string(),
+            "Section            Narration   Typing    Waits  Actions    Total\n\
+             (before sections)       0:01     0:00     0:00     0:00     0:01\n\
+             Build                   0:00     0:00     1:30     0:00     1:30\n\
+             Total                   0:01     0:00     1:30     0:00     1:31\n"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use crate::parser::cst::{DirectiveNode, NodeKind, SyntaxTree, parse_cst};
+use crate::parser::lexer::FENCE;
+
+/// Format a script in the canonical style:
+///
+/// - directive names in upper case, with single spaces inside and after the brackets
+/// - no indentation or trailing whitespace outside fenced blocks
+/// - values in each front matter table aligned in one column
+/// - at most one blank line in a row, and none at the start or end of the file
+///
+/// Comments, section headers and fenced `[TYPE]` bodies are kept, so the script means
+/// exactly what it did before. Lines the lexer would reject are left as written.
+pub fn format_script(input: &str) -> String {
+    format_tree(&parse_cst(input))
+}
+
+pub fn format_tree(tree: &SyntaxTree<'_>) -> String {
+    let mut out: Vec<String> = Vec::new();
+    for node in &tree.nodes {
+        match &node.kind {
+            NodeKind::FrontMatter { body, closed: true } => {
+                out.push("---".to_string());
+                out.extend(align_front_matter(body));
+                out.push("---".to_string());
+            }
+            // Without its closing marker there is no telling what the block holds
+            NodeKind::FrontMatter { closed: false, .. } | NodeKind::Invalid => {
+                out.extend(node.lines.iter().map(|l| l.to_string()));
+            }
+            NodeKind::Blank => {
+                if out.last().is_some_and(|l| !l.is_empty()) {
+                    out.push(String::new());
+                }
+            }
+            NodeKind::Comment(text) => out.push(text.to_string()),
+            NodeKind::Heading(level, name) => out.push(format!("{} {name}", level.marker())),
+            NodeKind::Directive(directive) => format_directive(directive, &mut out),
+        }
+    }
+    while out.last().is_some_and(String::is_empty) {
+        out.pop();
+    }
+
+    let mut formatted = out.join("\n");
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    formatted
+}
+
+fn format_directive(directive: &DirectiveNode<'_>, out: &mut Vec<String>) {
+    // The directive name is upper case and a language code after it lower case
+    let mut inside = match directive.tag.split_once(':') {
+        Some((name, lang)) => format!("{}:{}", name.to_uppercase(), lang.to_lowercase()),
+        None => directive.tag.to_uppercase(),
+    };
+    for attribute in &directive.attributes {
+        inside.push_str(&format!(" {}={}", attribute.key, attribute.value));
+    }
+    if !directive.inline.is_empty() {
+        inside.push(' ');
+        inside.push_str(directive.inline);
+    }
+    let mut line = format!("[{inside}]");
+    if !directive.after.is_empty() {
+        line.push(' ');
+        line.push_str(directive.after);
+    }
+
+    let Some(fence) = &directive.fence else {
+        out.push(line);
+        return;
+    };
+    out.push(line);
+    if fence.own_line {
+        out.push(fence.opening.to_string());
+    }
+    out.extend(fence.body.iter().map(|l| l.to_string()));
+    if fence.closed {
+        out.push(FENCE.to_string());
+    }
+}
+
+/// Align the values of sibling `key: value` lines, dropping trailing whitespace. Block
+/// scalars (`key: |`) are copied as written.
+fn align_front_matter(body: &[&str]) -> Vec<String> {
+    // `None` for lines that are not entries, including the contents of block scalars
+    let mut entries: Vec<Option<Entry>> = Vec::with_capacity(body.len());
+    let mut verbatim = vec![false; body.len()];
+    let mut block_scalar: Option<usize> = None;
+    for (idx, line) in body.iter().enumerate() {
+        let indent = line.len() - line.trim_start().len();
+        if let Some(parent) = block_scalar {
+            if line.trim().is_empty() || indent > parent {
+                entries.push(None);
+                verbatim[idx] = true;
+                continue;
+            }
+            block_scalar = None;
+        }
+        let entry = Entry::parse(line);
+        if let Some(Entry {
+            value: Some(value),
+            indent,
+            ..
+        }) = &entry
+            && value.starts_with(['|', '>'])
+        {
+            block_scalar = Some(*indent);
+        }
+        entries.push(entry);
+    }
+
+    // Siblings share an indent and are not separated by a shallower line, a blank line or a
+    // comment. Each group is aligned to its longest key that has a value on the same line.
+    let mut widths: Vec<Option<usize>> = vec![None; body.len()];
+    for idx in 0..body.len() {
+        let Some(entry) = &entries[idx] else {
+            continue;
+        };
+        if widths[idx].is_some() {
+            continue;
+        }
+        let mut group = vec![idx];
+        let mut next = idx + 1;
+        while let Some(Some(other)) = entries.get(next) {
+            if other.indent < entry.indent {
+                break;
+            }
+            if other.indent == entry.indent {
+                group.push(next);
+            }
+            next += 1;
+        }
+        let width = group
+            .iter()
+            .filter_map(|&i| entries[i].as_ref().filter(|e| e.value.is_some()))
+            .map(|e| e.key.chars().count())
+            .max()
+            .unwrap_or(0);
+        for i in group {
+            widths[i] = Some(width);
+        }
+    }
+
+    body.iter()
+        .zip(&entries)
+        .zip(widths)
+        .zip(verbatim)
+        .map(|(((line, entry), width), verbatim)| match (entry, width) {
+            (Some(entry), Some(width)) => {
+                let prefix = &line[..entry.indent];
+                match entry.value {
+                    Some(value) => {
+                        let pad = " ".repeat(width - entry.key.chars().count());
+                        format!("{prefix}{}:{pad} {value}", entry.key)
+                    }
+                    None => format!("{prefix}{}:", entry.key),
+                }
+            }
+            // Block scalar contents keep their trailing whitespace
+            _ if verbatim => line.to_string(),
+            _ => line.trim_end().to_string(),
+        })
+        .collect()
+}
+
+/// A `key: value` or `key:` line of the front matter.
+struct Entry<'a> {
+    indent: usize,
+    key: &'a str,
+    value: Option<&'a str>,
+}
+
+impl<'a> Entry<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(['#', '-', '"', '\'', '{', '[']) {
+            return None;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if let Some(key) = trimmed.strip_suffix(':') {
+            return (!key.contains(": ")).then_some(Self {
+                indent,
+                key: key.trim_end(),
+                value: None,
+            });
+        }
+        let (key, value) = trimmed.split_once(": ")?;
+        Some(Self {
+            indent,
+            key: key.trim_end(),
+            value: Some(value.trim()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::types::Directive;
+    use crate::parser::{ParseOptions, parse_script_at};
+    use std::path::Path;
+
+    /// The directives of `input` read as the file at `path`, so includes and snippet files
+    /// resolve.
+    fn directives(path: &str, input: &str) -> Vec<Directive> {
+        parse_script_at(input, Path::new(path), &ParseOptions::default())
+            .unwrap()
+            .lines
+            .into_iter()
+            .map(|l| l.directive)
+            .collect()
+    }
+
+    #[test]
+    fn test_format_directives() {
+        assert_eq!(
+            format_script("  [say]   hello there  \n[type  speed=80   variance=0  ]   ls\n"),
+            "[SAY] hello there\n[TYPE speed=80 variance=0] ls\n"
+        );
+        assert_eq!(format_script("[wait 3]\n[Run]"), "[WAIT 3]\n[RUN]\n");
+        assert_eq!(
+            format_script("[say:DE] Hallo\n[note:pt-BR] devagar"),
+            "[SAY:de] Hallo\n[NOTE:pt-br] devagar\n"
+        );
+    }
+
+    #[test]
+    fn test_format_collapses_blank_lines() {
+        assert_eq!(
+            format_script("\n\n[SAY] a\n\n\n\n[RUN]\n\n\n"),
+            "[SAY] a\n\n[RUN]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments_sections_and_fences() {
+        let input = "# intro  \n## Section:   Build  \n[type]\n```\n  indented  \n\n```\n";
+        assert_eq!(
+            format_script(input),
+            "# intro\n## Section: Build\n[TYPE]\n```\n  indented  \n\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_format_headings() {
+        let input = "#Chapter:Basics\n  ###   Step: Install \n## Chapter: wrong level\n";
+        assert_eq!(
+            format_script(input),
+            "# Chapter: Basics\n### Step: Install\n## Chapter: wrong level\n"
+        );
+    }
+
+    #[test]
+    fn test_format_aligns_front_matter() {
+        let input = "---\ntitle: Demo  \ntyping_speed: 40\nvars:\n  project: demo\n  \
+                     port: 8080\nagent_port: 9876\n---\n[SAY] hi\n";
+        assert_eq!(
+            format_script(input),
+            "---\ntitle:        Demo\ntyping_speed: 40\nvars:\n  project: demo\n  \
+             port:    8080\nagent_port:   9876\n---\n[SAY] hi\n"
+        );
+    }
+
+    #[test]
+    fn test_format_leaves_block_scalars_alone() {
+        let input = "---\nvars:\n  script: |\n    a: b\n      c\n  x: 1\n---\n";
+        assert_eq!(
+            format_script(input),
+            "---\nvars:\n  script: |\n    a: b\n      c\n  x: 1\n---\n"
+        );
+    }
+
+    #[test]
+    fn test_format_is_idempotent_and_preserves_meaning() {
+        for path in [
+            "examples/demo.cm",
+            "examples/macros.cm",
+            "examples/vars.cm",
+            "examples/applescript-actions.cm",
+            "examples/composed.cm",
+            "examples/snippets.cm",
+            "examples/shared/reset-terminal.cm",
+        ] {
+            let input = std::fs::read_to_string(path).unwrap();
+            let formatted = format_script(&input);
+            assert_eq!(format_script(&formatted), formatted, "{path}");
+            assert_eq!(
+                directives(path, &formatted),
+                directives(path, &input),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_empty() {
+        assert_eq!(format_script(""), "");
+        assert_eq!(format_script("\n\n"), "");
+    }
+}
@@ -1,6 +1,11 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
-use crate::parser::types::{Directive, Script};
+use crate::parser::types::{
+    Directive, Heading, HeadingLevel, Localized, ParsedLine, Script, SourceLocation,
+};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BlockType {
@@ -9,89 +14,361 @@ pub enum BlockType {
     NarrationOnly,
 }
 
+/// An inclusive range of line numbers in the top-level script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn contains(&self, line_number: usize) -> bool {
+        (self.start..=self.end).contains(&line_number)
+    }
+
+    fn extend(range: &mut Option<LineRange>, line_number: usize) {
+        *range = Some(match *range {
+            Some(r) => LineRange {
+                start: r.start.min(line_number),
+                end: r.end.max(line_number),
+            },
+            None => LineRange {
+                start: line_number,
+                end: line_number,
+            },
+        });
+    }
+}
+
+impl fmt::Display for LineRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "line {}", self.start)
+        } else {
+            write!(f, "lines {}-{}", self.start, self.end)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActionBlock {
     pub narration: Option<String>,
     pub actions: Vec<Directive>,
+    /// The name of the `## Section:` the block is in.
     pub section: Option<String>,
+    /// The chapter, section and step the block is in, outermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<Heading>,
     pub block_type: BlockType,
+    /// `[NOTE]`s for the presenter's eyes only, kept apart from the narration.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// `[SAY:lang]` and `[NOTE:lang]` text by language code.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, Translation>,
+    /// Lines of the `[SAY]`s that make up `narration`.
+    pub narration_lines: Option<LineRange>,
+    /// Where each of `actions` comes from, index for index. The file is only set for
+    /// actions from an included script.
+    pub action_lines: Vec<SourceLocation>,
+    /// Every line of the top-level script that contributes to the block, including a
+    /// `[PAUSE]`. `None` if the whole block comes from included files.
+    pub lines: Option<LineRange>,
+    /// `[LABEL]`s just before or inside the block, which jump to its start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// The label of a `[SKIP_TO]` the presenter may take instead of running the block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_to: Option<String>,
+}
+
+/// The narration and notes of a block in one language.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Translation {
+    pub narration: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// A place in the presentation the presenter can jump to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JumpTarget {
+    pub kind: JumpKind,
+    pub name: String,
+    /// Index of the block the jump lands on.
+    pub block: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum JumpKind {
+    Label,
+    Heading(HeadingLevel),
+}
+
+/// Lines read since the last block was emitted.
+#[derive(Default)]
+struct Pending {
+    narration: Vec<String>,
+    notes: Vec<String>,
+    translations: BTreeMap<String, Translation>,
+    narration_lines: Option<LineRange>,
+    actions: Vec<Directive>,
+    action_lines: Vec<SourceLocation>,
+    lines: Option<LineRange>,
+    labels: Vec<String>,
+    skip_to: Option<String>,
+}
+
+impl Pending {
+    fn record(&mut self, location: &SourceLocation) {
+        if location.file.is_none() {
+            LineRange::extend(&mut self.lines, location.line_number);
+        }
+    }
+
+    /// Emit a block of `block_type` from everything pending, under `headings`.
+    fn take(&mut self, block_type: BlockType, headings: &[Heading]) -> ActionBlock {
+        ActionBlock {
+            narration: flush_narration(&mut self.narration),
+            notes: std::mem::take(&mut self.notes),
+            translations: std::mem::take(&mut self.translations),
+            actions: std::mem::take(&mut self.actions),
+            section: headings
+                .iter()
+                .find(|h| h.level == HeadingLevel::Section)
+                .map(|h| h.name.clone()),
+            headings: headings.to_vec(),
+            block_type,
+            narration_lines: self.narration_lines.take(),
+            action_lines: std::mem::take(&mut self.action_lines),
+            lines: self.lines.take(),
+            labels: std::mem::take(&mut self.labels),
+            skip_to: self.skip_to.take(),
+        }
+    }
 }
 
 pub fn group_into_blocks(script: &Script) -> Vec<ActionBlock> {
     let mut blocks = Vec::new();
-    let mut current_narration: Vec<String> = Vec::new();
-    let mut current_actions: Vec<Directive> = Vec::new();
-    let mut current_section: Option<String> = None;
+    let mut pending = Pending::default();
+    // The chapter, section and step being read
+    let mut headings: Vec<Heading> = Vec::new();
+    // Set by `[SPEED]`, until the next section
+    let mut section_speed: Option<u64> = None;
 
     for parsed_line in &script.lines {
+        let location = site(script, parsed_line);
         match &parsed_line.directive {
             Directive::Say(text) => {
                 // Flush any pending action block before accumulating narration
-                if !current_actions.is_empty() {
-                    blocks.push(ActionBlock {
-                        narration: flush_narration(&mut current_narration),
-                        actions: std::mem::take(&mut current_actions),
-                        section: current_section.clone(),
-                        block_type: BlockType::Action,
-                    });
+                if !pending.actions.is_empty() {
+                    blocks.push(pending.take(BlockType::Action, &headings));
+                }
+                pending.narration.push(text.clone());
+                pending.record(&location);
+                if location.file.is_none() {
+                    LineRange::extend(&mut pending.narration_lines, location.line_number);
                 }
-                current_narration.push(text.clone());
             }
-            Directive::Section(name) => {
+            Directive::SayIn(Localized { lang, text }) => {
+                // A translation of the narration, so it ends an action block the same way
+                if !pending.actions.is_empty() {
+                    blocks.push(pending.take(BlockType::Action, &headings));
+                }
+                let translation = pending.translations.entry(lang.clone()).or_default();
+                translation.narration = Some(match translation.narration.take() {
+                    Some(narration) => format!("{narration}\n{text}"),
+                    None => text.clone(),
+                });
+                pending.record(&location);
+                if location.file.is_none() {
+                    LineRange::extend(&mut pending.narration_lines, location.line_number);
+                }
+            }
+            Directive::Chapter(_) | Directive::Section(_) | Directive::Step(_) => {
                 // Flush any pending action block
-                if !current_actions.is_empty() {
-                    blocks.push(ActionBlock {
-                        narration: flush_narration(&mut current_narration),
-                        actions: std::mem::take(&mut current_actions),
-                        section: current_section.clone(),
-                        block_type: BlockType::Action,
-                    });
+                if !pending.actions.is_empty() {
+                    blocks.push(pending.take(BlockType::Action, &headings));
                 }
-                current_section = Some(name.clone());
+                let Some(heading) = parsed_line.directive.heading() else {
+                    continue;
+                };
+                if heading.level != HeadingLevel::Step {
+                    section_speed = None;
+                }
+                // A heading closes any open heading at its level or below
+                headings.retain(|h| h.level < heading.level);
+                headings.push(heading);
+            }
+            Directive::Speed(ms) => {
+                section_speed = Some(*ms);
+            }
+            Directive::Note(text) => {
+                // Notes belong to the block being read, without starting a new one
+                pending.notes.push(text.clone());
+                pending.record(&location);
+            }
+            Directive::NoteIn(Localized { lang, text }) => {
+                let translation = pending.translations.entry(lang.clone()).or_default();
+                translation.notes.push(text.clone());
+                pending.record(&location);
+            }
+            Directive::Label(name) => {
+                // A label marks the start of a block
+                if !pending.actions.is_empty() {
+                    blocks.push(pending.take(BlockType::Action, &headings));
+                }
+                pending.labels.push(name.clone());
+                pending.record(&location);
+            }
+            Directive::SkipTo(name) => {
+                // The first branch in a block wins
+                pending.skip_to.get_or_insert_with(|| name.clone());
+                pending.record(&location);
             }
             Directive::Pause(timeout) => {
                 // Flush any pending action block first
-                if !current_actions.is_empty() {
-                    blocks.push(ActionBlock {
-                        narration: flush_narration(&mut current_narration),
-                        actions: std::mem::take(&mut current_actions),
-                        section: current_section.clone(),
-                        block_type: BlockType::Action,
-                    });
+                if !pending.actions.is_empty() {
+                    blocks.push(pending.take(BlockType::Action, &headings));
                 }
                 // Pause is always its own block
-                blocks.push(ActionBlock {
-                    narration: flush_narration(&mut current_narration),
-                    actions: vec![],
-                    section: current_section.clone(),
-                    block_type: BlockType::Pause(*timeout),
-                });
+                pending.record(&location);
+                blocks.push(pending.take(BlockType::Pause(*timeout), &headings));
             }
             directive => {
                 // All other directives accumulate into the current action block
-                current_actions.push(directive.clone());
+                let mut directive = directive.clone();
+                if let Directive::Type(typed) = &mut directive {
+                    typed.speed = typed.speed.or(section_speed);
+                }
+                pending.actions.push(directive);
+                pending.record(&location);
+                pending.action_lines.push(location);
             }
         }
     }
 
     // Flush remaining
-    if !current_actions.is_empty() {
-        blocks.push(ActionBlock {
-            narration: flush_narration(&mut current_narration),
-            actions: std::mem::take(&mut current_actions),
-            section: current_section.clone(),
-            block_type: BlockType::Action,
-        });
-    } else if !current_narration.is_empty() {
-        blocks.push(ActionBlock {
-            narration: flush_narration(&mut current_narration),
-            actions: vec![],
-            section: current_section.clone(),
-            block_type: BlockType::NarrationOnly,
+    if !pending.actions.is_empty() {
+        blocks.push(pending.take(BlockType::Action, &headings));
+    } else if !pending.narration.is_empty()
+        || !pending.notes.is_empty()
+        || !pending.translations.is_empty()
+        || !pending.labels.is_empty()
+        || pending.skip_to.is_some()
+    {
+        blocks.push(pending.take(BlockType::NarrationOnly, &headings));
+    }
+
+    blocks
+}
+
+/// Where a line takes effect: the `[CALL]` for macro output, otherwise the line itself.
+/// The file is dropped for lines of the top-level script.
+fn site(script: &Script, line: &ParsedLine) -> SourceLocation {
+    let mut location = line
+        .expanded_from
+        .clone()
+        .unwrap_or_else(|| line.location());
+    if location
+        .file
+        .as_ref()
+        .is_some_and(|file| !script.includes.contains(file))
+    {
+        location.file = None;
+    }
+    location
+}
+
+/// Index of the block to start from so that the presentation begins at `line_number` of
+/// the top-level script: the first block with a line at or after it.
+pub fn block_index_for_line(blocks: &[ActionBlock], line_number: usize) -> Option<usize> {
+    blocks
+        .iter()
+        .position(|b| b.lines.is_some_and(|r| r.end >= line_number))
+}
+
+impl ActionBlock {
+    /// Show the narration and notes in `lang`, keeping the untagged text wherever the
+    /// block has no translation.
+    pub fn localize(&mut self, lang: &str) {
+        let Some(translation) = self.translations.get(lang) else {
+            return;
+        };
+        if let Some(narration) = &translation.narration {
+            self.narration = Some(narration.clone());
+        }
+        if !translation.notes.is_empty() {
+            self.notes = translation.notes.clone();
+        }
+    }
+
+    /// The directives, `SAY` and `NOTE`, whose untagged text has no version in `lang`.
+    pub fn missing_translations(&self, lang: &str) -> Vec<&'static str> {
+        let translation = self.translations.get(lang);
+        let mut missing = Vec::new();
+        if self.narration.is_some() && translation.is_none_or(|t| t.narration.is_none()) {
+            missing.push("SAY");
+        }
+        if !self.notes.is_empty() && translation.is_none_or(|t| t.notes.is_empty()) {
+            missing.push("NOTE");
+        }
+        missing
+    }
+
+    /// The heading names joined with `›`, as in `Basics › Build › Install`.
+    pub fn breadcrumb(&self) -> String {
+        self.headings
+            .iter()
+            .map(|h| h.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" › ")
+    }
+
+    /// The headings this block starts, given the block before it: all of them after a
+    /// jump or at the start, otherwise those that differ from the previous block's.
+    pub fn headings_entered(&self, previous: Option<&ActionBlock>) -> &[Heading] {
+        let unchanged = previous.map_or(0, |p| {
+            p.headings
+                .iter()
+                .zip(&self.headings)
+                .take_while(|(a, b)| a == b)
+                .count()
         });
+        &self.headings[unchanged..]
     }
+}
 
+/// Index of the block that `[LABEL label]` jumps to.
+pub fn block_index_for_label(blocks: &[ActionBlock], label: &str) -> Option<usize> {
     blocks
+        .iter()
+        .position(|b| b.labels.iter().any(|l| l == label))
+}
+
+/// Every label, and the first block under every chapter, section and step, in script
+/// order.
+pub fn jump_targets(blocks: &[ActionBlock]) -> Vec<JumpTarget> {
+    let mut targets = Vec::new();
+    for (index, block) in blocks.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|i| &blocks[i]);
+        targets.extend(
+            block
+                .headings_entered(previous)
+                .iter()
+                .map(|heading| JumpTarget {
+                    kind: JumpKind::Heading(heading.level),
+                    name: heading.name.clone(),
+                    block: index,
+                }),
+        );
+        targets.extend(block.labels.iter().map(|label| JumpTarget {
+            kind: JumpKind::Label,
+            name: label.clone(),
+            block: index,
+        }));
+    }
+    targets
 }
 
 fn flush_narration(narration: &mut Vec<String>) -> Option<String> {
@@ -107,19 +384,17 @@ fn flush_narration(narration: &mut Vec<String>) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::types::{FrontMatter, ParsedLine, Script, SlideAction};
+    use crate::parser::types::{SlideAction, TypeText};
+    use std::path::PathBuf;
 
     fn make_script(directives: Vec<Directive>) -> Script {
         Script {
-            front_matter: FrontMatter::default(),
             lines: directives
                 .into_iter()
                 .enumerate()
-                .map(|(i, directive)| ParsedLine {
-                    line_number: i + 1,
-                    directive,
-                })
+                .map(|(i, directive)| ParsedLine::new(i + 1, directive))
                 .collect(),
+            ..Default::default()
         }
     }
 
@@ -245,4 +520,253 @@ mod tests {
         assert_eq!(blocks[5].block_type, BlockType::NarrationOnly);
         assert_eq!(blocks[5].narration, Some("That's all".to_string()));
     }
+
+    #[test]
+    fn test_group_records_lines() {
+        let script = make_script(vec![
+            Directive::Say("one".into()),
+            Directive::Say("two".into()),
+            Directive::Focus("Terminal".into()),
+            Directive::Type("ls".into()),
+            Directive::Pause(None),
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(
+            blocks[0].narration_lines,
+            Some(LineRange { start: 1, end: 2 })
+        );
+        let action_lines: Vec<usize> = blocks[0]
+            .action_lines
+            .iter()
+            .map(|l| l.line_number)
+            .collect();
+        assert_eq!(action_lines, vec![3, 4]);
+        assert_eq!(blocks[0].lines, Some(LineRange { start: 1, end: 4 }));
+        assert_eq!(blocks[1].lines, Some(LineRange { start: 5, end: 5 }));
+        assert_eq!(blocks[1].narration_lines, None);
+    }
+
+    #[test]
+    fn test_group_lines_from_includes_and_macros() {
+        let include = PathBuf::from("shared/setup.cm");
+        let mut included = ParsedLine::new(2, Directive::Clear);
+        included.file = Some(include.clone());
+        let mut expanded = ParsedLine::new(1, Directive::Run);
+        expanded.file = Some(PathBuf::from("main.cm"));
+        expanded.expanded_from = Some(SourceLocation {
+            file: Some(PathBuf::from("main.cm")),
+            line_number: 7,
+        });
+        let script = Script {
+            lines: vec![included, expanded],
+            includes: vec![include.clone()],
+            ..Default::default()
+        };
+        let blocks = group_into_blocks(&script);
+        assert_eq!(blocks[0].action_lines[0].file, Some(include));
+        // The macro's output is attributed to its [CALL], in the top-level script
+        assert_eq!(
+            blocks[0].action_lines[1],
+            SourceLocation {
+                file: None,
+                line_number: 7
+            }
+        );
+        assert_eq!(blocks[0].lines, Some(LineRange { start: 7, end: 7 }));
+    }
+
+    #[test]
+    fn test_block_index_for_line() {
+        let script = make_script(vec![
+            Directive::Say("a".into()),
+            Directive::Run,
+            Directive::Say("b".into()),
+            Directive::Run,
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(block_index_for_line(&blocks, 1), Some(0));
+        assert_eq!(block_index_for_line(&blocks, 2), Some(0));
+        assert_eq!(block_index_for_line(&blocks, 3), Some(1));
+        assert_eq!(block_index_for_line(&blocks, 5), None);
+    }
+
+    #[test]
+    fn test_group_heading_path() {
+        let script = make_script(vec![
+            Directive::Chapter("Basics".into()),
+            Directive::Section("Build".into()),
+            Directive::Step("Compile".into()),
+            Directive::Run,
+            Directive::Step("Test".into()),
+            Directive::Run,
+            Directive::Chapter("Advanced".into()),
+            Directive::Step("Deploy".into()),
+            Directive::Run,
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].breadcrumb(), "Basics › Build › Compile");
+        assert_eq!(blocks[0].section.as_deref(), Some("Build"));
+        assert_eq!(blocks[1].breadcrumb(), "Basics › Build › Test");
+        // A new chapter closes the section and step before it
+        assert_eq!(blocks[2].breadcrumb(), "Advanced › Deploy");
+        assert_eq!(blocks[2].section, None);
+        assert_eq!(blocks[2].headings[1].level, HeadingLevel::Step);
+
+        let kinds: Vec<_> = jump_targets(&blocks)
+            .into_iter()
+            .map(|t| (t.kind, t.block))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (JumpKind::Heading(HeadingLevel::Chapter), 0),
+                (JumpKind::Heading(HeadingLevel::Section), 0),
+                (JumpKind::Heading(HeadingLevel::Step), 0),
+                (JumpKind::Heading(HeadingLevel::Step), 1),
+                (JumpKind::Heading(HeadingLevel::Chapter), 2),
+                (JumpKind::Heading(HeadingLevel::Step), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_labels_and_skip_to() {
+        let script = make_script(vec![
+            Directive::Type("ls".into()),
+            Directive::Run,
+            Directive::Label("live".into()),
+            Directive::Say("Build it".into()),
+            Directive::SkipTo("backup".into()),
+            Directive::Exec("make".into()),
+            Directive::Section("Backup".into()),
+            Directive::Label("backup".into()),
+            Directive::Say("Here is one I made earlier".into()),
+            Directive::Pause(None),
+            Directive::Label("end".into()),
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(blocks.len(), 4);
+        // The label starts a new block rather than joining the one before it
+        assert!(blocks[0].labels.is_empty());
+        assert_eq!(blocks[1].labels, ["live"]);
+        assert_eq!(blocks[1].skip_to.as_deref(), Some("backup"));
+        assert_eq!(blocks[1].actions.len(), 1);
+        assert_eq!(blocks[2].labels, ["backup"]);
+        assert_eq!(blocks[2].block_type, BlockType::Pause(None));
+        // A trailing label still has a block to land on
+        assert_eq!(blocks[3].labels, ["end"]);
+        assert_eq!(blocks[3].block_type, BlockType::NarrationOnly);
+
+        assert_eq!(block_index_for_label(&blocks, "backup"), Some(2));
+        assert_eq!(block_index_for_label(&blocks, "nope"), None);
+        let targets: Vec<_> = jump_targets(&blocks)
+            .into_iter()
+            .map(|t| (t.kind, t.name, t.block))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (JumpKind::Label, "live".to_string(), 1),
+                (
+                    JumpKind::Heading(HeadingLevel::Section),
+                    "Backup".to_string(),
+                    2
+                ),
+                (JumpKind::Label, "backup".to_string(), 2),
+                (JumpKind::Label, "end".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_notes() {
+        let script = make_script(vec![
+            Directive::Say("Build it".into()),
+            Directive::Type("make".into()),
+            Directive::Note("it takes a minute".into()),
+            Directive::Run,
+            Directive::Pause(None),
+            Directive::Note("wrap up".into()),
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(blocks.len(), 3);
+        // A note joins the block it sits in without splitting it
+        assert_eq!(blocks[0].notes, ["it takes a minute"]);
+        assert_eq!(blocks[0].actions.len(), 2);
+        assert_eq!(blocks[0].narration.as_deref(), Some("Build it"));
+        assert!(blocks[1].notes.is_empty());
+        assert_eq!(blocks[2].notes, ["wrap up"]);
+        assert_eq!(blocks[2].narration, None);
+        assert_eq!(blocks[2].block_type, BlockType::NarrationOnly);
+    }
+
+    #[test]
+    fn test_group_translations() {
+        let de = |text: &str| Localized {
+            lang: "de".into(),
+            text: text.into(),
+        };
+        let script = make_script(vec![
+            Directive::Say("Hello".into()),
+            Directive::SayIn(de("Hallo")),
+            Directive::SayIn(de("zusammen")),
+            Directive::Note("wave".into()),
+            Directive::Run,
+            Directive::Say("Bye".into()),
+            Directive::NoteIn(de("winken")),
+            Directive::Run,
+        ]);
+        let mut blocks = group_into_blocks(&script);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].narration.as_deref(), Some("Hello"));
+        assert_eq!(
+            blocks[0].translations["de"].narration.as_deref(),
+            Some("Hallo\nzusammen")
+        );
+        assert_eq!(blocks[0].missing_translations("de"), ["NOTE"]);
+        assert_eq!(blocks[1].missing_translations("de"), ["SAY"]);
+        assert_eq!(blocks[1].missing_translations("fr"), ["SAY"]);
+
+        for block in &mut blocks {
+            block.localize("de");
+        }
+        assert_eq!(blocks[0].narration.as_deref(), Some("Hallo\nzusammen"));
+        // Untranslated parts fall back to the untagged text
+        assert_eq!(blocks[0].notes, ["wave"]);
+        assert_eq!(blocks[1].narration.as_deref(), Some("Bye"));
+        assert_eq!(blocks[1].notes, ["winken"]);
+    }
+
+    #[test]
+    fn test_line_range_display() {
+        assert_eq!(LineRange { start: 4, end: 4 }.to_string(), "line 4");
+        assert_eq!(LineRange { start: 4, end: 7 }.to_string(), "lines 4-7");
+    }
+
+    #[test]
+    fn test_group_speed_is_section_scoped() {
+        let typed = |text: &str, speed| {
+            Directive::Type(TypeText {
+                speed,
+                ..TypeText::from(text)
+            })
+        };
+        let script = make_script(vec![
+            Directive::Type("a".into()),
+            Directive::Speed(80),
+            Directive::Type("b".into()),
+            typed("c", Some(10)),
+            Directive::Section("Next".into()),
+            Directive::Type("d".into()),
+        ]);
+        let blocks = group_into_blocks(&script);
+        assert_eq!(blocks.len(), 2);
+        // [SPEED] is not sent to the agent
+        assert_eq!(
+            blocks[0].actions,
+            vec![typed("a", None), typed("b", Some(80)), typed("c", Some(10))]
+        );
+        assert_eq!(blocks[1].actions, vec![typed("d", None)]);
+    }
 }
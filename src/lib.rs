@@ -1,6 +1,15 @@
 pub mod agent;
 pub mod client;
+pub mod diagnostics;
+pub mod estimate;
+pub mod formatter;
 pub mod grouper;
+pub mod lint;
+pub mod lsp;
 pub mod parser;
 pub mod protocol;
+pub mod render;
+pub mod report;
+#[cfg(test)]
+mod testing;
 pub mod tui;
@@ -0,0 +1,519 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::grouper::ActionBlock;
+use crate::parser::keys::TypeSegment;
+use crate::parser::lexer::Span;
+use crate::parser::types::{
+    Directive, Heading, HeadingLevel, Level, Script, SlideAction, SourceLocation,
+};
+
+/// A lint rule: its ID, as used in suppressions and the front matter `lint:` table, and
+/// the level it has unless configured otherwise.
+pub struct Rule {
+    pub id: &'static str,
+    pub default_level: Level,
+    pub description: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "type-without-focus",
+        default_level: Level::Warning,
+        description: "[TYPE] with no [FOCUS] earlier in the section",
+    },
+    Rule {
+        id: "run-after-focus",
+        default_level: Level::Warning,
+        description: "[RUN] straight after [FOCUS], with nothing typed",
+    },
+    Rule {
+        id: "slide-order",
+        default_level: Level::Warning,
+        description: "[SLIDE n] going backwards or past slide_count",
+    },
+    Rule {
+        id: "dangerous-exec",
+        default_level: Level::Error,
+        description: "[EXEC] using rm -rf or sudo",
+    },
+    Rule {
+        id: "trailing-whitespace",
+        default_level: Level::Warning,
+        description: "typed text with trailing whitespace",
+    },
+];
+
+/// Comment that suppresses rules on the line below it.
+const ALLOW_PREFIX: &str = "# cm-lint: allow(";
+
+/// The severity a rule at `level` is reported with, or `None` when it is off.
+fn severity(level: Level) -> Option<Severity> {
+    match level {
+        Level::Off => None,
+        Level::Warning => Some(Severity::Warning),
+        Level::Error => Some(Severity::Error),
+    }
+}
+
+pub fn rule_ids() -> Vec<&'static str> {
+    RULES.iter().map(|r| r.id).collect()
+}
+
+/// The source of every file in a script's include tree, used to quote lines in findings
+/// and to find `# cm-lint: allow(...)` comments. The top-level script has no file name,
+/// matching [`ActionBlock::action_lines`].
+#[derive(Debug, Default)]
+pub struct Sources {
+    files: BTreeMap<Option<PathBuf>, Vec<String>>,
+}
+
+impl Sources {
+    pub fn add(&mut self, file: Option<PathBuf>, text: &str) {
+        self.files
+            .insert(file, text.lines().map(str::to_string).collect());
+    }
+
+    fn line(&self, location: &SourceLocation) -> &str {
+        self.files
+            .get(&location.file)
+            .and_then(|lines| lines.get(location.line_number.wrapping_sub(1)))
+            .map_or("", String::as_str)
+    }
+
+    /// Whether a comment directly above `location` (skipping other comments) allows
+    /// `rule`.
+    fn allows(&self, location: &SourceLocation, rule: &str) -> bool {
+        let Some(lines) = self.files.get(&location.file) else {
+            return false;
+        };
+        let above = lines[..location.line_number.saturating_sub(1).min(lines.len())].iter();
+        above
+            .rev()
+            .map(|line| line.trim())
+            .take_while(|line| line.starts_with('#'))
+            .filter_map(allowed_rules)
+            .any(|rules| rules.contains(&rule))
+    }
+
+    /// Suppression comments naming a rule that does not exist.
+    fn unknown_suppressions(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (file, lines) in &self.files {
+            for (idx, line) in lines.iter().enumerate() {
+                let Some(rules) = allowed_rules(line.trim()) else {
+                    continue;
+                };
+                for rule in rules {
+                    if RULES.iter().any(|r| r.id == rule) {
+                        continue;
+                    }
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        code: None,
+                        message: format!("Unknown lint rule '{rule}'"),
+                        file: file.clone(),
+                        line_number: idx + 1,
+                        line_content: line.clone(),
+                        span: Some(Span::of(line, rule)),
+                        help: Some(format!("lint rules are {}", rule_ids().join(", "))),
+                    });
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+/// The rules listed in a `# cm-lint: allow(a, b)` comment.
+fn allowed_rules(comment: &str) -> Option<Vec<&str>> {
+    let list = comment.strip_prefix(ALLOW_PREFIX)?.strip_suffix(')')?;
+    Some(
+        list.split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .collect(),
+    )
+}
+
+/// Run every enabled rule over `blocks`, the grouped form of `script`. Findings come back
+/// in script order; rules are configured by the front matter's `lint:` table.
+pub fn lint(script: &Script, blocks: &[ActionBlock], sources: &Sources) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        script,
+        sources,
+        findings: sources.unknown_suppressions(),
+    };
+
+    // The chapter and section being read; steps share their section's focus
+    let mut section: Vec<&Heading> = Vec::new();
+    let mut focused = false;
+    let mut previous: Option<&Directive> = None;
+    let mut slide: u32 = 1;
+
+    for block in blocks {
+        let headings: Vec<&Heading> = block
+            .headings
+            .iter()
+            .filter(|h| h.level <= HeadingLevel::Section)
+            .collect();
+        if headings != section {
+            section = headings;
+            focused = false;
+        }
+        for (action, location) in block.actions.iter().zip(&block.action_lines) {
+            match action {
+                Directive::Focus(_) => focused = true,
+                Directive::Type(typed) => {
+                    if !focused {
+                        linter.report(
+                            "type-without-focus",
+                            location,
+                            "[TYPE] before any [FOCUS] in this section".to_string(),
+                            "add a [FOCUS] so the text goes to the right application",
+                        );
+                    }
+                    for line in trailing_whitespace(&typed.segments) {
+                        linter.report(
+                            "trailing-whitespace",
+                            location,
+                            format!("Line {line} of the typed text ends with whitespace"),
+                            "the audience will see the cursor move past the end of the line",
+                        );
+                    }
+                }
+                Directive::Run if matches!(previous, Some(Directive::Focus(_))) => {
+                    linter.report(
+                        "run-after-focus",
+                        location,
+                        "[RUN] straight after [FOCUS] with nothing typed".to_string(),
+                        "this presses Return in whatever the application had open",
+                    );
+                }
+                Directive::Slide(action) => {
+                    let target = match action {
+                        SlideAction::Next => slide + 1,
+                        SlideAction::Prev => slide.saturating_sub(1).max(1),
+                        SlideAction::GoTo(n) => *n,
+                    };
+                    if let SlideAction::GoTo(n) = action
+                        && *n < slide
+                    {
+                        linter.report(
+                            "slide-order",
+                            location,
+                            format!("[SLIDE {n}] goes back from slide {slide}"),
+                            "use [SLIDE prev] if going back is intended",
+                        );
+                    }
+                    if let Some(count) = script.front_matter.slide_count
+                        && target > count
+                    {
+                        linter.report(
+                            "slide-order",
+                            location,
+                            format!("Slide {target} is past the last slide ({count})"),
+                            "check slide_count in the front matter",
+                        );
+                    }
+                    slide = target;
+                }
+                Directive::Exec(exec) => {
+                    if let Some(danger) = dangerous_command(&exec.command) {
+                        linter.report_at(
+                            "dangerous-exec",
+                            location,
+                            format!("[EXEC] runs '{danger}'"),
+                            "a typo here could damage the demo machine; \
+                             prefer a script with a fixed path",
+                            danger,
+                        );
+                    }
+                }
+                _ => {}
+            }
+            previous = Some(action);
+        }
+    }
+
+    let mut findings = linter.findings;
+    findings.sort_by(|a, b| (&a.file, a.line_number).cmp(&(&b.file, b.line_number)));
+    findings
+}
+
+struct Linter<'a> {
+    script: &'a Script,
+    sources: &'a Sources,
+    findings: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(
+        &mut self,
+        rule: &'static str,
+        location: &SourceLocation,
+        message: String,
+        help: &str,
+    ) {
+        let line = self.sources.line(location).trim();
+        self.report_at(rule, location, message, help, line);
+    }
+
+    fn report_at(
+        &mut self,
+        rule: &'static str,
+        location: &SourceLocation,
+        message: String,
+        help: &str,
+        part: &str,
+    ) {
+        let level = self
+            .script
+            .front_matter
+            .lint
+            .get(rule)
+            .copied()
+            .or_else(|| RULES.iter().find(|r| r.id == rule).map(|r| r.default_level))
+            .unwrap_or(Level::Warning);
+        let Some(severity) = severity(level) else {
+            return;
+        };
+        if self.sources.allows(location, rule) {
+            return;
+        }
+        let line = self.sources.line(location);
+        self.findings.push(Diagnostic {
+            severity,
+            code: Some(rule),
+            message,
+            file: location.file.clone(),
+            line_number: location.line_number,
+            line_content: line.to_string(),
+            span: (!line.is_empty()).then(|| Span::of(line, part)),
+            help: Some(format!("{help}; silence with '{ALLOW_PREFIX}{rule})'")),
+        });
+    }
+}
+
+/// The lines of typed text, counting from 1, that end in whitespace: text followed by a
+/// return or by the end of the `[TYPE]`. Other keys, such as `{tab}` to complete a word,
+/// often follow a space on purpose.
+fn trailing_whitespace(segments: &[TypeSegment]) -> Vec<usize> {
+    let is_return = |combo: &str| combo == "return" || combo == "enter";
+    let mut lines = Vec::new();
+    let mut line = 1;
+    for (n, segment) in segments.iter().enumerate() {
+        match segment {
+            TypeSegment::Text(text) if text.ends_with([' ', '\t']) => {
+                let ends_line = match segments.get(n + 1) {
+                    Some(TypeSegment::Key { combo, .. }) => is_return(combo),
+                    Some(TypeSegment::Text(_)) => false,
+                    None => true,
+                };
+                if ends_line {
+                    lines.push(line);
+                }
+            }
+            TypeSegment::Key { combo, repeat } if is_return(combo) => line += *repeat as usize,
+            _ => {}
+        }
+    }
+    lines
+}
+
+/// The dangerous part of a shell command: `sudo`, or `rm` with both `-r` and `-f`.
+fn dangerous_command(command: &str) -> Option<&str> {
+    let words = words(command);
+    if let Some((_, sudo)) = words.iter().find(|(_, word)| *word == "sudo") {
+        return Some(sudo);
+    }
+    for (i, &(start, word)) in words.iter().enumerate() {
+        if word != "rm" {
+            continue;
+        }
+        let (mut recursive, mut force) = (false, false);
+        let options = words[i + 1..]
+            .iter()
+            .map(|&(_, word)| word)
+            .take_while(|word| word.starts_with('-') && *word != "--");
+        for option in options {
+            match option.strip_prefix("--") {
+                Some(long) => {
+                    recursive |= long == "recursive";
+                    force |= long == "force";
+                }
+                // A cluster of single-letter options, as in `-rf`
+                None => {
+                    recursive |= option.contains(['r', 'R']);
+                    force |= option.contains('f');
+                }
+            }
+        }
+        if recursive && force {
+            return Some(&command[start..]);
+        }
+    }
+    None
+}
+
+/// The whitespace-separated words of `command`, with their byte offsets.
+fn words(command: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (pos, ch) in command.char_indices().chain([(command.len(), ' ')]) {
+        match (ch.is_whitespace(), start) {
+            (true, Some(from)) => {
+                words.push((from, &command[from..pos]));
+                start = None;
+            }
+            (false, None) => start = Some(pos),
+            _ => {}
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouper::group_into_blocks;
+    use crate::parser::parse_script;
+
+    fn lint_source(input: &str) -> Vec<Diagnostic> {
+        let script = parse_script(input).unwrap();
+        let blocks = group_into_blocks(&script);
+        let mut sources = Sources::default();
+        sources.add(None, input);
+        lint(&script, &blocks, &sources)
+    }
+
+    fn codes(findings: &[Diagnostic]) -> Vec<(&str, usize)> {
+        findings
+            .iter()
+            .map(|d| (d.code.unwrap_or("-"), d.line_number))
+            .collect()
+    }
+
+    #[test]
+    fn test_type_without_focus() {
+        let findings = lint_source(
+            "## Section: A\n[TYPE] ls\n[FOCUS] Terminal\n[TYPE] pwd\n\
+             ## Section: B\n[TYPE] whoami",
+        );
+        assert_eq!(
+            codes(&findings),
+            vec![("type-without-focus", 2), ("type-without-focus", 6)]
+        );
+        assert_eq!(findings[0].severity, Severity::Warning);
+
+        // A step keeps its section's focus; a new chapter does not
+        let findings = lint_source(
+            "# Chapter: A\n[FOCUS] Terminal\n### Step: a\n[TYPE] ls\n\
+             # Chapter: B\n[TYPE] pwd",
+        );
+        assert_eq!(codes(&findings), vec![("type-without-focus", 6)]);
+    }
+
+    #[test]
+    fn test_run_after_focus() {
+        let findings = lint_source("[FOCUS] Terminal\n[RUN]\n[TYPE] ls\n[RUN]");
+        assert_eq!(codes(&findings), vec![("run-after-focus", 2)]);
+    }
+
+    #[test]
+    fn test_slide_order() {
+        let findings = lint_source(
+            "---\nslide_count: 3\n---\n[SLIDE 2]\n[SLIDE next]\n[SLIDE 1]\n[SLIDE prev]\n[SLIDE 4]",
+        );
+        assert_eq!(
+            codes(&findings),
+            vec![("slide-order", 6), ("slide-order", 8)]
+        );
+        assert!(findings[1].message.contains("past the last slide (3)"));
+    }
+
+    #[test]
+    fn test_dangerous_exec() {
+        let findings = lint_source("[EXEC] rm -rf /tmp/demo\n[EXEC] sudo ls\n[EXEC] rm -r build");
+        assert_eq!(
+            codes(&findings),
+            vec![("dangerous-exec", 1), ("dangerous-exec", 2)]
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[0].span, Some(Span { start: 7, end: 23 }));
+        assert_eq!(dangerous_command("rm -r -f x"), Some("rm -r -f x"));
+        assert_eq!(dangerous_command("echo sudoku"), None);
+        assert_eq!(
+            dangerous_command("rm --recursive --force x"),
+            Some("rm --recursive --force x")
+        );
+        assert_eq!(
+            dangerous_command("rm -R --force x"),
+            Some("rm -R --force x")
+        );
+        // Letters inside long options are not short flags
+        assert_eq!(dangerous_command("rm --force x"), None);
+        assert_eq!(dangerous_command("rm --preserve-root -r x"), None);
+        assert_eq!(dangerous_command("rm -- -rf"), None);
+        assert_eq!(
+            dangerous_command("flash firmware && rm -rf build"),
+            Some("rm -rf build")
+        );
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        let findings = lint_source("[FOCUS] Editor\n[TYPE]\n```\nfn main() {  \n}\n```");
+        assert_eq!(codes(&findings), vec![("trailing-whitespace", 2)]);
+        assert!(findings[0].message.contains("Line 1"));
+    }
+
+    #[test]
+    fn test_trailing_whitespace_before_return() {
+        for input in [
+            "[FOCUS] Terminal\n[TYPE] ls   \\n",
+            "[FOCUS] Terminal\n[TYPE] ls  {enter}",
+        ] {
+            let findings = lint_source(input);
+            assert_eq!(
+                codes(&findings),
+                vec![("trailing-whitespace", 2)],
+                "{input:?}"
+            );
+        }
+        let findings = lint_source("[FOCUS] Terminal\n[TYPE] ls{return x2}cd  {enter}git co {tab}");
+        assert_eq!(codes(&findings), vec![("trailing-whitespace", 2)]);
+        assert!(
+            findings[0].message.contains("Line 3"),
+            "{}",
+            findings[0].message
+        );
+    }
+
+    #[test]
+    fn test_inline_suppression() {
+        let findings = lint_source(
+            "# cm-lint: allow(dangerous-exec, type-without-focus)\n# why: demo cleanup\n\
+             [EXEC] sudo rm -rf /tmp/demo\n[EXEC] sudo ls",
+        );
+        assert_eq!(codes(&findings), vec![("dangerous-exec", 4)]);
+    }
+
+    #[test]
+    fn test_unknown_suppression_warns() {
+        let findings = lint_source("# cm-lint: allow(no-such-rule)\n[SAY] hi");
+        assert_eq!(codes(&findings), vec![("-", 1)]);
+        assert!(findings[0].message.contains("no-such-rule"));
+    }
+
+    #[test]
+    fn test_front_matter_levels() {
+        let findings = lint_source(
+            "---\nlint:\n  dangerous-exec: off\n  type-without-focus: error\n---\n\
+             [EXEC] sudo ls\n[TYPE] ls",
+        );
+        assert_eq!(codes(&findings), vec![("type-without-focus", 7)]);
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+}
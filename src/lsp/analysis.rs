@@ -0,0 +1,712 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::agent::applescript;
+use crate::agent::typewriter::typewriter_to_applescript;
+use crate::diagnostics::Diagnostic;
+use crate::grouper::group_into_blocks;
+use crate::lint::{self, RULES, Sources};
+use crate::parser::cst::{NodeKind, parse_cst};
+use crate::parser::front_matter::{AGENT_KEYS, EXECUTOR_KEYS, TOP_LEVEL_KEYS, parse_front_matter};
+use crate::parser::lexer::{self, DIRECTIVE_NAMES, ParseError, Preprocessor};
+use crate::parser::types::{Directive, FrontMatter, HeadingLevel, Localized, Script, SlideAction};
+use crate::parser::{ParseOptions, parse_script_at_all};
+
+/// Keystrokes of a `[TYPE]` shown on hover before the rest are summarised.
+const TYPE_PREVIEW: usize = 3;
+
+/// An open script, parsed once and queried by the editor requests. Lines are 0-based and
+/// columns are byte offsets into the line; the server converts them to LSP positions.
+pub struct Analysis<'a> {
+    path: &'a Path,
+    text: &'a str,
+    lines: Vec<&'a str>,
+    script: Result<Script, Vec<ParseError>>,
+}
+
+/// A completion candidate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Directive,
+    FrontMatterKey,
+    Value,
+}
+
+/// A heading, spanning the lines up to the next heading at the same level or above.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub level: HeadingLevel,
+    pub line: usize,
+    pub end_line: usize,
+    pub children: Vec<Symbol>,
+}
+
+/// A line of a script on disk, the target of go-to-definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl<'a> Analysis<'a> {
+    /// Parse `text`, the current contents of the script at `path`. Includes are read from
+    /// disk relative to `path`.
+    pub fn new(path: &'a Path, text: &'a str) -> Self {
+        Self {
+            path,
+            text,
+            lines: text.lines().collect(),
+            script: parse_script_at_all(text, path, &ParseOptions::default()),
+        }
+    }
+
+    /// Parse errors or, once the script parses, its warnings and lint findings. Diagnostics
+    /// for this script have no file; those raised in an included script name it.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let diagnostics = match &self.script {
+            Err(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Ok(script) => {
+                let mut sources = Sources::default();
+                sources.add(None, self.text);
+                for include in &script.includes {
+                    if let Ok(text) = std::fs::read_to_string(include) {
+                        sources.add(Some(include.clone()), &text);
+                    }
+                }
+                let blocks = group_into_blocks(script);
+                let mut diagnostics: Vec<Diagnostic> =
+                    script.warnings.iter().map(Diagnostic::warning).collect();
+                diagnostics.extend(lint::lint(script, &blocks, &sources));
+                diagnostics
+            }
+        };
+        diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                if diagnostic.file.as_deref() == Some(self.path) {
+                    diagnostic.file = None;
+                }
+                diagnostic
+            })
+            .collect()
+    }
+
+    /// Markdown describing what the directive on `line` does when presented: the
+    /// AppleScript or shell command it runs, or what a `[CALL]` expands to.
+    pub fn hover(&self, line: usize) -> Option<String> {
+        let text = *self.lines.get(line)?;
+        let line_number = line + 1;
+        if let Ok(Some(preprocessor)) = lexer::parse_preprocessor_line(text, line_number) {
+            return match preprocessor {
+                Preprocessor::Include(target) => Some(format!(
+                    "Includes `{}`",
+                    resolve(self.path, &target).display()
+                )),
+                Preprocessor::Call { name, .. } => self.expansion(line_number, &name),
+                Preprocessor::Define(_) | Preprocessor::End => None,
+            };
+        }
+
+        // Prefer the parsed script, which has variables and app aliases resolved
+        let parsed = self.script.as_ref().ok().and_then(|script| {
+            script.lines.iter().find(|l| {
+                l.expanded_from.is_none()
+                    && l.line_number == line_number
+                    && l.file.as_deref() == Some(self.path)
+            })
+        });
+        let directive = match parsed {
+            Some(parsed) => parsed.directive.clone(),
+            None => lexer::parse_line(text, line_number).ok()??.directive,
+        };
+        Some(describe(&directive, &self.front_matter()))
+    }
+
+    /// Candidates for the cursor at byte `column` of `line`: front matter keys inside the
+    /// front matter, directive names after `[`, and the arguments of `[FOCUS]`, `[SLIDE]`
+    /// and `[CALL]`.
+    pub fn completions(&self, line: usize, column: usize) -> Vec<Completion> {
+        let Some(text) = self.lines.get(line) else {
+            return Vec::new();
+        };
+        let prefix = text.get(..column).unwrap_or(text);
+        if self.in_front_matter(line) {
+            return self.front_matter_completions(line, prefix);
+        }
+
+        let Some(inside) = prefix.trim_start().strip_prefix('[') else {
+            return Vec::new();
+        };
+        if !inside.contains([']', ' ']) {
+            return DIRECTIVE_NAMES
+                .iter()
+                .map(|name| Completion {
+                    label: name.to_string(),
+                    kind: CompletionKind::Directive,
+                    detail: None,
+                })
+                .collect();
+        }
+        // Arguments are only completed once the directive name is finished
+        if !inside.contains(' ') {
+            return Vec::new();
+        }
+        let tag = inside.split([' ', ']']).next().unwrap_or_default();
+        match tag.to_uppercase().as_str() {
+            "FOCUS" => self.app_names(line),
+            "SLIDE" => self.slide_values(),
+            "CALL" => {
+                let names: BTreeSet<String> = self
+                    .macro_definitions()
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect();
+                names.into_iter().map(value).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The chapter, section and step headings of this script, each holding the headings
+    /// below it.
+    pub fn outline(&self) -> Vec<Symbol> {
+        let tree = parse_cst(self.text);
+        let mut headings = Vec::new();
+        let mut line: usize = 0;
+        for node in &tree.nodes {
+            if let NodeKind::Heading(level, name) = node.kind {
+                headings.push((level, name, line));
+            }
+            line += node.lines.len();
+        }
+        nest(&headings, line.saturating_sub(1))
+    }
+
+    /// The file an `[INCLUDE]` on `line` names, the `[DEFINE]` of the macro a `[CALL]`
+    /// on `line` uses, or the `[LABEL]` a `[SKIP_TO]` branches to.
+    pub fn definition(&self, line: usize) -> Option<Location> {
+        let text = self.lines.get(line)?;
+        let Some(preprocessor) = lexer::parse_preprocessor_line(text, line + 1).ok()? else {
+            return match lexer::parse_line(text, line + 1).ok()??.directive {
+                Directive::SkipTo(label) => self.label_definition(&label),
+                _ => None,
+            };
+        };
+        match preprocessor {
+            Preprocessor::Include(target) => {
+                let path = resolve(self.path, &target);
+                path.is_file().then_some(Location { path, line: 0 })
+            }
+            Preprocessor::Call { name, .. } => self
+                .macro_definitions()
+                .into_iter()
+                .find(|(defined, _)| *defined == name)
+                .map(|(_, location)| location),
+            Preprocessor::Define(_) | Preprocessor::End => None,
+        }
+    }
+
+    fn label_definition(&self, label: &str) -> Option<Location> {
+        let script = self.script.as_ref().ok()?;
+        script
+            .lines
+            .iter()
+            .find(|l| l.directive == Directive::Label(label.to_string()))
+            .map(|l| Location {
+                path: l.file.clone().unwrap_or_else(|| self.path.to_path_buf()),
+                line: l.line_number - 1,
+            })
+    }
+
+    fn front_matter(&self) -> FrontMatter {
+        match &self.script {
+            Ok(script) => script.front_matter.clone(),
+            Err(_) => parse_front_matter(&self.lines).front_matter,
+        }
+    }
+
+    /// What the `[CALL]` on `line_number` expands to.
+    fn expansion(&self, line_number: usize, name: &str) -> Option<String> {
+        let script = self.script.as_ref().ok()?;
+        let expanded: Vec<String> = script
+            .lines
+            .iter()
+            .filter(|l| {
+                l.expanded_from.as_ref().is_some_and(|from| {
+                    from.line_number == line_number && from.file.as_deref() == Some(self.path)
+                })
+            })
+            .map(|l| l.directive.to_string())
+            .collect();
+        Some(format!(
+            "Expands macro `{name}` to:\n\n```\n{}\n```",
+            expanded.join("\n")
+        ))
+    }
+
+    fn in_front_matter(&self, line: usize) -> bool {
+        if line == 0 || self.lines.first().is_none_or(|l| l.trim() != "---") {
+            return false;
+        }
+        self.lines[1..]
+            .iter()
+            .position(|l| l.trim() == "---")
+            .is_none_or(|closing| line <= closing)
+    }
+
+    /// Keys that may appear at the cursor's indentation, given the tables it is nested in.
+    fn front_matter_completions(&self, line: usize, prefix: &str) -> Vec<Completion> {
+        if prefix.contains(':') {
+            return Vec::new();
+        }
+        let indent = indent_of(prefix);
+        // The keys of the enclosing tables, innermost first
+        let mut parents = Vec::new();
+        let mut depth = indent;
+        for above in self.lines[1..line].iter().rev() {
+            if above.trim().is_empty() || indent_of(above) >= depth {
+                continue;
+            }
+            depth = indent_of(above);
+            parents.push(above.trim().trim_end_matches(':'));
+            if depth == 0 {
+                break;
+            }
+        }
+
+        let keys = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| Completion {
+                    label: key.to_string(),
+                    kind: CompletionKind::FrontMatterKey,
+                    detail: None,
+                })
+                .collect()
+        };
+        match parents.as_slice() {
+            [] if indent == 0 => keys(TOP_LEVEL_KEYS),
+            ["executor"] => keys(EXECUTOR_KEYS),
+            [_, "agents"] => keys(AGENT_KEYS),
+            ["lint"] => RULES
+                .iter()
+                .map(|rule| Completion {
+                    label: rule.id.to_string(),
+                    kind: CompletionKind::FrontMatterKey,
+                    detail: Some(rule.description.to_string()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Apps focused elsewhere in the script, and the aliases under `apps:`.
+    fn app_names(&self, current: usize) -> Vec<Completion> {
+        let mut apps = BTreeSet::new();
+        for (idx, line) in self.lines.iter().enumerate() {
+            if idx != current
+                && line.trim_start().starts_with('[')
+                && let Ok(bracket) = lexer::split_bracket(line, idx + 1)
+                && bracket.tag.eq_ignore_ascii_case("FOCUS")
+                && !bracket.arg().is_empty()
+            {
+                apps.insert(bracket.arg().to_string());
+            }
+        }
+        let front_matter = parse_front_matter(&self.lines).front_matter;
+        let mut completions: Vec<Completion> = front_matter
+            .apps
+            .iter()
+            .map(|(alias, app)| Completion {
+                detail: Some(app.clone()),
+                ..value(alias.clone())
+            })
+            .collect();
+        completions.extend(apps.into_iter().map(value));
+        completions
+    }
+
+    fn slide_values(&self) -> Vec<Completion> {
+        let slide_count = parse_front_matter(&self.lines).front_matter.slide_count;
+        let mut values = vec![value("next".to_string()), value("prev".to_string())];
+        values.extend((1..=slide_count.unwrap_or(0)).map(|n| value(n.to_string())));
+        values
+    }
+
+    /// Every macro defined in this script or the scripts it includes, in source order.
+    fn macro_definitions(&self) -> Vec<(String, Location)> {
+        let mut definitions = Vec::new();
+        let mut seen: Vec<PathBuf> = self.path.canonicalize().into_iter().collect();
+        collect_definitions(self.path, &self.lines, &mut seen, &mut definitions);
+        definitions
+    }
+}
+
+/// Nest `headings` (level, name, line) under the heading above them, ending the last one
+/// at `end_line`.
+fn nest(headings: &[(HeadingLevel, &str, usize)], end_line: usize) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < headings.len() {
+        let (level, name, line) = headings[i];
+        let next = headings[i + 1..]
+            .iter()
+            .position(|(other, _, _)| *other <= level)
+            .map_or(headings.len(), |p| i + 1 + p);
+        let end = headings
+            .get(next)
+            .map_or(end_line, |(_, _, l)| l.saturating_sub(1))
+            .max(line);
+        symbols.push(Symbol {
+            name: name.to_string(),
+            level,
+            line,
+            end_line: end,
+            children: nest(&headings[i + 1..next], end),
+        });
+        i = next;
+    }
+    symbols
+}
+
+fn collect_definitions(
+    path: &Path,
+    lines: &[&str],
+    seen: &mut Vec<PathBuf>,
+    definitions: &mut Vec<(String, Location)>,
+) {
+    for (idx, line) in lines.iter().enumerate() {
+        match lexer::parse_preprocessor_line(line, idx + 1) {
+            Ok(Some(Preprocessor::Define(name))) => definitions.push((
+                name,
+                Location {
+                    path: path.to_path_buf(),
+                    line: idx,
+                },
+            )),
+            Ok(Some(Preprocessor::Include(target))) => {
+                let included = resolve(path, &target);
+                let Ok(canonical) = included.canonicalize() else {
+                    continue;
+                };
+                if seen.contains(&canonical) {
+                    continue;
+                }
+                seen.push(canonical);
+                if let Ok(text) = std::fs::read_to_string(&included) {
+                    let lines: Vec<&str> = text.lines().collect();
+                    collect_definitions(&included, &lines, seen, definitions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The path an `[INCLUDE]` in `from` refers to, as the parser resolves it.
+fn resolve(from: &Path, target: &str) -> PathBuf {
+    from.parent().unwrap_or(Path::new("")).join(target)
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn value(label: String) -> Completion {
+    Completion {
+        label,
+        kind: CompletionKind::Value,
+        detail: None,
+    }
+}
+
+/// Hover text for a directive.
+fn describe(directive: &Directive, front_matter: &FrontMatter) -> String {
+    let script = |script: String| format!("```applescript\n{script}\n```");
+    match directive {
+        Directive::Say(text) => {
+            format!("Narration for the presenter; nothing is sent to the agent.\n\n> {text}")
+        }
+        Directive::SayIn(Localized { lang, text }) => format!(
+            "Narration in '{lang}', shown by `present --lang {lang}` in place of the [SAY].\n\n> {text}"
+        ),
+        Directive::NoteIn(Localized { lang, text }) => format!(
+            "A private note in '{lang}', shown by `present --lang {lang}` in place of the [NOTE].\n\n> {text}"
+        ),
+        Directive::Note(text) => format!(
+            "A private note shown to the presenter, not spoken and not sent to the agent.\n\n> {text}"
+        ),
+        Directive::Type(typed) => {
+            let speed = typed.speed.unwrap_or(front_matter.typing_speed);
+            let variance = typed.variance.unwrap_or(front_matter.typing_variance);
+            let keystrokes = typewriter_to_applescript(&typed.segments, speed, 0);
+            let shown: Vec<&str> = keystrokes
+                .iter()
+                .take(TYPE_PREVIEW)
+                .map(|(script, _)| script.as_str())
+                .collect();
+            let mut text = format!(
+                "Types {} keystroke(s), {speed} ms apart plus up to {variance} ms:\n\n{}",
+                keystrokes.len(),
+                script(shown.join("\n"))
+            );
+            if keystrokes.len() > TYPE_PREVIEW {
+                text.push_str(&format!("\n\nand {} more", keystrokes.len() - TYPE_PREVIEW));
+            }
+            text
+        }
+        Directive::Run => script(applescript::keystroke_script("return")),
+        Directive::Focus(app) => script(applescript::focus_app_script(app)),
+        Directive::Slide(SlideAction::Next) => script(applescript::slide_next_script()),
+        Directive::Slide(SlideAction::Prev) => script(applescript::slide_prev_script()),
+        Directive::Slide(SlideAction::GoTo(n)) => script(applescript::slide_goto_script(*n)),
+        Directive::Key(combo) => script(applescript::keystroke_script(combo)),
+        Directive::Clear => script(applescript::clear_script()),
+        Directive::Exec(exec) => {
+            let shell = front_matter.executor.shell.as_deref().unwrap_or("sh");
+            let wait = match exec.timeout {
+                Some(secs) => format!("waiting up to {secs} s for it to finish"),
+                None => "in the background".to_string(),
+            };
+            format!(
+                "Runs on the agent with `{shell} -c`, {wait}:\n\n```sh\n{}\n```",
+                exec.command
+            )
+        }
+        Directive::Wait(secs) => format!("The agent waits {secs} s before the next action"),
+        Directive::Pause(None) => "Waits for the presenter to press Enter".to_string(),
+        Directive::Pause(Some(secs)) => {
+            format!("Waits for the presenter, continuing on its own after {secs} s")
+        }
+        Directive::Speed(ms) => format!("Types the rest of the section at {ms} ms per keystroke"),
+        Directive::Chapter(name) => format!("Chapter '{name}'"),
+        Directive::Section(name) => format!("Section '{name}'"),
+        Directive::Step(name) => format!("Step '{name}'"),
+        Directive::Label(name) => format!("Label '{name}', a place the presenter can jump to"),
+        Directive::SkipTo(name) => {
+            format!("Lets the presenter skip ahead to label '{name}' instead of running the block")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn labels(completions: &[Completion]) -> Vec<&str> {
+        completions.iter().map(|c| c.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let path = Path::new("/tmp/demo.cm");
+        let analysis = Analysis::new(path, "[SAY] hi\n[SLDIE] next\n[WAIT x]\n");
+        let diagnostics = analysis.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].line_number, 2);
+        assert_eq!(diagnostics[0].file, None);
+
+        // Once the script parses, lint findings are reported
+        let analysis = Analysis::new(path, "[TYPE] ls\n");
+        let diagnostics = analysis.diagnostics();
+        assert_eq!(diagnostics[0].code, Some("type-without-focus"));
+    }
+
+    #[test]
+    fn test_hover() {
+        let text = "---\napps:\n  term: Terminal\nvars:\n  dir: src\n---\n\
+                    [FOCUS] term\n[TYPE] ls ${dir}\n[EXEC timeout=5 make]\n[PAUSE]";
+        let analysis = Analysis::new(Path::new("demo.cm"), text);
+        assert_eq!(
+            analysis.hover(6).unwrap(),
+            "```applescript\ntell application \"Terminal\" to activate\n```"
+        );
+        let typed = analysis.hover(7).unwrap();
+        assert!(
+            typed.starts_with("Types 6 keystroke(s), 40 ms apart"),
+            "{typed}"
+        );
+        assert!(typed.contains("keystroke \"l\"\n"), "{typed}");
+        assert!(typed.ends_with("and 3 more"), "{typed}");
+        assert!(
+            analysis
+                .hover(8)
+                .unwrap()
+                .contains("`sh -c`, waiting up to 5 s")
+        );
+        assert!(analysis.hover(9).unwrap().contains("Enter"));
+        assert_eq!(analysis.hover(1), None);
+    }
+
+    #[test]
+    fn test_hover_falls_back_when_the_script_has_errors() {
+        let analysis = Analysis::new(Path::new("demo.cm"), "[KEY cmd+s]\n[SLDIE]");
+        assert!(
+            analysis
+                .hover(0)
+                .unwrap()
+                .contains("keystroke \"s\" using command down")
+        );
+    }
+
+    #[test]
+    fn test_hover_call_shows_expansion() {
+        let text = "[DEFINE build]\n[TYPE] cargo build\n[RUN]\n[END]\n[CALL build]";
+        let analysis = Analysis::new(Path::new("demo.cm"), text);
+        assert_eq!(
+            analysis.hover(4).unwrap(),
+            "Expands macro `build` to:\n\n```\n[TYPE] cargo build\n[RUN]\n```"
+        );
+    }
+
+    #[test]
+    fn test_complete_directives() {
+        let analysis = Analysis::new(Path::new("demo.cm"), "[SA\n[SAY] hi");
+        let completions = analysis.completions(0, 3);
+        assert_eq!(completions.len(), DIRECTIVE_NAMES.len());
+        assert_eq!(completions[0].kind, CompletionKind::Directive);
+        assert!(analysis.completions(1, 9).is_empty());
+    }
+
+    #[test]
+    fn test_complete_front_matter_keys() {
+        let text = "---\nty\nexecutor:\n  sh\nagents:\n  demo:\n    ad\nlint:\n  \n---";
+        let analysis = Analysis::new(Path::new("demo.cm"), text);
+        let top = analysis.completions(1, 2);
+        assert!(labels(&top).contains(&"typing_speed"));
+        assert_eq!(top[0].kind, CompletionKind::FrontMatterKey);
+        assert_eq!(labels(&analysis.completions(3, 4)), EXECUTOR_KEYS);
+        assert_eq!(labels(&analysis.completions(6, 6)), AGENT_KEYS);
+        assert_eq!(labels(&analysis.completions(8, 2)), lint::rule_ids());
+        // Values are not completed
+        assert!(analysis.completions(2, 9).is_empty());
+    }
+
+    #[test]
+    fn test_complete_focus_apps_and_slides() {
+        let text = "---\napps:\n  editor: Visual Studio Code\nslide_count: 3\n---\n\
+                    [FOCUS] Terminal\n[FOCUS] Safari\n[FOCUS] \n[SLIDE ";
+        let analysis = Analysis::new(Path::new("demo.cm"), text);
+        let apps = analysis.completions(7, 8);
+        assert_eq!(labels(&apps), vec!["editor", "Safari", "Terminal"]);
+        assert_eq!(apps[0].detail.as_deref(), Some("Visual Studio Code"));
+        assert_eq!(
+            labels(&analysis.completions(8, 7)),
+            vec!["next", "prev", "1", "2", "3"]
+        );
+    }
+
+    #[test]
+    fn test_outline() {
+        let text = "[SAY] intro\n## Section: Build\n[TYPE] make\n\n## Section: Test\n\
+                    [TYPE]\n```\n## Section: not a header\n```\n";
+        let analysis = Analysis::new(Path::new("demo.cm"), text);
+        assert_eq!(
+            analysis.outline(),
+            vec![
+                Symbol {
+                    name: "Build".into(),
+                    level: HeadingLevel::Section,
+                    line: 1,
+                    end_line: 3,
+                    children: vec![],
+                },
+                Symbol {
+                    name: "Test".into(),
+                    level: HeadingLevel::Section,
+                    line: 4,
+                    end_line: 8,
+                    children: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outline_nests_headings() {
+        let text = "# Chapter: One\n## Section: A\n### Step: a1\n[RUN]\n### Step: a2\n\
+                    ## Section: B\n# Chapter: Two\n### Step: loose\n[RUN]";
+        let outline = Analysis::new(Path::new("demo.cm"), text).outline();
+        let shape = |symbols: &[Symbol]| -> Vec<(String, usize, usize, usize)> {
+            symbols
+                .iter()
+                .map(|s| (s.name.clone(), s.line, s.end_line, s.children.len()))
+                .collect()
+        };
+        assert_eq!(
+            shape(&outline),
+            [("One".into(), 0, 5, 2), ("Two".into(), 6, 8, 1)]
+        );
+        assert_eq!(
+            shape(&outline[0].children),
+            [("A".into(), 1, 4, 2), ("B".into(), 5, 5, 0)]
+        );
+        assert_eq!(
+            shape(&outline[0].children[0].children),
+            [("a1".into(), 2, 3, 0), ("a2".into(), 4, 4, 0)]
+        );
+        assert_eq!(outline[1].children[0].level, HeadingLevel::Step);
+    }
+
+    #[test]
+    fn test_definition_of_includes_and_macros() {
+        let dir = TempDir::new("lsp-definition");
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(
+            dir.join("shared/macros.cm"),
+            "# helpers\n[DEFINE open]\n[FOCUS] Terminal\n[END]\n",
+        )
+        .unwrap();
+        let path = dir.join("main.cm");
+        let text = "[INCLUDE shared/macros.cm]\n[DEFINE local]\n[RUN]\n[END]\n\
+                    [CALL open]\n[CALL local]\n[CALL missing]";
+        let analysis = Analysis::new(&path, text);
+
+        assert_eq!(
+            analysis.definition(0),
+            Some(Location {
+                path: dir.join("shared/macros.cm"),
+                line: 0
+            })
+        );
+        assert_eq!(
+            analysis.definition(4),
+            Some(Location {
+                path: dir.join("shared/macros.cm"),
+                line: 1
+            })
+        );
+        assert_eq!(
+            analysis.definition(5),
+            Some(Location {
+                path: path.clone(),
+                line: 1
+            })
+        );
+        assert_eq!(analysis.definition(6), None);
+        assert_eq!(analysis.definition(2), None);
+        assert_eq!(labels(&analysis.completions(6, 6)), vec!["local", "open"]);
+    }
+
+    #[test]
+    fn test_definition_of_skip_to() {
+        let path = Path::new("demo.cm");
+        let analysis = Analysis::new(path, "[SKIP_TO backup]\n[RUN]\n[LABEL backup]\n[RUN]");
+        assert_eq!(
+            analysis.definition(0),
+            Some(Location {
+                path: path.to_path_buf(),
+                line: 2
+            })
+        );
+        assert_eq!(analysis.definition(2), None);
+    }
+}
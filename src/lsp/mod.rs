@@ -0,0 +1,494 @@
+pub mod analysis;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::parser::types::HeadingLevel;
+use analysis::{Analysis, CompletionKind, Symbol};
+
+/// Run the language server over stdin and stdout until the client shuts it down.
+pub fn run_stdio() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    // The writer thread stops once the connection's sender is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Answer the initialize handshake on `connection`, then handle messages until shutdown.
+pub fn serve(connection: &Connection) -> Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.request(request).into())?;
+            }
+            Message::Notification(notification) => {
+                for outgoing in server.notification(notification) {
+                    connection.sender.send(outgoing.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["[".into(), " ".into()]),
+            ..Default::default()
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// The open documents, and the other files their diagnostics were last published to.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, String>,
+    published: HashMap<Url, Vec<Url>>,
+}
+
+impl Server {
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            DocumentSymbolRequest::METHOD => {
+                self.respond::<DocumentSymbolRequest>(request, Self::document_symbols)
+            }
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request '{}'", request.method),
+            ),
+        }
+    }
+
+    fn respond<R: lsp_types::request::Request>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    /// Track document changes, returning the diagnostics to publish.
+    fn notification(&mut self, notification: Notification) -> Vec<Notification> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = params::<DidOpenTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                self.publish(&document.uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(mut params) = params::<DidChangeTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                // Full sync: the last change holds the whole text
+                let Some(change) = params.content_changes.pop() else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                self.publish(&uri)
+            }
+            // Saving may change what other open scripts include
+            DidSaveTextDocument::METHOD => {
+                let uris: Vec<Url> = self.documents.keys().cloned().collect();
+                uris.iter().flat_map(|uri| self.publish(uri)).collect()
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = params::<DidCloseTextDocument>(notification) else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                let mut cleared = self.published.remove(&uri).unwrap_or_default();
+                cleared.push(uri);
+                cleared
+                    .into_iter()
+                    .map(|uri| publish_diagnostics(uri, Vec::new()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Diagnostics for the document at `uri`, and for the included files they point into.
+    /// Files that no longer have any are cleared.
+    fn publish(&mut self, uri: &Url) -> Vec<Notification> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let path = file_path(uri);
+        let mut by_file: BTreeMap<Url, Vec<lsp_types::Diagnostic>> = BTreeMap::new();
+        by_file.insert(uri.clone(), Vec::new());
+        for diagnostic in Analysis::new(&path, text).diagnostics() {
+            let target = match &diagnostic.file {
+                None => uri.clone(),
+                Some(file) => match Url::from_file_path(file) {
+                    Ok(target) => target,
+                    Err(()) => continue,
+                },
+            };
+            by_file.entry(target).or_default().push(to_lsp(&diagnostic));
+        }
+
+        let others = by_file.keys().filter(|u| *u != uri).cloned().collect();
+        for stale in self
+            .published
+            .insert(uri.clone(), others)
+            .unwrap_or_default()
+        {
+            by_file.entry(stale).or_default();
+        }
+        by_file
+            .into_iter()
+            .map(|(uri, diagnostics)| publish_diagnostics(uri, diagnostics))
+            .collect()
+    }
+
+    fn document(&self, uri: &Url) -> Option<(PathBuf, &str)> {
+        let text = self.documents.get(uri)?;
+        Some((file_path(uri), text))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let markdown = Analysis::new(&path, text).hover(position.position.line as usize)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }),
+            range: None,
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let line = position.position.line as usize;
+        let column = byte_offset(text.lines().nth(line)?, position.position.character);
+        let items = Analysis::new(&path, text)
+            .completions(line, column)
+            .into_iter()
+            .map(|completion| CompletionItem {
+                label: completion.label,
+                kind: Some(match completion.kind {
+                    CompletionKind::Directive => CompletionItemKind::KEYWORD,
+                    CompletionKind::FrontMatterKey => CompletionItemKind::PROPERTY,
+                    CompletionKind::Value => CompletionItemKind::VALUE,
+                }),
+                detail: completion.detail,
+                ..Default::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let (path, text) = self.document(&params.text_document.uri)?;
+        let lines: Vec<&str> = text.lines().collect();
+        let outline = Analysis::new(&path, text).outline();
+        Some(DocumentSymbolResponse::Nested(document_symbols(
+            outline, &lines,
+        )))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (path, text) = self.document(&position.text_document.uri)?;
+        let location = Analysis::new(&path, text).definition(position.position.line as usize)?;
+        let start = Position::new(location.line as u32, 0);
+        Some(GotoDefinitionResponse::Scalar(lsp_types::Location {
+            uri: Url::from_file_path(&location.path).ok()?,
+            range: Range::new(start, start),
+        }))
+    }
+}
+
+fn params<N: lsp_types::notification::Notification>(notification: Notification) -> Option<N::Params>
+where
+    N::Params: DeserializeOwned,
+{
+    serde_json::from_value(notification.params).ok()
+}
+
+fn publish_diagnostics(uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Notification {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+    };
+    Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+}
+
+/// The local path of a `file:` URI. Other schemes keep their path, so that unsaved
+/// documents can still be checked.
+fn file_path(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|()| PathBuf::from(uri.path()))
+}
+
+fn to_lsp(diagnostic: &Diagnostic) -> lsp_types::Diagnostic {
+    let line = diagnostic.line_number.saturating_sub(1) as u32;
+    let source = diagnostic.line_content.lines().next().unwrap_or_default();
+    // Without a span, underline the line's text
+    let (start, end) = match diagnostic.first_line_span() {
+        Some(span) => (span.start, span.end),
+        None => {
+            let start = source.len() - source.trim_start().len();
+            (start, source.trim_end().len().max(start))
+        }
+    };
+    let mut message = diagnostic.message.clone();
+    if let Some(help) = &diagnostic.help {
+        message.push_str(&format!("\nhelp: {help}"));
+    }
+    lsp_types::Diagnostic {
+        range: Range::new(
+            Position::new(line, utf16_len(&source[..start])),
+            Position::new(line, utf16_len(&source[..end])),
+        ),
+        severity: Some(match diagnostic.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        code: diagnostic
+            .code
+            .map(|code| NumberOrString::String(code.to_string())),
+        source: Some("code-monkey".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// The outline as LSP symbols: chapters as modules, sections as namespaces and steps as
+/// events.
+fn document_symbols(outline: Vec<Symbol>, lines: &[&str]) -> Vec<DocumentSymbol> {
+    outline
+        .into_iter()
+        .map(|symbol| {
+            let end = lines.get(symbol.end_line).map_or(0, |l| utf16_len(l));
+            let header = lines.get(symbol.line).map_or(0, |l| utf16_len(l));
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: symbol.name,
+                detail: None,
+                kind: match symbol.level {
+                    HeadingLevel::Chapter => SymbolKind::MODULE,
+                    HeadingLevel::Section => SymbolKind::NAMESPACE,
+                    HeadingLevel::Step => SymbolKind::EVENT,
+                },
+                tags: None,
+                deprecated: None,
+                range: Range::new(
+                    Position::new(symbol.line as u32, 0),
+                    Position::new(symbol.end_line as u32, end),
+                ),
+                selection_range: Range::new(
+                    Position::new(symbol.line as u32, 0),
+                    Position::new(symbol.line as u32, header),
+                ),
+                children: (!symbol.children.is_empty())
+                    .then(|| document_symbols(symbol.children, lines)),
+            }
+        })
+        .collect()
+}
+
+/// LSP columns count UTF-16 code units.
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+/// The byte offset in `line` of UTF-16 column `character`, clamped to the line.
+fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (offset, ch) in line.char_indices() {
+        if units >= character as usize {
+            return offset;
+        }
+        units += ch.len_utf16();
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use lsp_types::request::Initialize;
+    use lsp_types::{
+        DidOpenTextDocumentParams, InitializeParams, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams,
+    };
+
+    /// A server on an in-memory connection, past the initialize handshake.
+    fn start() -> (Connection, std::thread::JoinHandle<Result<()>>) {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || serve(&server));
+        let params = serde_json::to_value(InitializeParams::default()).unwrap();
+        let initialize = Request::new(1.into(), Initialize::METHOD.to_string(), params);
+        client.sender.send(initialize.into()).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected the initialize response");
+        };
+        assert!(response.result.unwrap()["capabilities"]["hoverProvider"] == true);
+        let initialized = Notification::new("initialized".to_string(), serde_json::json!({}));
+        client.sender.send(initialized.into()).unwrap();
+        (client, handle)
+    }
+
+    fn stop(client: Connection, handle: std::thread::JoinHandle<Result<()>>) {
+        let shutdown = Request::new(99.into(), "shutdown".to_string(), ());
+        client.sender.send(shutdown.into()).unwrap();
+        client.receiver.recv().unwrap();
+        let exit = Notification::new("exit".to_string(), ());
+        client.sender.send(exit.into()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    fn open(client: &Connection, uri: &Url, text: &str) {
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "cm".into(), 1, text.into()),
+        };
+        let notification = Notification::new(DidOpenTextDocument::METHOD.to_string(), params);
+        client.sender.send(notification.into()).unwrap();
+    }
+
+    fn request<R: lsp_types::request::Request>(
+        client: &Connection,
+        id: i32,
+        params: R::Params,
+    ) -> serde_json::Value {
+        let request = Request::new(RequestId::from(id), R::METHOD.to_string(), params);
+        client.sender.send(request.into()).unwrap();
+        let Message::Response(response) = client.receiver.recv().unwrap() else {
+            panic!("expected a response");
+        };
+        response.result.unwrap()
+    }
+
+    #[test]
+    fn test_publishes_diagnostics() {
+        let (client, handle) = start();
+        let uri = Url::parse("file:///tmp/code-monkey-lsp-demo.cm").unwrap();
+        open(&client, &uri, "[SAY] café\n  [SLDIE] next\n");
+
+        let Message::Notification(notification) = client.receiver.recv().unwrap() else {
+            panic!("expected diagnostics");
+        };
+        assert_eq!(notification.method, PublishDiagnostics::METHOD);
+        let params: PublishDiagnosticsParams = serde_json::from_value(notification.params).unwrap();
+        assert_eq!(params.uri, uri);
+        assert_eq!(params.diagnostics.len(), 1);
+        let diagnostic = &params.diagnostics[0];
+        assert_eq!(
+            diagnostic.range,
+            Range::new(Position::new(1, 3), Position::new(1, 8))
+        );
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostic.message,
+            "Unknown directive: [SLDIE]\nhelp: did you mean SLIDE?"
+        );
+        stop(client, handle);
+    }
+
+    #[test]
+    fn test_hover_and_completion_requests() {
+        let (client, handle) = start();
+        let uri = Url::parse("file:///tmp/code-monkey-lsp-hover.cm").unwrap();
+        open(&client, &uri, "[FOCUS] Terminal\n[KE");
+        client.receiver.recv().unwrap();
+
+        let position = |line, character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            position: Position::new(line, character),
+        };
+        let hover = request::<HoverRequest>(
+            &client,
+            2,
+            HoverParams {
+                text_document_position_params: position(0, 3),
+                work_done_progress_params: Default::default(),
+            },
+        );
+        assert_eq!(hover["contents"]["kind"], "markdown");
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("tell application \"Terminal\" to activate")
+        );
+
+        let completion = request::<Completion>(
+            &client,
+            3,
+            CompletionParams {
+                text_document_position: position(1, 3),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            },
+        );
+        let labels: Vec<&str> = completion
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert!(labels.contains(&"KEY"), "{labels:?}");
+        stop(client, handle);
+    }
+
+    #[test]
+    fn test_byte_offset() {
+        assert_eq!(byte_offset("[SAY] café", 9), 9);
+        assert_eq!(byte_offset("[SAY] café!", 10), 11);
+        assert_eq!(byte_offset("ab", 10), 2);
+        assert_eq!(utf16_len("é😀"), 3);
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -23,33 +23,489 @@ enum Commands {
         /// TCP port to listen on
         #[arg(long, default_value = "9876")]
         port: u16,
+        /// Override a front matter variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// Treat warnings, such as unknown front matter keys, as errors
+        #[arg(long)]
+        strict: bool,
+        /// Type [TYPE file=...] text from the files next to this script, not the presenter's
+        #[arg(long)]
+        preload: bool,
+        /// What performs the actions: applescript, linux, xdotool, ydotool, tmux, pty or dry-run
+        #[arg(long, value_name = "KIND")]
+        executor: Option<String>,
+        /// tmux session, window or pane for --executor tmux, e.g. demo:shell.1
+        #[arg(long, value_name = "TARGET")]
+        tmux_target: Option<String>,
+        /// Print each action and how long it took (not with --executor pty)
+        #[arg(long)]
+        log_actions: bool,
+        /// Write each action, its timing and any error to FILE as JSON Lines
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
     },
     /// Run a presentation (run on the presenter's laptop)
     Present {
         /// Script file path
         script: PathBuf,
-        /// Agent address (ip:port)
+        /// Agent address (ip:port), or the name of an agent listed under `agents:`
         #[arg(long)]
         agent: Option<String>,
         /// Show actions without connecting or executing
         #[arg(long)]
         dry_run: bool,
+        /// Output format for --dry-run
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Start at the block containing this line of the script
+        #[arg(long, value_name = "LINE")]
+        from_line: Option<usize>,
+        /// Override a front matter variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// Treat warnings, such as unknown front matter keys, as errors
+        #[arg(long)]
+        strict: bool,
+        /// Leave out [NOTE]s, e.g. when rehearsing over a screen share
+        #[arg(long)]
+        hide_notes: bool,
+        /// Narrate from [SAY:lang] and [NOTE:lang], falling back to the untagged text
+        #[arg(long, value_name = "LANG")]
+        lang: Option<String>,
     },
     /// Parse and validate a script without running
     Check {
         /// Script file path
         script: PathBuf,
+        /// Also run the lint rules, failing if any reports an error
+        #[arg(long)]
+        lint: bool,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Override a front matter variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// Treat warnings, such as unknown front matter keys, as errors
+        #[arg(long)]
+        strict: bool,
+        /// Warn about blocks with no [SAY:lang] or [NOTE:lang] for their narration or notes
+        #[arg(long, value_name = "LANG")]
+        lang: Option<String>,
+    },
+    /// Predict how long a script takes to present, per section
+    Estimate {
+        /// Script file path
+        script: PathBuf,
+        /// Reading rate for [SAY] narration, in words per minute
+        #[arg(long, default_value_t = code_monkey::estimate::DEFAULT_WPM)]
+        wpm: u64,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Override a front matter variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// Treat warnings, such as unknown front matter keys, as errors
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Record a script to an asciinema .cast file, running it in a local terminal
+    Render {
+        /// Script file path
+        script: PathBuf,
+        /// Where to write the asciicast v2 recording
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+        /// Seconds a [PAUSE] without a timeout lasts in the recording
+        #[arg(long, value_name = "SECS", default_value_t = 2.0)]
+        pause: f64,
+        /// Milliseconds without output that end a command before the next action
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        settle: u64,
+        /// Terminal width
+        #[arg(long, default_value_t = 80)]
+        cols: u16,
+        /// Terminal height
+        #[arg(long, default_value_t = 24)]
+        rows: u16,
+        /// Command to run in the terminal instead of your login shell
+        #[arg(long, value_name = "COMMAND")]
+        shell: Option<String>,
+        /// Override a front matter variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        /// Treat warnings, such as unknown front matter keys, as errors
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Rewrite scripts in the canonical style
+    Fmt {
+        /// Script file paths
+        #[arg(required = true)]
+        scripts: Vec<PathBuf>,
+        /// Report files that would change instead of rewriting them, failing if there are any
+        #[arg(long)]
+        check: bool,
     },
+    /// Run the language server for editors, over stdin and stdout
+    Lsp,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text, with diagnostics on stderr
+    Text,
+    /// One JSON document on stdout, diagnostics included
+    Json,
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))?;
+    Ok((key.trim().to_string(), value.to_string()))
+}
+
+/// Read a script and its include tree, applying `--var` overrides. The outer error is for
+/// a file that cannot be read; the inner one holds every parse error.
+fn parse_file(
+    path: &Path,
+    vars: Vec<(String, String)>,
+    strict: bool,
+) -> Result<Result<code_monkey::parser::types::Script, Vec<code_monkey::parser::lexer::ParseError>>>
+{
+    let content = std::fs::read_to_string(path)?;
+    let options = code_monkey::parser::ParseOptions {
+        vars: vars.into_iter().collect(),
+        strict,
+    };
+    Ok(code_monkey::parser::parse_script_at_all(
+        &content, path, &options,
+    ))
+}
+
+/// Like [`parse_file`], but prints warnings, and every parse error before failing.
+fn load_script(
+    path: &Path,
+    vars: Vec<(String, String)>,
+    strict: bool,
+) -> Result<code_monkey::parser::types::Script> {
+    let color = code_monkey::diagnostics::stderr_supports_color();
+    let script = parse_file(path, vars, strict)?.map_err(|errors| {
+        for error in &errors {
+            eprintln!(
+                "{}",
+                code_monkey::diagnostics::Diagnostic::from(error).render(color)
+            );
+        }
+        anyhow::anyhow!("{} parse error(s) in '{}'", errors.len(), path.display())
+    })?;
+    for warning in &script.warnings {
+        eprintln!(
+            "{}",
+            code_monkey::diagnostics::Diagnostic::warning(warning).render(color)
+        );
+    }
+    Ok(script)
+}
+
+/// Executors for `agent --executor` and `executor.kind` in the front matter.
+const EXECUTOR_KINDS: &[&str] = &[
+    "applescript",
+    "linux",
+    "xdotool",
+    "ydotool",
+    "tmux",
+    "pty",
+    "dry-run",
+];
+
+/// Build the executor named by `agent --executor` or the front matter, defaulting to the
+/// one for this platform. `tmux_target` is the pane for the tmux executor.
+fn build_executor(
+    kind: Option<&str>,
+    tmux_target: Option<&str>,
+    options: &code_monkey::parser::types::ExecutorOptions,
+) -> Result<Box<dyn code_monkey::agent::ActionExecutor>> {
+    use code_monkey::agent::linux::InputTool;
+    use code_monkey::agent::middleware::{DryRunExecutor, ShellExecutor};
+    use code_monkey::agent::tmux::Tmux;
+    use code_monkey::agent::{AppleScriptExecutor, LinuxExecutor, PtyExecutor, TmuxExecutor};
+
+    let default = if cfg!(target_os = "macos") {
+        "applescript"
+    } else {
+        "linux"
+    };
+    let linux = |tool| Box::new(LinuxExecutor::new(tool));
+    let executor: Box<dyn code_monkey::agent::ActionExecutor> = match kind.unwrap_or(default) {
+        "applescript" => Box::new(AppleScriptExecutor),
+        "linux" => linux(InputTool::detect()),
+        "xdotool" => linux(InputTool::Xdotool),
+        "ydotool" => linux(InputTool::Ydotool),
+        "tmux" => {
+            let target = tmux_target.or(options.target.as_deref()).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The tmux executor needs a session: pass --tmux-target or set executor.target"
+                )
+            })?;
+            Box::new(TmuxExecutor::new(Tmux::new(target)))
+        }
+        "pty" => Box::new(PtyExecutor::mirror(None)?),
+        // Runs nothing, so it needs no shell
+        "dry-run" => return Ok(Box::new(DryRunExecutor::new())),
+        other => anyhow::bail!(
+            "Unknown executor '{other}' (expected one of {})",
+            EXECUTOR_KINDS.join(", ")
+        ),
+    };
+    Ok(match &options.shell {
+        Some(shell) => Box::new(ShellExecutor::new(executor, shell.as_str())),
+        None => executor,
+    })
+}
+
+/// Resolve `--agent`: an `ip:port` address, or a name from the front matter's `agents:`.
+fn agent_address(
+    agent: &str,
+    front_matter: &code_monkey::parser::types::FrontMatter,
+) -> Result<std::net::SocketAddr> {
+    let address = match front_matter.agents.get(agent) {
+        Some(config) => config.address.as_str(),
+        None => agent,
+    };
+    address.parse().map_err(|e| {
+        if front_matter.agents.is_empty() || address.contains(':') {
+            anyhow::anyhow!("Invalid agent address '{address}': {e}")
+        } else {
+            let names: Vec<&str> = front_matter.agents.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown agent '{agent}' (agents in the front matter: {})",
+                names.join(", ")
+            )
+        }
+    })
+}
+
+/// Run the lint rules over a parsed script.
+fn lint_findings(
+    path: &Path,
+    script: &code_monkey::parser::types::Script,
+    blocks: &[code_monkey::grouper::ActionBlock],
+) -> Result<Vec<code_monkey::diagnostics::Diagnostic>> {
+    let mut sources = code_monkey::lint::Sources::default();
+    sources.add(None, &std::fs::read_to_string(path)?);
+    for include in &script.includes {
+        sources.add(Some(include.clone()), &std::fs::read_to_string(include)?);
+    }
+    Ok(code_monkey::lint::lint(script, blocks, &sources))
+}
+
+/// Print the lint findings for a parsed script, failing if any is an error.
+fn lint_script(
+    path: &Path,
+    script: &code_monkey::parser::types::Script,
+    blocks: &[code_monkey::grouper::ActionBlock],
+) -> Result<()> {
+    let findings = lint_findings(path, script, blocks)?;
+    let color = code_monkey::diagnostics::stderr_supports_color();
+    let mut errors = 0;
+    for mut finding in findings.iter().cloned() {
+        finding.file.get_or_insert_with(|| path.to_path_buf());
+        eprintln!("{}", finding.render(color));
+        if finding.severity == code_monkey::diagnostics::Severity::Error {
+            errors += 1;
+        }
+    }
+    println!(
+        "Lint: {} warning(s), {errors} error(s)",
+        findings.len() - errors
+    );
+    if errors > 0 {
+        anyhow::bail!("{errors} lint error(s) in '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// `check --lang`: a warning for each block whose narration or notes have no translation
+/// into `lang`, or an error with `strict`.
+fn translation_findings(
+    path: &Path,
+    blocks: &[code_monkey::grouper::ActionBlock],
+    lang: &str,
+    strict: bool,
+) -> Result<Vec<code_monkey::diagnostics::Diagnostic>> {
+    let content = std::fs::read_to_string(path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let mut findings = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let missing = block.missing_translations(lang);
+        if missing.is_empty() {
+            continue;
+        }
+        let line_number = block.narration_lines.or(block.lines).map_or(1, |r| r.start);
+        let tags: Vec<String> = missing
+            .iter()
+            .map(|tag| format!("[{tag}:{lang}]"))
+            .collect();
+        findings.push(code_monkey::diagnostics::Diagnostic {
+            severity: if strict {
+                code_monkey::diagnostics::Severity::Error
+            } else {
+                code_monkey::diagnostics::Severity::Warning
+            },
+            code: None,
+            message: format!("Block {} has no {}", i + 1, tags.join(" or ")),
+            file: Some(path.to_path_buf()),
+            line_number,
+            line_content: lines.get(line_number - 1).unwrap_or(&"").to_string(),
+            span: None,
+            help: Some(format!(
+                "'present --lang {lang}' shows the untagged text for this block"
+            )),
+        });
+    }
+    Ok(findings)
+}
+
+/// What a JSON report includes besides the parsed script.
+#[derive(Default)]
+struct ReportOptions {
+    /// Run the lint rules (`check --lint`).
+    lint: bool,
+    /// Report on untranslated blocks (`check --lang`).
+    check_lang: Option<String>,
+    /// Leave out blocks before the one containing this line (`present --from-line`).
+    from_line: Option<usize>,
+    /// Leave out `[NOTE]`s (`present --hide-notes`).
+    hide_notes: bool,
+    /// Show the narration in this language (`present --lang`).
+    lang: Option<String>,
+}
+
+/// Group a script into blocks as `present` shows them: without notes for `--hide-notes`,
+/// and in the language of `--lang`.
+fn presented_blocks(
+    script: &mut code_monkey::parser::types::Script,
+    hide_notes: bool,
+    lang: Option<&str>,
+) -> Vec<code_monkey::grouper::ActionBlock> {
+    if hide_notes {
+        script.remove_notes();
+    }
+    let mut blocks = code_monkey::grouper::group_into_blocks(script);
+    if let Some(lang) = lang {
+        for block in &mut blocks {
+            block.localize(lang);
+        }
+    }
+    blocks
+}
+
+/// Print a JSON report on a script for `--format json`, failing if it has errors.
+fn report_json(
+    path: &Path,
+    vars: Vec<(String, String)>,
+    strict: bool,
+    options: &ReportOptions,
+) -> Result<()> {
+    let mut parsed = match parse_file(path, vars, strict)? {
+        Ok(parsed) => parsed,
+        Err(errors) => {
+            let diagnostics = errors
+                .iter()
+                .map(code_monkey::diagnostics::Diagnostic::from);
+            let report = code_monkey::report::ScriptReport::failed(path, diagnostics);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            anyhow::bail!("{} parse error(s) in '{}'", errors.len(), path.display());
+        }
+    };
+    let blocks = presented_blocks(&mut parsed, options.hide_notes, options.lang.as_deref());
+    let start = match options.from_line {
+        Some(line) => block_at_line(path, &blocks, line)?,
+        None => 0,
+    };
+
+    let mut report = code_monkey::report::ScriptReport::new(path, &parsed, &blocks, start);
+    if options.lint {
+        report.add_diagnostics(lint_findings(path, &parsed, &blocks)?);
+    }
+    if let Some(lang) = &options.check_lang {
+        report.add_diagnostics(translation_findings(path, &blocks, lang, strict)?);
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.valid {
+        anyhow::bail!("'{}' has errors", path.display());
+    }
+    Ok(())
+}
+
+/// The index of the block for `present --from-line`.
+fn block_at_line(
+    path: &Path,
+    blocks: &[code_monkey::grouper::ActionBlock],
+    line: usize,
+) -> Result<usize> {
+    code_monkey::grouper::block_index_for_line(blocks, line)
+        .ok_or_else(|| anyhow::anyhow!("No block at or after line {line} in '{}'", path.display()))
+}
+
+/// Format each script in place, or with `check` only report the ones that are not
+/// formatted.
+fn format_scripts(scripts: &[PathBuf], check: bool) -> Result<()> {
+    let mut unformatted = 0;
+    for path in scripts {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read '{}': {e}", path.display()))?;
+        let formatted = code_monkey::formatter::format_script(&content);
+        if formatted == content {
+            continue;
+        }
+        if check {
+            // Point at the first line that changes
+            let line = content
+                .lines()
+                .zip(formatted.lines())
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| content.lines().count().min(formatted.lines().count()))
+                + 1;
+            println!("{}:{line}: not formatted", path.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(path, formatted)?;
+            println!("Formatted {}", path.display());
+        }
+    }
+    if unformatted > 0 {
+        anyhow::bail!("{unformatted} file(s) would be reformatted by 'code-monkey fmt'");
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Check { script } => {
-            let content = std::fs::read_to_string(&script)?;
-            let parsed =
-                code_monkey::parser::parse_script(&content).map_err(|e| anyhow::anyhow!("{e}"))?;
+        Commands::Check {
+            script,
+            lint,
+            format,
+            vars,
+            strict,
+            lang,
+        } => {
+            let lang = lang.map(|lang| lang.to_lowercase());
+            if format == OutputFormat::Json {
+                let options = ReportOptions {
+                    lint,
+                    check_lang: lang,
+                    ..ReportOptions::default()
+                };
+                return report_json(&script, vars, strict, &options);
+            }
+            let parsed = load_script(&script, vars, strict)?;
             let blocks = code_monkey::grouper::group_into_blocks(&parsed);
             println!(
                 "Script '{}' is valid: {} directives, {} action blocks",
@@ -60,30 +516,92 @@ fn main() -> Result<()> {
             if let Some(title) = &parsed.front_matter.title {
                 println!("Title: {title}");
             }
+            if parsed.macros_defined > 0 {
+                println!(
+                    "Macros: {} defined, {} expanded",
+                    parsed.macros_defined, parsed.macros_expanded
+                );
+            }
+            if !parsed.includes.is_empty() {
+                let names: Vec<String> = parsed
+                    .includes
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect();
+                println!("Includes: {}", names.join(", "));
+            }
+            if let Some(lang) = &lang {
+                let findings = translation_findings(&script, &blocks, lang, strict)?;
+                let color = code_monkey::diagnostics::stderr_supports_color();
+                for finding in &findings {
+                    eprintln!("{}", finding.render(color));
+                }
+                if strict && !findings.is_empty() {
+                    anyhow::bail!(
+                        "{} block(s) in '{}' have no '{lang}' translation",
+                        findings.len(),
+                        script.display()
+                    );
+                }
+            }
+            if lint {
+                lint_script(&script, &parsed, &blocks)?;
+            }
             Ok(())
         }
         Commands::Present {
             script,
             dry_run,
+            format,
+            from_line,
             agent,
+            vars,
+            strict,
+            hide_notes,
+            lang,
         } => {
-            let content = std::fs::read_to_string(&script)?;
-            let parsed =
-                code_monkey::parser::parse_script(&content).map_err(|e| anyhow::anyhow!("{e}"))?;
+            let lang = lang.map(|lang| lang.to_lowercase());
+            if format == OutputFormat::Json {
+                if !dry_run {
+                    anyhow::bail!("--format json is only available with --dry-run");
+                }
+                let options = ReportOptions {
+                    from_line,
+                    hide_notes,
+                    lang,
+                    ..ReportOptions::default()
+                };
+                return report_json(&script, vars, strict, &options);
+            }
+            let mut parsed = load_script(&script, vars, strict)?;
+            let blocks = presented_blocks(&mut parsed, hide_notes, lang.as_deref());
+            let start = match from_line {
+                Some(line) => block_at_line(&script, &blocks, line)?,
+                None => 0,
+            };
 
             if dry_run {
-                let blocks = code_monkey::grouper::group_into_blocks(&parsed);
                 println!("=== Dry Run: {} ===\n", script.display());
-                for (i, block) in blocks.iter().enumerate() {
-                    println!("--- Block {} ---", i + 1);
-                    if let Some(section) = &block.section {
-                        println!("  Section: {section}");
+                for (i, block) in blocks.iter().enumerate().skip(start) {
+                    let previous = (i > start).then(|| &blocks[i - 1]);
+                    for heading in block.headings_entered(previous) {
+                        println!("{heading}\n");
+                    }
+                    match block.lines {
+                        Some(lines) => println!("--- Block {} ({lines}) ---", i + 1),
+                        None => println!("--- Block {} ---", i + 1),
+                    }
+                    for label in &block.labels {
+                        println!("  [LABEL {label}]");
                     }
                     if let Some(narration) = &block.narration {
                         for line in narration.lines() {
                             println!("  [SAY] {line}");
                         }
                     }
+                    for note in &block.notes {
+                        println!("  [NOTE] {note}");
+                    }
                     match &block.block_type {
                         code_monkey::grouper::BlockType::Action => {
                             for action in &block.actions {
@@ -100,6 +618,9 @@ fn main() -> Result<()> {
                             println!("  (narration only)");
                         }
                     }
+                    if let Some(label) = &block.skip_to {
+                        println!("  [SKIP_TO {label}] (presenter may take this branch)");
+                    }
                     println!();
                 }
                 return Ok(());
@@ -110,11 +631,15 @@ fn main() -> Result<()> {
                 anyhow::anyhow!("--agent <ip:port> is required when not using --dry-run")
             })?;
 
-            let agent_addr: std::net::SocketAddr = agent_str
-                .parse()
-                .map_err(|e| anyhow::anyhow!("Invalid agent address '{agent_str}': {e}"))?;
+            let agent_addr = agent_address(&agent_str, &parsed.front_matter)?;
 
             let mut presenter = code_monkey::client::Presenter::new(parsed, agent_addr);
+            if let Some(lang) = &lang {
+                presenter.set_language(lang);
+            }
+            if let Some(line) = from_line {
+                presenter.start_at_line(line);
+            }
 
             println!("Connecting to agent at {agent_addr}...");
             match presenter.connect() {
@@ -130,10 +655,88 @@ fn main() -> Result<()> {
             code_monkey::tui::run_tui(&mut app)?;
             Ok(())
         }
-        Commands::Agent { script, port } => {
-            let content = std::fs::read_to_string(&script)?;
-            let parsed =
-                code_monkey::parser::parse_script(&content).map_err(|e| anyhow::anyhow!("{e}"))?;
+        Commands::Estimate {
+            script,
+            wpm,
+            format,
+            vars,
+            strict,
+        } => {
+            let parsed = load_script(&script, vars, strict)?;
+            let blocks = code_monkey::grouper::group_into_blocks(&parsed);
+            let estimate = code_monkey::estimate::estimate(&blocks, &parsed.front_matter, wpm);
+            match format {
+                OutputFormat::Text => print!("{estimate}"),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&estimate)?),
+            }
+            if let (Some(duration), Some(over)) = (estimate.duration_ms, estimate.over_duration_ms)
+            {
+                use code_monkey::estimate::format_duration;
+                eprintln!(
+                    "warning: estimated {} is {} over the planned duration of {}",
+                    format_duration(estimate.total_ms),
+                    format_duration(over),
+                    format_duration(duration)
+                );
+            }
+            Ok(())
+        }
+        Commands::Render {
+            script,
+            output,
+            pause,
+            settle,
+            cols,
+            rows,
+            shell,
+            vars,
+            strict,
+        } => {
+            let pause = std::time::Duration::try_from_secs_f64(pause)
+                .map_err(|_| anyhow::anyhow!("--pause must be a number of seconds, not {pause}"))?;
+            let parsed = load_script(&script, vars, strict)?;
+            let blocks = code_monkey::grouper::group_into_blocks(&parsed);
+            let options = code_monkey::render::RenderOptions {
+                cols,
+                rows,
+                pause,
+                settle: std::time::Duration::from_millis(settle),
+                shell,
+            };
+            let cast = code_monkey::render::render(&blocks, &parsed.front_matter, &options)?;
+            let file = std::fs::File::create(&output)
+                .map_err(|e| anyhow::anyhow!("Cannot write {}: {e}", output.display()))?;
+            cast.write_to(std::io::BufWriter::new(file))?;
+            println!(
+                "Rendered {} ({}) to {}",
+                script.display(),
+                code_monkey::estimate::format_duration((cast.duration() * 1000.0) as u64),
+                output.display()
+            );
+            Ok(())
+        }
+        Commands::Fmt { scripts, check } => format_scripts(&scripts, check),
+        Commands::Lsp => code_monkey::lsp::run_stdio(),
+        Commands::Agent {
+            script,
+            port,
+            vars,
+            strict,
+            preload,
+            executor,
+            tmux_target,
+            log_actions,
+            record,
+        } => {
+            let parsed = load_script(&script, vars, strict)?;
+            let options = &parsed.front_matter.executor;
+            let kind = executor.as_deref().or(options.kind.as_deref());
+            if log_actions && kind == Some("pty") {
+                anyhow::bail!(
+                    "--log-actions would print over the pty executor's terminal; \
+                     use --record <FILE> to keep a log instead"
+                );
+            }
             let blocks = code_monkey::grouper::group_into_blocks(&parsed);
             println!(
                 "Script validated: {} directives, {} action blocks",
@@ -144,8 +747,38 @@ fn main() -> Result<()> {
                 println!("Title: {title}");
             }
 
-            let executor = code_monkey::agent::AppleScriptExecutor;
-            let agent = code_monkey::agent::Agent::new(Box::new(executor), port);
+            let preloaded = preload.then(|| {
+                let preloaded: std::collections::HashMap<_, _> = parsed
+                    .lines
+                    .iter()
+                    .filter_map(|line| match &line.directive {
+                        code_monkey::parser::types::Directive::Type(typed) => {
+                            typed.source.clone().map(|source| (source, typed.clone()))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                println!("Preloaded {} file snippets", preloaded.len());
+                preloaded
+            });
+
+            // The pty executor takes over the terminal, so it starts after everything above
+            let mut executor = build_executor(kind, tmux_target.as_deref(), options)?;
+            if let Some(record) = &record {
+                executor = Box::new(code_monkey::agent::middleware::RecordingExecutor::create(
+                    executor, record,
+                )?);
+            }
+            if log_actions {
+                executor = Box::new(code_monkey::agent::middleware::LoggingExecutor::new(
+                    executor,
+                ));
+            }
+            let mut agent =
+                code_monkey::agent::Agent::new(executor, port).with_quiet(kind == Some("pty"));
+            if let Some(preloaded) = preloaded {
+                agent = agent.with_preloaded(preloaded);
+            }
             agent.run().map_err(|e| {
                 let msg = e.to_string();
                 if msg.contains("Address already in use") || msg.contains("AddrInUse") {
@@ -0,0 +1,301 @@
+use std::fmt;
+
+use super::lexer::{self, Attribute, FENCE};
+use super::types::HeadingLevel;
+
+/// A lossless view of a script: every line, including the blank lines and comments that
+/// [`Script`](super::types::Script) drops, grouped into nodes. Printing the tree reproduces
+/// the source byte for byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree<'a> {
+    pub nodes: Vec<Node<'a>>,
+    /// Whether the source ends with a line break.
+    pub trailing_newline: bool,
+}
+
+/// One or more consecutive source lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node<'a> {
+    pub kind: NodeKind<'a>,
+    /// The lines the node was built from, verbatim and without their line breaks.
+    pub lines: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind<'a> {
+    /// The `---` block at the top of the file, including both markers. `closed` is false
+    /// when the closing marker is missing and the block runs to the end of the file.
+    FrontMatter {
+        body: Vec<&'a str>,
+        closed: bool,
+    },
+    Blank,
+    /// A `#` comment.
+    Comment(&'a str),
+    /// A `# Chapter:`, `## Section:` or `### Step:` heading, with the name trimmed.
+    Heading(HeadingLevel, &'a str),
+    /// A bracket line such as `[TYPE speed=80] cargo build`, with the body of a fenced
+    /// `[TYPE]` when one follows.
+    Directive(DirectiveNode<'a>),
+    /// A line the lexer would reject, kept as written.
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectiveNode<'a> {
+    /// The directive name as written, in any case.
+    pub tag: &'a str,
+    pub attributes: Vec<Attribute<'a>>,
+    /// The rest of the bracket contents after the attributes.
+    pub inline: &'a str,
+    /// The text after the closing bracket.
+    pub after: &'a str,
+    pub fence: Option<Fence<'a>>,
+}
+
+/// The body of a fenced `[TYPE]` block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fence<'a> {
+    /// Whether the opening fence is on its own line below a bare `[TYPE]`.
+    pub own_line: bool,
+    /// The opening fence line, e.g. ```` ```rust ````.
+    pub opening: &'a str,
+    pub body: Vec<&'a str>,
+    /// False when the fence is never closed and the body runs to the end of the file.
+    pub closed: bool,
+}
+
+/// Build the syntax tree of `input`. This never fails: anything the lexer would reject
+/// becomes an [`NodeKind::Invalid`] node.
+pub fn parse_cst(input: &str) -> SyntaxTree<'_> {
+    let mut lines: Vec<&str> = input.split('\n').collect();
+    let trailing_newline = input.ends_with('\n');
+    if trailing_newline || input.is_empty() {
+        lines.pop();
+    }
+
+    let mut nodes = Vec::new();
+    let mut idx = 0;
+    if lines.first().is_some_and(|l| l.trim() == "---") {
+        let closing = lines[1..].iter().position(|l| l.trim() == "---");
+        let end = closing.map_or(lines.len(), |c| c + 2);
+        nodes.push(Node {
+            kind: NodeKind::FrontMatter {
+                body: lines[1..closing.map_or(lines.len(), |c| c + 1)].to_vec(),
+                closed: closing.is_some(),
+            },
+            lines: lines[..end].to_vec(),
+        });
+        idx = end;
+    }
+
+    while idx < lines.len() {
+        let line = lines[idx];
+        let trimmed = line.trim();
+        let single = |kind| Node {
+            kind,
+            lines: vec![line],
+        };
+
+        if trimmed.is_empty() {
+            nodes.push(single(NodeKind::Blank));
+        } else if let Some((level, hashes, name)) = lexer::split_heading(trimmed) {
+            // The lexer rejects a heading with the wrong number of `#`s
+            nodes.push(single(if hashes == level.depth() {
+                NodeKind::Heading(level, name)
+            } else {
+                NodeKind::Invalid
+            }));
+        } else if trimmed.starts_with('#') {
+            nodes.push(single(NodeKind::Comment(trimmed)));
+        } else if trimmed.starts_with('[')
+            && let Ok(bracket) = lexer::split_bracket(line, idx + 1)
+        {
+            let (attributes, inline) = lexer::split_attributes(bracket.tag, bracket.inline);
+            let mut directive = DirectiveNode {
+                tag: bracket.tag,
+                attributes,
+                inline,
+                after: bracket.after,
+                fence: None,
+            };
+            let end = match fence_at(&directive, &lines, idx) {
+                Some((fence, end)) => {
+                    directive.fence = Some(fence);
+                    end
+                }
+                None => idx + 1,
+            };
+            nodes.push(Node {
+                kind: NodeKind::Directive(directive),
+                lines: lines[idx..end].to_vec(),
+            });
+            idx = end;
+            continue;
+        } else {
+            nodes.push(single(NodeKind::Invalid));
+        }
+        idx += 1;
+    }
+
+    SyntaxTree {
+        nodes,
+        trailing_newline,
+    }
+}
+
+/// The fenced body following the `[TYPE]` on `lines[idx]`, if any, with the index of the
+/// first line after it. Mirrors [`lexer::parse_fenced_type`].
+fn fence_at<'a>(
+    directive: &DirectiveNode<'a>,
+    lines: &[&'a str],
+    idx: usize,
+) -> Option<(Fence<'a>, usize)> {
+    // `[TYPE file=...]` takes its text from the file, never from a fence
+    if !directive.tag.eq_ignore_ascii_case("TYPE")
+        || directive.attributes.iter().any(|a| a.key == "file")
+    {
+        return None;
+    }
+    let arg = if directive.after.is_empty() {
+        directive.inline
+    } else {
+        directive.after
+    };
+    let (fence_idx, opening) = if arg.starts_with(FENCE) {
+        (idx, arg)
+    } else if arg.is_empty()
+        && let Some(next) = lines.get(idx + 1)
+        && next.trim().starts_with(FENCE)
+    {
+        (idx + 1, next.trim())
+    } else {
+        return None;
+    };
+
+    let body_start = fence_idx + 1;
+    let closing = lines[body_start..].iter().position(|l| l.trim() == FENCE);
+    let body_end = closing.map_or(lines.len(), |c| body_start + c);
+    let fence = Fence {
+        own_line: fence_idx != idx,
+        opening,
+        body: lines[body_start..body_end].to_vec(),
+        closed: closing.is_some(),
+    };
+    let end = if fence.closed { body_end + 1 } else { body_end };
+    Some((fence, end))
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for line in self.nodes.iter().flat_map(|node| &node.lines) {
+            if !first {
+                f.write_str("\n")?;
+            }
+            f.write_str(line)?;
+            first = false;
+        }
+        if self.trailing_newline {
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds<'a>(tree: &'a SyntaxTree<'a>) -> Vec<&'a NodeKind<'a>> {
+        tree.nodes.iter().map(|n| &n.kind).collect()
+    }
+
+    #[test]
+    fn test_cst_is_lossless() {
+        let inputs = [
+            "",
+            "\n",
+            "[SAY] hi",
+            "---\ntitle: Demo\n---\n\n\n# note\r\n  [type  speed=80]  x  \n[TYPE]\n```\n  a\n```\n",
+            "---\ntitle: unclosed\n[SAY] hi\n",
+            "[TYPE] ```\nnever closed\n",
+            "not a directive\n[SAY missing bracket\n",
+        ];
+        for input in inputs {
+            assert_eq!(parse_cst(input).to_string(), input, "{input:?}");
+        }
+        for path in ["examples/demo.cm", "examples/macros.cm", "examples/vars.cm"] {
+            let input = std::fs::read_to_string(path).unwrap();
+            assert_eq!(parse_cst(&input).to_string(), input, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_cst_keeps_comments_and_sections() {
+        let tree = parse_cst("# intro\n## Section: Build\n\n[SAY] hi\noops\n");
+        assert_eq!(
+            kinds(&tree),
+            vec![
+                &NodeKind::Comment("# intro"),
+                &NodeKind::Heading(HeadingLevel::Section, "Build"),
+                &NodeKind::Blank,
+                &NodeKind::Directive(DirectiveNode {
+                    tag: "SAY",
+                    attributes: vec![],
+                    inline: "",
+                    after: "hi",
+                    fence: None,
+                }),
+                &NodeKind::Invalid,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cst_directive_parts() {
+        let tree = parse_cst("  [exec timeout=5 ./run.sh]");
+        let NodeKind::Directive(directive) = &tree.nodes[0].kind else {
+            panic!("expected a directive");
+        };
+        assert_eq!(directive.tag, "exec");
+        assert_eq!(
+            directive.attributes,
+            vec![Attribute {
+                key: "timeout",
+                value: "5"
+            }]
+        );
+        assert_eq!(directive.inline, "./run.sh");
+    }
+
+    #[test]
+    fn test_cst_fences() {
+        let tree = parse_cst("[TYPE]\n```rust\nfn main() {}\n\n```\n[RUN]");
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.nodes[0].lines.len(), 5);
+        let NodeKind::Directive(directive) = &tree.nodes[0].kind else {
+            panic!("expected a directive");
+        };
+        let fence = directive.fence.as_ref().unwrap();
+        assert!(fence.own_line && fence.closed);
+        assert_eq!(fence.opening, "```rust");
+        assert_eq!(fence.body, vec!["fn main() {}", ""]);
+
+        let tree = parse_cst("[TYPE] ```\n[SAY] not a directive");
+        assert_eq!(tree.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_cst_front_matter() {
+        let tree = parse_cst("---\ntitle: Demo\n---\n[SAY] hi");
+        assert_eq!(
+            tree.nodes[0].kind,
+            NodeKind::FrontMatter {
+                body: vec!["title: Demo"],
+                closed: true
+            }
+        );
+        assert_eq!(tree.nodes[0].lines.len(), 3);
+    }
+}
@@ -1,9 +1,66 @@
-use super::lexer::ParseError;
+use std::ops::Range;
+
+use serde::de::{self, Deserializer, Visitor};
+
+use super::lexer::{ParseError, Span};
 use super::types::FrontMatter;
+use crate::diagnostics::closest_match;
+use crate::lint::rule_ids;
+
+/// Keys allowed at the top level of the front matter.
+pub const TOP_LEVEL_KEYS: &[&str] = &[
+    "title",
+    "typing_speed",
+    "typing_variance",
+    "agent_port",
+    "vars",
+    "agents",
+    "apps",
+    "executor",
+    "slide_count",
+    "duration",
+    "lint",
+];
+/// Keys of an entry under `agents:`.
+pub const AGENT_KEYS: &[&str] = &["address"];
+/// Keys of the `executor:` table.
+pub const EXECUTOR_KEYS: &[&str] = &["kind", "shell", "target"];
+
+/// The result of reading a script's front matter.
+#[derive(Debug, Default)]
+pub struct ParsedFrontMatter {
+    /// The settings, keeping defaults for any invalid entries.
+    pub front_matter: FrontMatter,
+    /// Index of the first line after the front matter.
+    pub content_start: usize,
+    pub errors: Vec<ParseError>,
+    /// Unknown keys, which are ignored.
+    pub warnings: Vec<ParseError>,
+    /// Some variables could not be read, so a reference to one is not an error of its own.
+    pub vars_failed: bool,
+}
 
 pub fn extract_front_matter(lines: &[&str]) -> Result<(FrontMatter, usize), ParseError> {
+    let mut parsed = parse_front_matter(lines);
+    if parsed.errors.is_empty() {
+        Ok((parsed.front_matter, parsed.content_start))
+    } else {
+        Err(parsed.errors.remove(0))
+    }
+}
+
+/// Parse the YAML between the opening and closing `---`.
+///
+/// A YAML syntax error is reported once and leaves every setting at its default. An invalid
+/// setting is reported where it was found, and the front matter is read again without that
+/// top-level entry, so each bad entry is reported and the rest still apply. An unclosed
+/// front matter block swallows the rest of the file, since there is no way to tell where it
+/// was meant to end.
+pub fn parse_front_matter(lines: &[&str]) -> ParsedFrontMatter {
+    let mut parsed = ParsedFrontMatter::default();
+
     if lines.is_empty() || lines[0].trim() != "---" {
-        return Ok((FrontMatter::default(), 0));
+        return parsed;
     }
 
     // Find closing ---
@@ -11,79 +68,325 @@ pub fn extract_front_matter(lines: &[&str]) -> Result<(FrontMatter, usize), Pars
     let closing_idx = match closing {
         Some(idx) => idx + 1, // offset by 1 because we started searching from index 1
         None => {
-            return Err(ParseError {
-                line_number: 1,
-                line_content: "---".to_string(),
-                message: "Front matter opened but never closed with '---'".to_string(),
-            });
+            parsed.errors.push(ParseError::new(
+                1,
+                "---",
+                "Front matter opened but never closed with '---'",
+            ));
+            parsed.content_start = lines.len();
+            return parsed;
         }
     };
+    parsed.content_start = closing_idx + 1;
 
-    let mut fm = FrontMatter::default();
+    let body = &lines[1..closing_idx];
+    match serde_yaml_ng::from_str::<serde_yaml_ng::Value>(&body.join("\n")) {
+        Ok(serde_yaml_ng::Value::Null) => return parsed,
+        Ok(serde_yaml_ng::Value::Mapping(_)) => {}
+        Ok(_) => {
+            parsed.errors.push(
+                ParseError::new(2, body[0], "Front matter must be 'key: value' lines")
+                    .with_span(Span::of(body[0], body[0].trim())),
+            );
+            parsed.vars_failed = true;
+            return parsed;
+        }
+        Err(e) => {
+            parsed.errors.push(
+                error_at(body, &e, "Invalid front matter: ")
+                    .with_help("front matter is YAML; quote values that contain ': ' or ' #'"),
+            );
+            parsed.vars_failed = true;
+            return parsed;
+        }
+    }
 
-    for (i, line) in lines[1..closing_idx].iter().enumerate() {
-        let line_number = i + 2; // 1-indexed, offset by opening ---
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+    let mut remaining = body.to_vec();
+    parsed.front_matter = loop {
+        match serde_yaml_ng::from_str::<FrontMatter>(&remaining.join("\n")) {
+            Ok(front_matter) => break front_matter,
+            Err(e) => {
+                parsed.errors.push(error_at(body, &e, ""));
+                let entry = e.location().and_then(|at| entry_lines(&remaining, at));
+                let Some(entry) = entry else {
+                    parsed.vars_failed = true;
+                    break FrontMatter::default();
+                };
+                let vars = key_line(&remaining, &["vars"]);
+                parsed.vars_failed |= vars
+                    .is_some_and(|vars| (vars..block_end(&remaining, vars)).contains(&entry.start));
+                remaining[entry].fill("");
+            }
         }
+    };
+    check(&mut parsed, body);
+    parsed
+}
 
-        // Strip inline comments
-        let without_comment = if let Some(hash_pos) = trimmed.find('#') {
-            trimmed[..hash_pos].trim()
-        } else {
-            trimmed
+/// Report unknown keys and agents without an address, and drop them.
+fn check(parsed: &mut ParsedFrontMatter, body: &[&str]) {
+    let fm = &mut parsed.front_matter;
+    let mut unknown = |path: &[&str], known: &[&str], what: &str| {
+        let key = path[path.len() - 1];
+        let help = match closest_match(key, known) {
+            Some(name) => format!("did you mean '{name}'?"),
+            None => format!("known keys are {}", known.join(", ")),
         };
+        parsed
+            .warnings
+            .push(key_error(body, path, format!("Unknown {what} '{key}'")).with_help(help));
+    };
 
-        let Some((key, value)) = without_comment.split_once(':') else {
-            return Err(ParseError {
-                line_number,
-                line_content: line.to_string(),
-                message: "Expected 'key: value' format in front matter".to_string(),
-            });
-        };
+    for key in std::mem::take(&mut fm.extra).keys() {
+        unknown(&[key], TOP_LEVEL_KEYS, "front matter key");
+    }
+    for key in std::mem::take(&mut fm.executor.extra).keys() {
+        unknown(&["executor", key], EXECUTOR_KEYS, "executor setting");
+    }
+    for (name, agent) in &mut fm.agents {
+        for key in std::mem::take(&mut agent.extra).keys() {
+            unknown(&["agents", name, key], AGENT_KEYS, "agent setting");
+        }
+    }
+    let rules = rule_ids();
+    fm.lint.retain(|rule, _| {
+        let known = rules.contains(&rule.as_str());
+        if !known {
+            unknown(&["lint", rule], &rules, "lint rule");
+        }
+        known
+    });
 
-        let key = key.trim();
-        let value = value.trim();
+    fm.agents.retain(|name, agent| {
+        if agent.address.is_empty() {
+            parsed.errors.push(
+                key_error(
+                    body,
+                    &["agents", name],
+                    format!("Agent '{name}' has no address"),
+                )
+                .with_help("give it one, as in 'demo: 192.168.1.20:9876'"),
+            );
+        }
+        !agent.address.is_empty()
+    });
+    parsed.errors.sort_by_key(|e| e.line_number);
+    parsed.warnings.sort_by_key(|w| w.line_number);
+}
 
-        match key {
-            "title" => {
-                fm.title = Some(value.to_string());
-            }
-            "typing_speed" => {
-                fm.typing_speed = value.parse().map_err(|_| ParseError {
-                    line_number,
-                    line_content: line.to_string(),
-                    message: format!("Invalid typing_speed value: '{value}'"),
-                })?;
-            }
-            "typing_variance" => {
-                fm.typing_variance = value.parse().map_err(|_| ParseError {
-                    line_number,
-                    line_content: line.to_string(),
-                    message: format!("Invalid typing_variance value: '{value}'"),
-                })?;
+/// Read `duration:` as `20m`, `90s`, `1h30m` or `12:30`.
+pub(super) fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    struct DurationVisitor;
+
+    impl Visitor<'_> for DurationVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a duration such as 20m, 1h30m or 12:30")
+        }
+
+        // Fail inside the deserializer, so the error points at the value
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<Option<u64>, E> {
+            parse_duration(text).map(Some).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_str(DurationVisitor)
+}
+
+/// An error from serde, on the line and under the value it points at. `prefix` goes before
+/// serde's message, which names the key.
+fn error_at(body: &[&str], error: &serde_yaml_ng::Error, prefix: &str) -> ParseError {
+    // The position is shown with the source line, so leave it out of the message
+    let mut message = error.to_string();
+    while let Some(pos) = message.find(" at line ") {
+        let rest = &message[pos + 1..];
+        let end = rest.find(',').unwrap_or(rest.len());
+        message.replace_range(pos..pos + 1 + end, "");
+    }
+    let Some(at) = error.location() else {
+        return ParseError::new(1, "---", format!("{prefix}{message}"));
+    };
+    let idx = (at.line() - 1).min(body.len().saturating_sub(1));
+    let line = body.get(idx).copied().unwrap_or("");
+    let start = line
+        .char_indices()
+        .nth(at.column() - 1)
+        .map_or(line.len(), |(pos, _)| pos);
+    let value = strip_comment(&line[start..]).trim_end();
+    let span = if value.is_empty() {
+        Span::of(line, line.trim())
+    } else {
+        Span {
+            start,
+            end: start + value.len(),
+        }
+    };
+    ParseError::new(idx + 2, line, format!("{prefix}{message}")).with_span(span)
+}
+
+/// An error on the key at `path`, or on the first line if it cannot be found.
+fn key_error(body: &[&str], path: &[&str], message: String) -> ParseError {
+    let key = path[path.len() - 1];
+    match key_line(body, path) {
+        Some(idx) => {
+            ParseError::new(idx + 2, body[idx], message).with_span(Span::of(body[idx], key))
+        }
+        None => ParseError::new(1, "---", message),
+    }
+}
+
+/// The line where `path`, keys from the top level down, is set. Each key is looked for
+/// among the lines indented under the one before it, at the indent of the first of them,
+/// so text inside a block string is not taken for a key.
+fn key_line(body: &[&str], path: &[&str]) -> Option<usize> {
+    let mut lines = 0..body.len();
+    let mut parent_indent = None;
+    let mut found = None;
+    for key in path {
+        let mut indent = None;
+        let mut next = None;
+        for idx in lines.clone() {
+            let line = body[idx];
+            let text = line.trim_start();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
             }
-            "agent_port" => {
-                fm.agent_port = value.parse().map_err(|_| ParseError {
-                    line_number,
-                    line_content: line.to_string(),
-                    message: format!("Invalid agent_port value: '{value}'"),
-                })?;
+            let this_indent = line.len() - text.len();
+            if parent_indent.is_some_and(|parent| this_indent <= parent) {
+                break;
             }
-            _ => {
-                // Unknown keys are silently ignored
+            let indent = *indent.get_or_insert(this_indent);
+            if this_indent == indent
+                && let Some(rest) = text.strip_prefix(key)
+                && rest.trim_start().starts_with(':')
+            {
+                next = Some((idx, this_indent));
+                break;
             }
         }
+        let (idx, indent) = next?;
+        found = Some(idx);
+        parent_indent = Some(indent);
+        lines = idx + 1..body.len();
+    }
+    found
+}
+
+/// The lines of the entry that `at` points into: the key on that line, or else the key
+/// the value is indented under. A table left empty without the entry goes with it, since
+/// an empty table is not valid either.
+fn entry_lines(body: &[&str], at: serde_yaml_ng::Location) -> Option<Range<usize>> {
+    let idx = at.line().checked_sub(1).filter(|&idx| idx < body.len())?;
+    let text = strip_comment(body[idx].trim_start());
+    let is_key = !text.starts_with(['-', '[', '{']) && text.contains(':');
+    let mut start = if is_key || at.column() - 1 > indent(body[idx]) {
+        idx
+    } else {
+        parent_line(body, idx)?
+    };
+    let mut entry = start..block_end(body, start);
+    while let Some(parent) = parent_line(body, start)
+        && (parent + 1..block_end(body, parent))
+            .all(|idx| entry.contains(&idx) || !is_content(body[idx]))
+    {
+        start = parent;
+        entry = start..block_end(body, start);
+    }
+    Some(entry)
+}
+
+/// The nearest line before `idx` indented less than it.
+fn parent_line(body: &[&str], idx: usize) -> Option<usize> {
+    let own = indent(body[idx]);
+    body[..idx]
+        .iter()
+        .rposition(|line| is_content(line) && indent(line) < own)
+}
+
+/// The end of the lines indented under `start`.
+fn block_end(body: &[&str], start: usize) -> usize {
+    let own = indent(body[start]);
+    body[start + 1..]
+        .iter()
+        .position(|line| is_content(line) && indent(line) <= own)
+        .map_or(body.len(), |pos| start + 1 + pos)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_content(line: &str) -> bool {
+    !line.trim().is_empty() && !line.trim_start().starts_with('#')
+}
+
+/// Seconds in a duration written as `20m`, `90s`, `1h30m` or `12:30` (minutes and
+/// seconds).
+fn parse_duration(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid duration value: '{text}'");
+    let too_long = || "duration too long".to_string();
+    let text = text.trim();
+    if let Some((minutes, seconds)) = text.split_once(':') {
+        let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.parse().map_err(|_| invalid())?;
+        if seconds >= 60 {
+            return Err(invalid());
+        }
+        return minutes
+            .checked_mul(60)
+            .and_then(|secs| secs.checked_add(seconds))
+            .ok_or_else(too_long);
     }
 
-    Ok((fm, closing_idx + 1))
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    let mut total: u64 = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match rest[digits..].chars().next() {
+            Some('h') => 3600,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(invalid()),
+        };
+        total = amount
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(too_long)?;
+        rest = &rest[digits + 1..];
+    }
+    Ok(total)
+}
+
+/// `value` up to a ` #` comment, unless the `#` is inside quotes.
+fn strip_comment(value: &str) -> &str {
+    let trimmed = value.trim_start();
+    if trimmed.starts_with(['"', '\'']) {
+        return value;
+    }
+    match value.find(" #") {
+        Some(pos) => &value[..pos],
+        None => value,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse(input: &str) -> ParsedFrontMatter {
+        let lines: Vec<&str> = input.lines().collect();
+        parse_front_matter(&lines)
+    }
+
     #[test]
     fn test_front_matter_basic() {
         let lines: Vec<&str> = "---\ntitle: My Talk\ntyping_speed: 60\n---\n[SAY] hi"
@@ -115,9 +418,18 @@ mod tests {
 
     #[test]
     fn test_front_matter_unknown_key_warns() {
-        let lines: Vec<&str> = "---\nfoo: bar\n---".lines().collect();
-        let (fm, _) = extract_front_matter(&lines).unwrap();
-        assert_eq!(fm.title, None); // unknown key ignored
+        let parsed = parse("---\ntitle: Demo\ntyping_sped: 60\n---");
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.front_matter.typing_speed, 40);
+        assert_eq!(parsed.warnings.len(), 1);
+        let warning = &parsed.warnings[0];
+        assert_eq!(warning.line_number, 3);
+        assert!(warning.message.contains("typing_sped"), "{warning}");
+        assert_eq!(
+            warning.help.as_deref(),
+            Some("did you mean 'typing_speed'?")
+        );
+        assert_eq!(warning.span, Some(Span { start: 0, end: 11 }));
     }
 
     #[test]
@@ -147,6 +459,122 @@ mod tests {
         assert_eq!(fm.agent_port, 4444);
     }
 
+    #[test]
+    fn test_front_matter_vars() {
+        let lines: Vec<&str> =
+            "---\ntitle: Demo\nvars:\n  project: my-demo\n  port: 8080\ntyping_speed: 50\n---"
+                .lines()
+                .collect();
+        let (fm, _) = extract_front_matter(&lines).unwrap();
+        assert_eq!(fm.vars.len(), 2);
+        assert_eq!(fm.vars["project"], "my-demo");
+        assert_eq!(fm.vars["port"], "8080");
+        assert_eq!(fm.typing_speed, 50);
+    }
+
+    #[test]
+    fn test_front_matter_vars_inline_value_rejected() {
+        let lines: Vec<&str> = "---\nvars: project\n---".lines().collect();
+        let err = extract_front_matter(&lines).unwrap_err();
+        assert_eq!(err.line_number, 2);
+        assert_eq!(err.span, Some(Span { start: 6, end: 13 }));
+    }
+
+    #[test]
+    fn test_front_matter_collects_all_errors() {
+        let parsed =
+            parse("---\ntyping_speed: fast\ntitle: Still Parsed\nvars: {}\nagent_port: 99999\n---");
+        assert_eq!(parsed.content_start, 6);
+        assert_eq!(parsed.front_matter.title, Some("Still Parsed".to_string()));
+        assert_eq!(parsed.front_matter.typing_speed, 40);
+        let lines: Vec<usize> = parsed.errors.iter().map(|e| e.line_number).collect();
+        assert_eq!(lines, vec![2, 5]);
+        // The bad value is underlined, not the whole line
+        assert_eq!(parsed.errors[0].span, Some(Span { start: 14, end: 18 }));
+    }
+
+    #[test]
+    fn test_front_matter_syntax_error() {
+        let parsed = parse("---\ntitle: Demo\nnonsense\ntyping_speed: 60\n---\n[SAY] hi");
+        assert_eq!(parsed.content_start, 5);
+        assert_eq!(parsed.errors.len(), 1);
+        let err = &parsed.errors[0];
+        assert!(err.message.starts_with("Invalid front matter"), "{err}");
+        assert!(!err.message.contains("column"), "{err}");
+        assert!((2..=4).contains(&err.line_number), "{err}");
+    }
+
+    #[test]
+    fn test_front_matter_errors_point_at_the_parsed_key() {
+        // A block string can hold text that looks like another key
+        let parsed = parse("---\nvars:\n  notes: |\n    port: 1\n  port: [1]\n---");
+        assert_eq!(parsed.errors.len(), 1, "{:?}", parsed.errors);
+        assert_eq!(parsed.errors[0].line_number, 5);
+        assert_eq!(parsed.errors[0].span, Some(Span { start: 8, end: 11 }));
+        assert_eq!(parsed.front_matter.vars["notes"], "port: 1\n");
+    }
+
+    #[test]
+    fn test_front_matter_invalid_entry_keeps_its_siblings() {
+        let parsed = parse(
+            "---\nvars:\n  a: [1, 2]\n  b: 3\nagents:\n  demo:\n    address: [1]\n  backup: 10.0.0.2:9876\n---",
+        );
+        let lines: Vec<usize> = parsed.errors.iter().map(|e| e.line_number).collect();
+        assert_eq!(lines, vec![3, 7], "{:?}", parsed.errors);
+        assert_eq!(parsed.errors[0].span, Some(Span { start: 5, end: 11 }));
+        let fm = parsed.front_matter;
+        assert_eq!(fm.vars.keys().collect::<Vec<_>>(), ["b"]);
+        assert_eq!(fm.agents.keys().collect::<Vec<_>>(), ["backup"]);
+    }
+
+    #[test]
+    fn test_front_matter_unknown_lint_rule_warns() {
+        let parsed = parse("---\nlint:\n  nonsense: off\n---");
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert!(parsed.front_matter.lint.is_empty());
+        assert_eq!(parsed.warnings.len(), 1);
+        assert_eq!(parsed.warnings[0].line_number, 3);
+        assert!(parsed.warnings[0].message.contains("lint rule 'nonsense'"));
+    }
+
+    #[test]
+    fn test_front_matter_aliases() {
+        let parsed = parse("---\nvars:\n  host: &host demo.local\n  url: *host\n---");
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.front_matter.vars["url"], "demo.local");
+    }
+
+    #[test]
+    fn test_duration() {
+        let parsed = parse("---\nduration: 1h30m\n---");
+        assert_eq!(parsed.front_matter.duration, Some(5400));
+        assert_eq!(parse_duration("12:30"), Ok(750));
+        assert_eq!(parse_duration("90s"), Ok(90));
+        assert_eq!(parse_duration("20m"), Ok(1200));
+
+        for bad in ["20", "m", "1x", "12:75", ""] {
+            assert!(parse_duration(bad).is_err(), "{bad}");
+        }
+        for huge in [
+            "307445734561825861:00",
+            "5124095576030432h",
+            "18446744073709551615s1s",
+        ] {
+            assert_eq!(
+                parse_duration(huge),
+                Err("duration too long".into()),
+                "{huge}"
+            );
+        }
+        let parsed = parse("---\nduration: 20\n---");
+        assert_eq!(
+            parsed.errors[0].message,
+            "duration: Invalid duration value: '20'"
+        );
+        assert_eq!(parsed.errors[0].span, Some(Span { start: 10, end: 12 }));
+        assert_eq!(parsed.errors[0].line_number, 2);
+    }
+
     #[test]
     fn test_front_matter_with_inline_comments() {
         let lines: Vec<&str> = "---\ntyping_speed: 60  # fast typing\n---"
@@ -155,4 +583,61 @@ mod tests {
         let (fm, _) = extract_front_matter(&lines).unwrap();
         assert_eq!(fm.typing_speed, 60);
     }
+
+    #[test]
+    fn test_front_matter_keeps_hash_and_colon() {
+        let parsed = parse(
+            "---\ntitle: C# Tips\nvars:\n  url: \"http://localhost:8080/#top\"\n  tag: 'a # b'\n---",
+        );
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let fm = parsed.front_matter;
+        assert_eq!(fm.title.as_deref(), Some("C# Tips"));
+        assert_eq!(fm.vars["url"], "http://localhost:8080/#top");
+        assert_eq!(fm.vars["tag"], "a # b");
+    }
+
+    #[test]
+    fn test_front_matter_nested_tables() {
+        let parsed = parse(
+            "---\n\
+             agents:\n  \
+               demo:\n    \
+                 address: 192.168.1.20:9876\n  \
+               backup: 10.0.0.2:9876\n\
+             apps:\n  \
+               editor: Visual Studio Code\n  \
+               term: iTerm\n\
+             executor:\n  \
+               kind: applescript\n  \
+               shell: /bin/zsh\n  \
+               target: demo:shell\n\
+             ---",
+        );
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert!(parsed.warnings.is_empty(), "{:?}", parsed.warnings);
+        let fm = parsed.front_matter;
+        assert_eq!(fm.agents["demo"].address, "192.168.1.20:9876");
+        assert_eq!(fm.agents["backup"].address, "10.0.0.2:9876");
+        assert_eq!(fm.apps["editor"], "Visual Studio Code");
+        assert_eq!(fm.executor.kind.as_deref(), Some("applescript"));
+        assert_eq!(fm.executor.shell.as_deref(), Some("/bin/zsh"));
+        assert_eq!(fm.executor.target.as_deref(), Some("demo:shell"));
+    }
+
+    #[test]
+    fn test_front_matter_nested_unknown_keys_warn() {
+        let parsed = parse(
+            "---\nagents:\n  demo:\n    adress: 1.2.3.4:9876\nexecutor:\n  knd: applescript\n---",
+        );
+        let lines: Vec<usize> = parsed.warnings.iter().map(|w| w.line_number).collect();
+        assert_eq!(lines, vec![4, 6]);
+        assert!(
+            parsed.warnings[1]
+                .message
+                .contains("executor setting 'knd'")
+        );
+        // The agent is unusable without its address
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line_number, 3);
+    }
 }
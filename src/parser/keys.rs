@@ -0,0 +1,301 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// Keys that can be pressed on their own, by name.
+pub const KEY_NAMES: &[&str] = &[
+    "return",
+    "enter",
+    "tab",
+    "space",
+    "delete",
+    "backspace",
+    "escape",
+    "esc",
+    "left",
+    "right",
+    "up",
+    "down",
+];
+
+/// Modifiers accepted before `+` in a key combo.
+pub const MODIFIERS: &[&str] = &[
+    "cmd", "command", "ctrl", "control", "shift", "alt", "opt", "option",
+];
+
+/// One step of a `[TYPE]`: text typed character by character, or a key press.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TypeSegment {
+    Text(String),
+    Key { combo: String, repeat: u32 },
+}
+
+/// A malformed key token, with its byte range in the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyTokenError {
+    pub message: String,
+    pub range: Range<usize>,
+}
+
+/// Split `[TYPE]` text into typed runs and key presses.
+///
+/// `\n` and `\t` press Return and Tab; `\\` and `\{` are a literal backslash and brace.
+/// `{tab}`, `{esc}`, `{cmd+s}` or `{left x3}` press a key, optionally repeated. Braces that
+/// do not hold a key name, as in `fn main() {}` or `"{name}"`, are typed as written. A
+/// literal line break presses Return, like `\n`.
+pub fn parse_type_text(text: &str) -> Result<Vec<TypeSegment>, KeyTokenError> {
+    let mut segments = Vec::new();
+    let mut run = String::new();
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, ch)) = chars.next() {
+        match ch {
+            '\\' => match chars.peek().map(|&(_, c)| c) {
+                Some('n') => {
+                    chars.next();
+                    push_key(&mut segments, &mut run, "return", 1);
+                }
+                Some('t') => {
+                    chars.next();
+                    push_key(&mut segments, &mut run, "tab", 1);
+                }
+                Some(c @ ('\\' | '{')) => {
+                    chars.next();
+                    run.push(c);
+                }
+                // Anything else keeps its backslash, so paths and regexes survive
+                _ => run.push('\\'),
+            },
+            '\n' => push_key(&mut segments, &mut run, "return", 1),
+            '{' => match key_token(&text[pos..]) {
+                Token::Key { combo, repeat, len } => {
+                    push_key(&mut segments, &mut run, &combo, repeat);
+                    // Skip the rest of the token; it is all ASCII
+                    for _ in 1..len {
+                        chars.next();
+                    }
+                }
+                Token::Invalid { message, len } => {
+                    return Err(KeyTokenError {
+                        message,
+                        range: pos..pos + len,
+                    });
+                }
+                Token::NotAKey => run.push('{'),
+            },
+            c => run.push(c),
+        }
+    }
+
+    if !run.is_empty() {
+        segments.push(TypeSegment::Text(run));
+    }
+    Ok(segments)
+}
+
+fn push_key(segments: &mut Vec<TypeSegment>, run: &mut String, combo: &str, repeat: u32) {
+    if !run.is_empty() {
+        segments.push(TypeSegment::Text(std::mem::take(run)));
+    }
+    segments.push(TypeSegment::Key {
+        combo: combo.to_string(),
+        repeat,
+    });
+}
+
+/// Escape `text` so that [`parse_type_text`] types it exactly as written, with no escape
+/// sequences or key tokens. Used for fenced blocks, which hold code verbatim.
+pub fn escape_literal(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (pos, ch) in text.char_indices() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '{' if !matches!(key_token(&text[pos..]), Token::NotAKey) => out.push_str("\\{"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The inverse of [`escape_literal`].
+pub fn unescape_literal(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\'
+            && let Some(&next @ ('\\' | '{')) = chars.peek()
+        {
+            chars.next();
+            out.push(next);
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+enum Token {
+    Key {
+        combo: String,
+        repeat: u32,
+        len: usize,
+    },
+    Invalid {
+        message: String,
+        len: usize,
+    },
+    NotAKey,
+}
+
+/// Classify the `{...}` at the start of `text`.
+fn key_token(text: &str) -> Token {
+    let Some(end) = text.find('}') else {
+        return Token::NotAKey;
+    };
+    let inner = &text[1..end];
+    let len = end + 1;
+    if inner.is_empty()
+        || !inner
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == ' ')
+    {
+        return Token::NotAKey;
+    }
+
+    let (combo, repeat) = match inner.rsplit_once(" x") {
+        Some((combo, count)) if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) => {
+            match count.parse::<u32>() {
+                Ok(n) if n > 0 => (combo.trim(), n),
+                _ => {
+                    return Token::Invalid {
+                        message: format!("Invalid repeat count in {{{inner}}}"),
+                        len,
+                    };
+                }
+            }
+        }
+        _ => (inner, 1),
+    };
+    let combo = combo.to_lowercase();
+    let parts: Vec<&str> = combo.split('+').collect();
+    let (key, modifiers) = parts.split_last().expect("split yields at least one part");
+
+    let key_ok = KEY_NAMES.contains(key) || (!modifiers.is_empty() && key.chars().count() == 1);
+    if key_ok && modifiers.iter().all(|m| MODIFIERS.contains(m)) {
+        return Token::Key {
+            combo: combo.clone(),
+            repeat,
+            len,
+        };
+    }
+
+    // A plain word such as `{name}` is ordinary text; anything combo-shaped is a mistake
+    if modifiers.is_empty() && repeat == 1 {
+        return Token::NotAKey;
+    }
+    let message = match modifiers.iter().find(|m| !MODIFIERS.contains(m)) {
+        Some(m) => format!("Unknown modifier '{m}' in {{{inner}}}"),
+        None => format!("Unknown key '{key}' in {{{inner}}}"),
+    };
+    Token::Invalid { message, len }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> TypeSegment {
+        TypeSegment::Text(s.to_string())
+    }
+
+    fn key(combo: &str, repeat: u32) -> TypeSegment {
+        TypeSegment::Key {
+            combo: combo.to_string(),
+            repeat,
+        }
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            parse_type_text("cargo build").unwrap(),
+            vec![text("cargo build")]
+        );
+        assert!(parse_type_text("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(
+            parse_type_text(r"a\nb\tc").unwrap(),
+            vec![
+                text("a"),
+                key("return", 1),
+                text("b"),
+                key("tab", 1),
+                text("c")
+            ]
+        );
+        assert_eq!(
+            parse_type_text(r"C:\dir \\n \{tab}").unwrap(),
+            vec![text(r"C:\dir \n {tab}")]
+        );
+    }
+
+    #[test]
+    fn test_key_tokens() {
+        assert_eq!(
+            parse_type_text("Vec::wi{tab}(10){esc}{cmd+s}{left x3}").unwrap(),
+            vec![
+                text("Vec::wi"),
+                key("tab", 1),
+                text("(10)"),
+                key("esc", 1),
+                key("cmd+s", 1),
+                key("left", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_braces_that_are_not_keys() {
+        for input in ["fn main() {}", "println!(\"{name}\")", "{ a }", "{x}"] {
+            assert_eq!(
+                parse_type_text(input).unwrap(),
+                vec![text(input)],
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_break_presses_return() {
+        assert_eq!(
+            parse_type_text("a\nb").unwrap(),
+            vec![text("a"), key("return", 1), text("b")]
+        );
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        let err = parse_type_text("ab{cmd+bogus}").unwrap_err();
+        assert!(err.message.contains("bogus"), "{}", err.message);
+        assert_eq!(err.range, 2..13);
+        assert!(
+            parse_type_text("{hyper+s}")
+                .unwrap_err()
+                .message
+                .contains("hyper")
+        );
+        assert!(parse_type_text("{left x0}").is_err());
+        assert!(parse_type_text("{word x2}").is_err());
+    }
+
+    #[test]
+    fn test_escape_literal_roundtrip() {
+        let code = "printf(\"a\\n\"); {tab} {name} \\";
+        let escaped = escape_literal(code);
+        assert_eq!(parse_type_text(&escaped).unwrap(), vec![text(code)]);
+        assert_eq!(unescape_literal(&escaped), code);
+    }
+}
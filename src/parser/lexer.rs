@@ -1,11 +1,117 @@
-use super::types::{Directive, ParsedLine, SlideAction};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, thiserror::Error)]
-#[error("Parse error at line {line_number}: {message}\n  | {line_content}")]
+use super::keys::{KEY_NAMES, escape_literal, parse_type_text};
+use super::macros::split_args;
+use super::types::{
+    Directive, ExecCommand, HeadingLevel, Localized, ParsedLine, SlideAction, TypeSource, TypeText,
+};
+use crate::diagnostics::closest_match;
+
+/// Every bracket directive the lexer understands, in the order they are usually introduced.
+pub const DIRECTIVE_NAMES: &[&str] = &[
+    "SAY", "TYPE", "RUN", "PAUSE", "FOCUS", "SLIDE", "KEY", "CLEAR", "WAIT", "EXEC", "SPEED",
+    "NOTE", "LABEL", "SKIP_TO", "INCLUDE", "DEFINE", "END", "CALL",
+];
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Parse error {}at line {line_number}: {message}\n  | {line_content}",
+    file_prefix(file.as_deref())
+)]
 pub struct ParseError {
+    pub file: Option<PathBuf>,
     pub line_number: usize,
     pub line_content: String,
     pub message: String,
+    /// The offending part of `line_content`, if it can be narrowed down.
+    pub span: Option<Span>,
+    /// A hint on how to fix the error.
+    pub help: Option<Box<str>>,
+}
+
+/// A byte range within a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The position of `part` within `line`. `part` is normally a slice of `line`; otherwise
+    /// its first occurrence is used, falling back to the whole line.
+    pub fn of(line: &str, part: &str) -> Self {
+        let base = line.as_ptr() as usize;
+        let offset = (part.as_ptr() as usize).wrapping_sub(base);
+        let start = if offset
+            .checked_add(part.len())
+            .is_some_and(|end| end <= line.len())
+        {
+            offset
+        } else if let Some(found) = line.find(part) {
+            found
+        } else {
+            return Self {
+                start: 0,
+                end: line.len(),
+            };
+        };
+        Self {
+            start,
+            end: start + part.len(),
+        }
+    }
+}
+
+impl ParseError {
+    pub fn new(line_number: usize, line_content: &str, message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            line_number,
+            line_content: line_content.to_string(),
+            message: message.into(),
+            span: None,
+            help: None,
+        }
+    }
+
+    /// Attribute the error to `file`, unless it already names one (errors raised inside an
+    /// included script keep pointing at that script).
+    pub fn in_file(mut self, file: Option<&Path>) -> Self {
+        if self.file.is_none() {
+            self.file = file.map(Path::to_path_buf);
+        }
+        self
+    }
+
+    /// Point the error at `part` of its line.
+    pub fn at(mut self, part: &str) -> Self {
+        self.span = Some(Span::of(&self.line_content, part));
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into().into_boxed_str());
+        self
+    }
+}
+
+fn file_prefix(file: Option<&Path>) -> String {
+    file.map(|f| format!("in {} ", f.display()))
+        .unwrap_or_default()
+}
+
+/// Lines that shape how the script is assembled rather than producing a [`Directive`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preprocessor {
+    Include(String),
+    Define(String),
+    End,
+    Call { name: String, args: Vec<String> },
 }
 
 pub fn parse_line(line: &str, line_number: usize) -> Result<Option<ParsedLine>, ParseError> {
@@ -15,69 +121,399 @@ pub fn parse_line(line: &str, line_number: usize) -> Result<Option<ParsedLine>,
         return Ok(None);
     }
 
-    // Comments
-    if trimmed.starts_with('#') && !trimmed.starts_with("## Section:") {
-        return Ok(None);
-    }
-
-    // Section headers
-    if let Some(rest) = trimmed.strip_prefix("## Section:") {
-        let name = rest.trim().to_string();
+    // Chapter, section and step headings; any other `#` line is a comment
+    if trimmed.starts_with('#') {
+        let Some((level, hashes, name)) = split_heading(trimmed) else {
+            return Ok(None);
+        };
+        if hashes != level.depth() {
+            let marker = &trimmed[..trimmed.find(':').unwrap_or(hashes) + 1];
+            return Err(ParseError::new(
+                line_number,
+                line,
+                format!(
+                    "{} headings start with {}",
+                    level.keyword(),
+                    "#".repeat(level.depth())
+                ),
+            )
+            .at(marker)
+            .with_help(format!("write '{} {name}'", level.marker())));
+        }
+        let name = name.to_string();
+        let directive = match level {
+            HeadingLevel::Chapter => Directive::Chapter(name),
+            HeadingLevel::Section => Directive::Section(name),
+            HeadingLevel::Step => Directive::Step(name),
+        };
         return Ok(Some(ParsedLine {
-            line_number,
-            directive: Directive::Section(name),
+            line_content: line.to_string(),
+            ..ParsedLine::new(line_number, directive)
         }));
     }
 
     // Bracket directives: [DIRECTIVE] optional_arg
     if trimmed.starts_with('[') {
-        let directive = parse_bracket_directive(trimmed, line_number)?;
+        let directive = parse_bracket_directive(line, line_number)?;
         return Ok(Some(ParsedLine {
-            line_number,
-            directive,
+            line_content: line.to_string(),
+            ..ParsedLine::new(line_number, directive)
         }));
     }
 
-    Err(ParseError {
-        line_number,
-        line_content: line.to_string(),
-        message: "Unrecognized line format".to_string(),
+    Err(
+        ParseError::new(line_number, line, "Unrecognized line format")
+            .with_span(Span::of(line, trimmed))
+            .with_help(
+                "lines must be a directive such as '[SAY] text', a heading such as \
+                 '## Section: name', or a '#' comment",
+            ),
+    )
+}
+
+/// Split a `#` line that names a heading level, as in `## Section: Build`, into the level,
+/// the number of `#`s and the trimmed name. The `#`s are not checked against the level.
+pub(crate) fn split_heading(trimmed: &str) -> Option<(HeadingLevel, usize, &str)> {
+    let hashes = trimmed.len() - trimmed.trim_start_matches('#').len();
+    if !(1..=3).contains(&hashes) {
+        return None;
+    }
+    let rest = trimmed[hashes..].trim_start();
+    HeadingLevel::ALL.into_iter().find_map(|level| {
+        let name = rest.strip_prefix(level.keyword())?.strip_prefix(':')?;
+        Some((level, hashes, name.trim()))
     })
 }
 
-fn parse_bracket_directive(line: &str, line_number: usize) -> Result<Directive, ParseError> {
+/// Recognise preprocessor lines: `[INCLUDE path]`, `[DEFINE name]`, `[END]` and
+/// `[CALL name args...]`. Returns `None` for anything else, which should then go through
+/// [`parse_line`].
+pub fn parse_preprocessor_line(
+    line: &str,
+    line_number: usize,
+) -> Result<Option<Preprocessor>, ParseError> {
+    if !line.trim().starts_with('[') {
+        return Ok(None);
+    }
+    let bracket = split_bracket(line, line_number)?;
+    let (tag, arg) = (bracket.tag, bracket.arg());
+    let error = |message: &str, part: &str| {
+        ParseError::new(line_number, line, message).with_span(Span::of(line, part))
+    };
+
+    match tag.to_uppercase().as_str() {
+        "INCLUDE" => {
+            if arg.is_empty() {
+                return Err(error("INCLUDE requires a file path", arg)
+                    .with_help("for example: [INCLUDE shared/setup.cm]"));
+            }
+            Ok(Some(Preprocessor::Include(arg.to_string())))
+        }
+        "DEFINE" => {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                return Err(error("DEFINE requires a single-word macro name", arg)
+                    .with_help("for example: [DEFINE open-terminal]"));
+            }
+            Ok(Some(Preprocessor::Define(arg.to_string())))
+        }
+        "END" => Ok(Some(Preprocessor::End)),
+        "CALL" => {
+            let mut args = split_args(arg).map_err(|e| error(&e, arg))?;
+            if args.is_empty() {
+                return Err(error("CALL requires a macro name", arg)
+                    .with_help("for example: [CALL open-terminal \"cargo test\"]"));
+            }
+            let name = args.remove(0);
+            Ok(Some(Preprocessor::Call { name, args }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Marker that opens and closes a multi-line `[TYPE]` block.
+pub const FENCE: &str = "```";
+
+/// Collect the body of a fenced `[TYPE]` block.
+///
+/// `arg` is the argument of the `[TYPE]` directive found at `lines[idx]`. The fence may open
+/// on the same line (`[TYPE] ```rust`) or on the line directly below a bare `[TYPE]`. Body
+/// lines are kept verbatim so indentation survives. Returns the text and the index of the
+/// first line after the closing fence, or `None` for an ordinary single-line `[TYPE]`.
+pub fn parse_fenced_type(
+    arg: &str,
+    lines: &[&str],
+    idx: usize,
+) -> Result<Option<(String, usize)>, ParseError> {
+    let fence_idx = if arg.starts_with(FENCE) {
+        idx
+    } else if arg.is_empty()
+        && lines
+            .get(idx + 1)
+            .is_some_and(|next| next.trim().starts_with(FENCE))
+    {
+        idx + 1
+    } else {
+        return Ok(None);
+    };
+
+    let body_start = fence_idx + 1;
+    let closing = lines[body_start..]
+        .iter()
+        .position(|l| l.trim() == FENCE)
+        .ok_or_else(|| {
+            let line = lines[fence_idx];
+            let fence = line.find(FENCE).unwrap_or(0);
+            ParseError::new(
+                fence_idx + 1,
+                line,
+                "Fenced [TYPE] block opened but never closed with '```'",
+            )
+            .with_span(Span {
+                start: fence,
+                end: fence + FENCE.len(),
+            })
+            .with_help("add a line containing only ``` where the typed text ends")
+        })?;
+
+    // Fenced code is typed verbatim, so escape anything that looks like a key token
+    let text = escape_literal(&lines[body_start..body_start + closing].join("\n"));
+    Ok(Some((text, body_start + closing + 1)))
+}
+
+/// The parts of a bracket line, all slices of the line so they can be turned into spans.
+pub(crate) struct Bracket<'a> {
+    /// The first word inside the brackets.
+    pub(crate) tag: &'a str,
+    /// The rest of the bracket contents.
+    pub(crate) inline: &'a str,
+    /// Whatever follows the closing bracket.
+    pub(crate) after: &'a str,
+}
+
+impl<'a> Bracket<'a> {
+    /// The argument: the text after the closing bracket, or the rest of the bracket contents
+    /// if nothing follows it.
+    pub(crate) fn arg(&self) -> &'a str {
+        if !self.after.is_empty() {
+            self.after
+        } else {
+            self.inline
+        }
+    }
+}
+
+/// A `key=value` setting written inside the brackets, as in `[TYPE speed=80] text`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attribute<'a> {
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+/// Attributes each directive accepts.
+fn allowed_attributes(tag: &str) -> &'static [&'static str] {
+    match tag {
+        "TYPE" => &["speed", "variance", "file", "lines", "region"],
+        "EXEC" => &["timeout"],
+        _ => &[],
+    }
+}
+
+pub(crate) fn split_bracket(line: &str, line_number: usize) -> Result<Bracket<'_>, ParseError> {
+    let trimmed = line.trim();
     // Find the closing bracket
-    let close_bracket: usize = line.find(']').ok_or_else(|| ParseError {
-        line_number,
-        line_content: line.to_string(),
-        message: "Missing closing bracket ']'".to_string(),
+    let close_bracket: usize = trimmed.find(']').ok_or_else(|| {
+        ParseError::new(line_number, line, "Missing closing bracket ']'")
+            .with_span(Span::of(line, &trimmed[trimmed.len()..]))
+            .with_help("close the directive name with ']', as in '[SAY] text'")
     })?;
 
-    let inside = &line[1..close_bracket];
-    let after = line[close_bracket + 1..].trim();
+    let inside = &trimmed[1..close_bracket];
+    let after = trimmed[close_bracket + 1..].trim();
 
     // Split inside brackets: tag is the first word, rest is inline arg
-    let (tag_str, inline_arg) = match inside.find(' ') {
+    let (tag, inline) = match inside.find(' ') {
         Some(pos) => (&inside[..pos], inside[pos + 1..].trim()),
-        None => (inside, ""),
+        None => (inside, &inside[inside.len()..]),
     };
-    let tag_upper = tag_str.to_uppercase();
+    Ok(Bracket { tag, inline, after })
+}
 
-    // Combine inline arg and after-bracket arg (prefer after-bracket if both present)
-    let arg = if !after.is_empty() { after } else { inline_arg };
+/// Split leading `key=value` words off the inside of the brackets, for the keys directive
+/// `tag` accepts. Any other word starts the inline argument, so `[EXEC no_proxy=1 curl x]`
+/// runs the whole command.
+pub(crate) fn split_attributes<'a>(tag: &str, inline: &'a str) -> (Vec<Attribute<'a>>, &'a str) {
+    let allowed = allowed_attributes(&tag.to_uppercase());
+    let mut attributes = Vec::new();
+    let mut rest = inline;
+    while let Some((key, value)) = leading_assignment(rest)
+        && allowed.contains(&key)
+    {
+        attributes.push(Attribute { key, value });
+        rest = rest[key.len() + 1 + value.len()..].trim_start();
+    }
+    (attributes, rest)
+}
+
+/// The lower-case `key=value` word at the start of `text`, if there is one.
+fn leading_assignment(text: &str) -> Option<(&str, &str)> {
+    let word_end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let (key, value) = text[..word_end].split_once('=')?;
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+    (is_key && !value.is_empty()).then_some((key, value))
+}
+
+fn parse_bracket_directive(line: &str, line_number: usize) -> Result<Directive, ParseError> {
+    let bracket = split_bracket(line, line_number)?;
+    let tag_str = bracket.tag;
+    let error = |message: String, part: &str| {
+        ParseError::new(line_number, line, message).with_span(Span::of(line, part))
+    };
+    // `[SAY:de]`: the language follows the directive name
+    let (tag_upper, lang) = match tag_str.split_once(':') {
+        Some((name, lang)) => (name.to_uppercase(), Some(lang)),
+        None => (tag_str.to_uppercase(), None),
+    };
+    if let Some(lang) = lang
+        && DIRECTIVE_NAMES.contains(&tag_upper.as_str())
+    {
+        if tag_upper != "SAY" && tag_upper != "NOTE" {
+            return Err(
+                error(format!("[{tag_upper}] does not take a language"), lang)
+                    .with_help("only [SAY] and [NOTE] are translated, as in [SAY:de]"),
+            );
+        }
+        if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(
+                error(format!("Invalid language code: '{lang}'"), lang).with_help(format!(
+                    "give a code such as 'de' or 'pt-br', as in [{tag_upper}:de]"
+                )),
+            );
+        }
+    }
+    let localized = |text: &str| {
+        lang.map(|lang| Localized {
+            lang: lang.to_lowercase(),
+            text: text.to_string(),
+        })
+    };
+
+    let (attributes, inline) = split_attributes(tag_str, bracket.inline);
+    let arg = if !bracket.after.is_empty() {
+        bracket.after
+    } else {
+        inline
+    };
+    for (i, attribute) in attributes.iter().enumerate() {
+        if attributes[..i].iter().any(|a| a.key == attribute.key) {
+            return Err(error(
+                format!("Attribute '{}' is given more than once", attribute.key),
+                attribute.key,
+            ));
+        }
+    }
+    // With text after the brackets a setting the directive does not take would be dropped,
+    // so it is most likely a misspelt one
+    let allowed = allowed_attributes(&tag_upper);
+    if !allowed.is_empty()
+        && !bracket.after.is_empty()
+        && let Some((key, _)) = leading_assignment(inline)
+    {
+        let help = match closest_match(key, allowed) {
+            Some(name) => format!("did you mean {name}?"),
+            None => format!("[{tag_upper}] accepts {}", allowed.join(", ")),
+        };
+        return Err(
+            error(format!("Unknown attribute '{key}' for [{tag_upper}]"), key).with_help(help),
+        );
+    }
+    let number = |key: &str| -> Result<Option<u64>, ParseError> {
+        let Some(attribute) = attributes.iter().find(|a| a.key == key) else {
+            return Ok(None);
+        };
+        attribute.value.parse().map(Some).map_err(|_| {
+            error(
+                format!("Invalid {key} value: '{}'", attribute.value),
+                attribute.value,
+            )
+            .with_help(format!("give a whole number, as in '{key}=50'"))
+        })
+    };
 
     match tag_upper.as_str() {
-        "SAY" => Ok(Directive::Say(arg.to_string())),
-        "TYPE" => Ok(Directive::Type(arg.to_string())),
+        "SAY" => Ok(match localized(arg) {
+            Some(localized) => Directive::SayIn(localized),
+            None => Directive::Say(arg.to_string()),
+        }),
+        "NOTE" => Ok(match localized(arg) {
+            Some(localized) => Directive::NoteIn(localized),
+            None => Directive::Note(arg.to_string()),
+        }),
+        "TYPE" => {
+            let attribute = |key: &str| attributes.iter().find(|a| a.key == key);
+            let source = match attribute("file") {
+                Some(file) => {
+                    if !arg.is_empty() {
+                        return Err(error("[TYPE file=...] takes no text".into(), arg)
+                            .with_help("the text comes from the file; drop it or drop file="));
+                    }
+                    if let (Some(_), Some(region)) = (attribute("lines"), attribute("region")) {
+                        return Err(error(
+                            "Give either lines or region, not both".into(),
+                            region.key,
+                        ));
+                    }
+                    let lines = attribute("lines")
+                        .map(|lines| {
+                            parse_line_range(lines.value).ok_or_else(|| {
+                                error(
+                                    format!("Invalid lines value: '{}'", lines.value),
+                                    lines.value,
+                                )
+                                .with_help("give a range of lines, as in 'lines=10-20'")
+                            })
+                        })
+                        .transpose()?;
+                    Some(TypeSource {
+                        path: file.value.to_string(),
+                        lines,
+                        region: attribute("region").map(|r| r.value.to_string()),
+                    })
+                }
+                None => {
+                    if let Some(orphan) = attribute("lines").or(attribute("region")) {
+                        return Err(error(
+                            format!("'{}' only applies to [TYPE file=...]", orphan.key),
+                            orphan.key,
+                        )
+                        .with_help("add file=path to type part of a file"));
+                    }
+                    None
+                }
+            };
+            let segments = parse_type_text(arg).map_err(|e| {
+                error(e.message, &arg[e.range]).with_help(format!(
+                    "valid keys are {}; write \\{{ for a literal brace",
+                    KEY_NAMES.join(", ")
+                ))
+            })?;
+            Ok(Directive::Type(TypeText {
+                text: arg.to_string(),
+                speed: number("speed")?,
+                variance: number("variance")?,
+                source,
+                segments,
+            }))
+        }
         "RUN" => Ok(Directive::Run),
         "PAUSE" => {
             if arg.is_empty() {
                 Ok(Directive::Pause(None))
             } else {
-                let secs: u64 = arg.parse().map_err(|_| ParseError {
-                    line_number,
-                    line_content: line.to_string(),
-                    message: format!("Invalid PAUSE duration: '{arg}'"),
+                let secs: u64 = arg.parse().map_err(|_| {
+                    error(format!("Invalid PAUSE duration: '{arg}'"), arg).with_help(
+                        "give a whole number of seconds, as in '[PAUSE 3]', or leave it \
+                         empty to wait for a keypress",
+                    )
                 })?;
                 Ok(Directive::Pause(Some(secs)))
             }
@@ -88,12 +524,14 @@ fn parse_bracket_directive(line: &str, line_number: usize) -> Result<Directive,
                 "next" => SlideAction::Next,
                 "prev" | "previous" => SlideAction::Prev,
                 other => {
-                    let n: u32 = other.parse().map_err(|_| ParseError {
-                        line_number,
-                        line_content: line.to_string(),
-                        message: format!(
-                            "Invalid SLIDE argument: '{arg}' (expected 'next', 'prev', or a number)"
-                        ),
+                    let n: u32 = other.parse().map_err(|_| {
+                        error(
+                            format!(
+                                "Invalid SLIDE argument: '{arg}' (expected 'next', 'prev', or a number)"
+                            ),
+                            arg,
+                        )
+                        .with_help("for example: [SLIDE next], [SLIDE prev] or [SLIDE 4]")
                     })?;
                     SlideAction::GoTo(n)
                 }
@@ -103,25 +541,59 @@ fn parse_bracket_directive(line: &str, line_number: usize) -> Result<Directive,
         "KEY" => Ok(Directive::Key(arg.to_string())),
         "CLEAR" => Ok(Directive::Clear),
         "WAIT" => {
-            let secs: u64 = arg.parse().map_err(|_| ParseError {
-                line_number,
-                line_content: line.to_string(),
-                message: format!("Invalid WAIT duration: '{arg}'"),
+            let secs: u64 = arg.parse().map_err(|_| {
+                error(format!("Invalid WAIT duration: '{arg}'"), arg)
+                    .with_help("give a whole number of seconds, as in '[WAIT 2]'")
             })?;
             Ok(Directive::Wait(secs))
         }
-        "EXEC" => Ok(Directive::Exec(arg.to_string())),
-        _ => Err(ParseError {
-            line_number,
-            line_content: line.to_string(),
-            message: format!("Unknown directive: [{tag_str}]"),
-        }),
+        "EXEC" => Ok(Directive::Exec(ExecCommand {
+            command: arg.to_string(),
+            timeout: number("timeout")?,
+        })),
+        "SPEED" => {
+            let ms: u64 = arg.parse().map_err(|_| {
+                error(format!("Invalid SPEED value: '{arg}'"), arg).with_help(
+                    "give milliseconds per keystroke for the rest of the section, as in \
+                     '[SPEED 80]'",
+                )
+            })?;
+            Ok(Directive::Speed(ms))
+        }
+        "LABEL" | "SKIP_TO" => {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                return Err(
+                    error(format!("{tag_upper} requires a single-word label"), arg)
+                        .with_help(format!("for example: [{tag_upper} backup-demo]")),
+                );
+            }
+            Ok(if tag_upper == "LABEL" {
+                Directive::Label(arg.to_string())
+            } else {
+                Directive::SkipTo(arg.to_string())
+            })
+        }
+        _ => {
+            let help = match closest_match(&tag_upper, DIRECTIVE_NAMES) {
+                Some(name) => format!("did you mean {name}?"),
+                None => format!("valid directives are {}", DIRECTIVE_NAMES.join(", ")),
+            };
+            Err(error(format!("Unknown directive: [{tag_str}]"), tag_str).with_help(help))
+        }
     }
 }
 
+/// `a-b` or a single line `a`, 1-based with `a <= b`.
+fn parse_line_range(value: &str) -> Option<(usize, usize)> {
+    let (start, end) = value.split_once('-').unwrap_or((value, value));
+    let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+    (start >= 1 && start <= end).then_some((start, end))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::keys::TypeSegment;
 
     #[test]
     fn test_parse_empty_line() {
@@ -146,6 +618,182 @@ mod tests {
         assert_eq!(parsed.directive, Directive::Type("cargo build".into()));
     }
 
+    #[test]
+    fn test_parse_type_keeps_key_tokens() {
+        let parsed = parse_line(r"[TYPE] Vec::wi{tab}\n", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Type(r"Vec::wi{tab}\n".into()));
+    }
+
+    #[test]
+    fn test_parse_type_bad_key_token() {
+        let err = parse_line("[TYPE] save{cmd+sss}", 4).unwrap_err();
+        assert!(err.message.contains("sss"), "{}", err.message);
+        assert_eq!(err.span, Some(Span { start: 11, end: 20 }));
+    }
+
+    #[test]
+    fn test_parse_type_attributes() {
+        let parsed = parse_line("[TYPE speed=80 variance=0] cargo build", 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::Type(TypeText {
+                speed: Some(80),
+                variance: Some(0),
+                ..TypeText::from("cargo build")
+            })
+        );
+        // Attributes may also precede an inline argument
+        let parsed = parse_line("[TYPE speed=5 ls -la]", 1).unwrap().unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::Type(TypeText {
+                speed: Some(5),
+                ..TypeText::from("ls -la")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_label_and_skip_to() {
+        let parsed = parse_line("[LABEL backup]", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Label("backup".into()));
+        let parsed = parse_line("[skip_to] backup", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::SkipTo("backup".into()));
+
+        let err = parse_line("[LABEL two words]", 1).unwrap_err();
+        assert_eq!(err.message, "LABEL requires a single-word label");
+        let err = parse_line("[SKIP_TO]", 1).unwrap_err();
+        assert_eq!(
+            err.help.as_deref(),
+            Some("for example: [SKIP_TO backup-demo]")
+        );
+    }
+
+    #[test]
+    fn test_parse_type_file() {
+        let parsed = parse_line("[TYPE file=src/main.rs lines=3-9 speed=20]", 1)
+            .unwrap()
+            .unwrap();
+        let Directive::Type(typed) = parsed.directive else {
+            panic!("expected [TYPE]");
+        };
+        assert_eq!(typed.speed, Some(20));
+        assert_eq!(
+            typed.source,
+            Some(TypeSource {
+                path: "src/main.rs".into(),
+                lines: Some((3, 9)),
+                region: None,
+            })
+        );
+
+        let parsed = parse_line("[TYPE file=lib.rs region=setup]", 1)
+            .unwrap()
+            .unwrap();
+        let Directive::Type(typed) = parsed.directive else {
+            panic!("expected [TYPE]");
+        };
+        assert_eq!(typed.source.unwrap().region.as_deref(), Some("setup"));
+        assert_eq!(parse_line_range("4"), Some((4, 4)));
+    }
+
+    #[test]
+    fn test_parse_type_file_errors() {
+        let cases = [
+            ("[TYPE file=a.rs] text", "[TYPE file=...] takes no text"),
+            (
+                "[TYPE file=a.rs lines=1-2 region=x]",
+                "Give either lines or region, not both",
+            ),
+            ("[TYPE file=a.rs lines=9-2]", "Invalid lines value: '9-2'"),
+            ("[TYPE file=a.rs lines=0]", "Invalid lines value: '0'"),
+            (
+                "[TYPE region=x] text",
+                "'region' only applies to [TYPE file=...]",
+            ),
+        ];
+        for (line, message) in cases {
+            let err = parse_line(line, 1).unwrap_err();
+            assert_eq!(err.message, message, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_parse_exec_timeout() {
+        let parsed = parse_line("[EXEC timeout=5] make test", 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::Exec(ExecCommand {
+                command: "make test".into(),
+                timeout: Some(5),
+            })
+        );
+        // Upper-case assignments are part of the command, not attributes
+        let parsed = parse_line("[EXEC FOO=1 ./run.sh]", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Exec("FOO=1 ./run.sh".into()));
+    }
+
+    #[test]
+    fn test_parse_attribute_errors() {
+        let err = parse_line("[TYPE sped=80] ls", 1).unwrap_err();
+        assert!(err.message.contains("Unknown attribute 'sped'"));
+        assert_eq!(err.help.as_deref(), Some("did you mean speed?"));
+        assert_eq!(err.span, Some(Span { start: 6, end: 10 }));
+
+        let err = parse_line("[EXEC tiemout=5] make", 1).unwrap_err();
+        assert_eq!(err.help.as_deref(), Some("did you mean timeout?"));
+
+        let err = parse_line("[TYPE speed=fast] ls", 1).unwrap_err();
+        assert!(err.message.contains("Invalid speed value"));
+        assert_eq!(err.span, Some(Span { start: 12, end: 16 }));
+
+        let err = parse_line("[EXEC timeout=1 timeout=2] ls", 1).unwrap_err();
+        assert!(err.message.contains("more than once"));
+    }
+
+    #[test]
+    fn test_parse_assignments_without_attributes() {
+        let cases = [
+            (
+                "[SAY x=1 is the answer]",
+                Directive::Say("x=1 is the answer".into()),
+            ),
+            (
+                "[EXEC no_proxy=1 curl x]",
+                Directive::Exec("no_proxy=1 curl x".into()),
+            ),
+            ("[FOCUS name=foo]", Directive::Focus("name=foo".into())),
+            ("[TYPE x=1]", Directive::Type("x=1".into())),
+        ];
+        for (line, directive) in cases {
+            let parsed = parse_line(line, 1).unwrap().unwrap();
+            assert_eq!(parsed.directive, directive, "{line}");
+        }
+        // A setting the directive takes is still read, and the rest is the argument
+        let parsed = parse_line("[EXEC timeout=5 no_proxy=1 curl x]", 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::Exec(ExecCommand {
+                command: "no_proxy=1 curl x".into(),
+                timeout: Some(5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_speed() {
+        let parsed = parse_line("[SPEED 80]", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Speed(80));
+        assert!(parse_line("[SPEED]", 1).is_err());
+        assert!(parse_line("[SPEED fast]", 1).is_err());
+    }
+
     #[test]
     fn test_parse_run() {
         let parsed = parse_line("[RUN]", 1).unwrap().unwrap();
@@ -217,17 +865,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_fenced_type_on_next_line() {
+        let lines = vec![
+            "[TYPE]",
+            "```rust",
+            "fn main() {",
+            "    println!();",
+            "}",
+            "```",
+        ];
+        let (text, next) = parse_fenced_type("", &lines, 0).unwrap().unwrap();
+        assert_eq!(text, "fn main() {\n    println!();\n}");
+        assert_eq!(next, 6);
+    }
+
+    #[test]
+    fn test_parse_fenced_type_on_same_line() {
+        let lines = vec!["[TYPE] ```", "a", "b", "```", "[RUN]"];
+        let (text, next) = parse_fenced_type("```", &lines, 0).unwrap().unwrap();
+        assert_eq!(text, "a\nb");
+        assert_eq!(next, 4);
+    }
+
+    #[test]
+    fn test_parse_fenced_type_is_verbatim() {
+        let lines = vec!["[TYPE] ```", r#"printf("{tab}\n");"#, "```"];
+        let (text, _) = parse_fenced_type("```", &lines, 0).unwrap().unwrap();
+        assert_eq!(
+            parse_type_text(&text).unwrap(),
+            vec![TypeSegment::Text(r#"printf("{tab}\n");"#.into())]
+        );
+    }
+
+    #[test]
+    fn test_parse_fenced_type_not_fenced() {
+        let lines = vec!["[TYPE] ls", "[RUN]"];
+        assert!(parse_fenced_type("ls", &lines, 0).unwrap().is_none());
+        let lines = vec!["[TYPE]", "[RUN]"];
+        assert!(parse_fenced_type("", &lines, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_fenced_type_unclosed() {
+        let lines = vec!["[SAY] hi", "[TYPE]", "```", "let x = 1;"];
+        let err = parse_fenced_type("", &lines, 1).unwrap_err();
+        assert_eq!(err.line_number, 3);
+        assert!(err.message.contains("never closed"));
+    }
+
+    #[test]
+    fn test_parse_include() {
+        assert_eq!(
+            parse_preprocessor_line("[INCLUDE setup.cm]", 1).unwrap(),
+            Some(Preprocessor::Include("setup.cm".into()))
+        );
+        assert_eq!(
+            parse_preprocessor_line("[include] shared/teardown.cm", 1).unwrap(),
+            Some(Preprocessor::Include("shared/teardown.cm".into()))
+        );
+        assert!(parse_preprocessor_line("[SAY] hi", 1).unwrap().is_none());
+        assert!(parse_preprocessor_line("[INCLUDE]", 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_macro_lines() {
+        assert_eq!(
+            parse_preprocessor_line("[DEFINE run-cmd]", 1).unwrap(),
+            Some(Preprocessor::Define("run-cmd".into()))
+        );
+        assert_eq!(
+            parse_preprocessor_line("[END]", 1).unwrap(),
+            Some(Preprocessor::End)
+        );
+        assert_eq!(
+            parse_preprocessor_line(r#"[CALL run-cmd "cargo test" -q]"#, 1).unwrap(),
+            Some(Preprocessor::Call {
+                name: "run-cmd".into(),
+                args: vec!["cargo test".into(), "-q".into()],
+            })
+        );
+        assert!(parse_preprocessor_line("[DEFINE two words]", 1).is_err());
+        assert!(parse_preprocessor_line("[CALL]", 1).is_err());
+    }
+
     #[test]
     fn test_parse_section() {
         let parsed = parse_line("## Section: Intro", 1).unwrap().unwrap();
         assert_eq!(parsed.directive, Directive::Section("Intro".into()));
     }
 
+    #[test]
+    fn test_parse_headings() {
+        let parsed = parse_line("# Chapter: Basics", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Chapter("Basics".into()));
+        let parsed = parse_line("  ###Step:  Install ", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Step("Install".into()));
+        // Other `#` lines are still comments
+        assert!(parse_line("# Chapters are optional", 1).unwrap().is_none());
+        assert!(parse_line("#### Step: too deep", 1).unwrap().is_none());
+
+        let err = parse_line("## Chapter: Basics", 1).unwrap_err();
+        assert_eq!(err.message, "Chapter headings start with #");
+        assert_eq!(err.help.as_deref(), Some("write '# Chapter: Basics'"));
+        assert_eq!(err.span, Some(Span { start: 0, end: 11 }));
+    }
+
     #[test]
     fn test_parse_unknown_directive() {
         let err = parse_line("[BOGUS]", 5).unwrap_err();
         assert!(err.to_string().contains("5"));
         assert!(err.to_string().contains("BOGUS"));
+        assert_eq!(err.span, Some(Span { start: 1, end: 6 }));
+        assert!(err.help.unwrap().contains("SAY, TYPE, RUN"));
+    }
+
+    #[test]
+    fn test_parse_unknown_directive_suggests_close_match() {
+        let err = parse_line("  [SLDIE] next", 1).unwrap_err();
+        assert_eq!(err.line_content, "  [SLDIE] next");
+        assert_eq!(err.span, Some(Span { start: 3, end: 8 }));
+        assert_eq!(err.help.as_deref(), Some("did you mean SLIDE?"));
+    }
+
+    #[test]
+    fn test_parse_error_spans_argument() {
+        let err = parse_line("[WAIT] soon", 1).unwrap_err();
+        assert_eq!(err.span, Some(Span { start: 7, end: 11 }));
+        let err = parse_line("[PAUSE forever]", 1).unwrap_err();
+        assert_eq!(err.span, Some(Span { start: 7, end: 14 }));
+        let err = parse_preprocessor_line("[INCLUDE]", 1).unwrap_err();
+        assert_eq!(err.span, Some(Span { start: 8, end: 8 }));
+    }
+
+    #[test]
+    fn test_span_of() {
+        let line = "[SAY] hello";
+        assert_eq!(Span::of(line, &line[6..]), Span { start: 6, end: 11 });
+        assert_eq!(Span::of(line, "SAY"), Span { start: 1, end: 4 });
+        assert_eq!(Span::of(line, "absent"), Span { start: 0, end: 11 });
     }
 
     #[test]
@@ -242,4 +1018,38 @@ mod tests {
         // trim() is applied to the argument
         assert_eq!(parsed.directive, Directive::Say("spaced out".into()));
     }
+
+    #[test]
+    fn test_parse_note() {
+        let parsed = parse_line("[NOTE] mention the flag", 1).unwrap().unwrap();
+        assert_eq!(parsed.directive, Directive::Note("mention the flag".into()));
+    }
+
+    #[test]
+    fn test_parse_localized() {
+        let parsed = parse_line("[say:DE] Hallo zusammen", 1).unwrap().unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::SayIn(Localized {
+                lang: "de".into(),
+                text: "Hallo zusammen".into()
+            })
+        );
+        let parsed = parse_line("[NOTE:pt-BR] devagar", 1).unwrap().unwrap();
+        assert_eq!(
+            parsed.directive,
+            Directive::NoteIn(Localized {
+                lang: "pt-br".into(),
+                text: "devagar".into()
+            })
+        );
+
+        let err = parse_line("[TYPE:de] ls", 1).unwrap_err();
+        assert_eq!(err.message, "[TYPE] does not take a language");
+        assert_eq!(err.span, Some(Span { start: 6, end: 8 }));
+        let err = parse_line("[SAY:] Hallo", 1).unwrap_err();
+        assert_eq!(err.message, "Invalid language code: ''");
+        let err = parse_line("[SAY:de_ch] Hallo", 1).unwrap_err();
+        assert_eq!(err.message, "Invalid language code: 'de_ch'");
+    }
 }
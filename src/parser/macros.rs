@@ -0,0 +1,455 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::lexer::ParseError;
+use super::types::{ParsedLine, SourceLocation};
+use super::vars::substitutable_text;
+use crate::diagnostics::closest_match;
+
+/// A `[DEFINE name] ... [END]` block.
+#[derive(Debug, Clone)]
+pub struct MacroDef {
+    pub name: String,
+    /// Where the `[DEFINE]` line is.
+    pub location: SourceLocation,
+    pub body: Vec<Item>,
+}
+
+/// A `[CALL name args...]` line awaiting expansion.
+#[derive(Debug, Clone)]
+pub struct MacroCall {
+    pub name: String,
+    pub args: Vec<String>,
+    pub location: SourceLocation,
+    pub line_content: String,
+}
+
+/// A parsed line before macro expansion.
+#[derive(Debug, Clone)]
+pub enum Item {
+    Line(ParsedLine),
+    Call(MacroCall),
+}
+
+/// Register `def`, rejecting a second definition under the same name. Seeing the same
+/// definition again (a fragment included twice) is not an error.
+pub fn define(
+    macros: &mut BTreeMap<String, MacroDef>,
+    def: MacroDef,
+    line_content: &str,
+) -> Result<(), ParseError> {
+    if let Some(existing) = macros.get(&def.name) {
+        if existing.location == def.location {
+            return Ok(());
+        }
+        return Err(ParseError::new(
+            def.location.line_number,
+            line_content,
+            format!(
+                "Macro '{}' is already defined at {}",
+                def.name, existing.location
+            ),
+        )
+        .in_file(def.location.file.as_deref()));
+    }
+    macros.insert(def.name.clone(), def);
+    Ok(())
+}
+
+/// Expand every `[CALL]` in `items` into the lines of the macro it names.
+///
+/// Returns the flattened lines and the number of calls expanded. Expanded lines keep the
+/// line number of their definition and record the outermost `[CALL]` in `expanded_from`.
+/// Calls that cannot be expanded are dropped and reported in `errors`, as are recursive
+/// macros whether or not they are called.
+pub fn expand(
+    items: Vec<Item>,
+    macros: &BTreeMap<String, MacroDef>,
+    errors: &mut Vec<ParseError>,
+) -> (Vec<ParsedLine>, usize) {
+    check_recursion(macros, errors);
+    let mut expander = Expander {
+        macros,
+        stack: Vec::new(),
+        out: Vec::new(),
+        expanded: 0,
+        errors,
+    };
+    expander.expand_items(&items, &[], None);
+    (expander.out, expander.expanded)
+}
+
+struct Expander<'a> {
+    macros: &'a BTreeMap<String, MacroDef>,
+    /// Names of the macros currently being expanded, outermost first.
+    stack: Vec<String>,
+    out: Vec<ParsedLine>,
+    expanded: usize,
+    errors: &'a mut Vec<ParseError>,
+}
+
+impl Expander<'_> {
+    fn expand_items(&mut self, items: &[Item], args: &[String], call_site: Option<&MacroCall>) {
+        for item in items {
+            match item {
+                Item::Line(line) => {
+                    let mut line = line.clone();
+                    if let Some(call) = call_site {
+                        if let Some(text) = substitutable_text(&mut line.directive) {
+                            match substitute_args(text, args) {
+                                Ok(substituted) => *text = substituted,
+                                Err(message) => {
+                                    self.errors.push(call_error(call, message));
+                                    continue;
+                                }
+                            }
+                        }
+                        line.expanded_from = Some(call.location.clone());
+                    }
+                    self.out.push(line);
+                }
+                Item::Call(call) => {
+                    let mut call = call.clone();
+                    if let Some(outer) = call_site {
+                        let substituted: Result<Vec<String>, String> = call
+                            .args
+                            .iter()
+                            .map(|arg| substitute_args(arg, args))
+                            .collect();
+                        match substituted {
+                            Ok(substituted) => call.args = substituted,
+                            Err(message) => {
+                                self.errors.push(call_error(outer, message));
+                                continue;
+                            }
+                        }
+                    }
+                    self.expand_call(&call, call_site);
+                }
+            }
+        }
+    }
+
+    fn expand_call(&mut self, call: &MacroCall, outer_call: Option<&MacroCall>) {
+        let Some(def) = self.macros.get(&call.name) else {
+            let message = format!("Unknown macro '{}'", call.name);
+            let names: Vec<&str> = self.macros.keys().map(String::as_str).collect();
+            let mut error = call_error(call, message).at(&call.name);
+            if let Some(name) = closest_match(&call.name, &names) {
+                error = error.with_help(format!("did you mean '{name}'?"));
+            }
+            self.errors.push(error);
+            return;
+        };
+        if self.stack.contains(&call.name) {
+            // Already reported by check_recursion
+            return;
+        }
+
+        self.expanded += 1;
+        self.stack.push(call.name.clone());
+        // Lines are attributed to the outermost call, which is where they run
+        let site = outer_call.unwrap_or(call);
+        self.expand_items(&def.body, &call.args, Some(site));
+        self.stack.pop();
+    }
+}
+
+/// Report each cycle of `[CALL]`s between definitions at the call inside a definition that
+/// closes it.
+fn check_recursion(macros: &BTreeMap<String, MacroDef>, errors: &mut Vec<ParseError>) {
+    /// Walk the calls in `name`'s body depth first. `stack` holds the macros being walked;
+    /// `done` those whose calls have all been followed.
+    fn visit<'a>(
+        name: &'a str,
+        macros: &'a BTreeMap<String, MacroDef>,
+        stack: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        stack.push(name);
+        for item in &macros[name].body {
+            let Item::Call(call) = item else {
+                continue;
+            };
+            let Some((callee, _)) = macros.get_key_value(&call.name) else {
+                continue;
+            };
+            if let Some(start) = stack.iter().position(|name| name == callee) {
+                let chain = [&stack[start..], &[callee.as_str()]].concat();
+                let message = format!("Recursive macro call: {}", chain.join(" -> "));
+                errors.push(call_error(call, message).at(&call.name));
+            } else if !done.contains(callee.as_str()) {
+                visit(callee, macros, stack, done, errors);
+            }
+        }
+        stack.pop();
+        done.insert(name);
+    }
+
+    let mut done = HashSet::new();
+    for name in macros.keys() {
+        if !done.contains(name.as_str()) {
+            visit(name, macros, &mut Vec::new(), &mut done, errors);
+        }
+    }
+}
+
+fn call_error(call: &MacroCall, message: String) -> ParseError {
+    ParseError::new(call.location.line_number, &call.line_content, message)
+        .in_file(call.location.file.as_deref())
+}
+
+/// Replace positional references `${1}`, `${2}`, ... with call arguments. Named references
+/// and `$${` escapes are left for variable substitution.
+fn substitute_args(text: &str, args: &[String]) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find("${") {
+        let after = &rest[pos + 2..];
+        let end = after.find('}');
+        let index = end.and_then(|end| after[..end].trim().parse::<usize>().ok());
+
+        match (index, end) {
+            (Some(n), Some(end)) if !rest[..pos].ends_with('$') => {
+                let value = n.checked_sub(1).and_then(|i| args.get(i)).ok_or_else(|| {
+                    format!(
+                        "Macro argument ${{{n}}} is not provided ({} argument(s) given)",
+                        args.len()
+                    )
+                })?;
+                out.push_str(&rest[..pos]);
+                out.push_str(value);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..pos + 2]);
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Split `[CALL]` arguments on whitespace. Double quotes group words, and `\"` inside quotes
+/// is a literal quote.
+pub fn split_args(input: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(args);
+        };
+
+        let mut arg = String::new();
+        if first == '"' {
+            loop {
+                match chars.next() {
+                    Some('\\') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        arg.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => arg.push(c),
+                    None => return Err("Unterminated quoted argument".to_string()),
+                }
+            }
+        } else {
+            arg.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lexer::Span;
+    use crate::parser::types::Directive;
+
+    fn loc(line_number: usize) -> SourceLocation {
+        SourceLocation {
+            file: None,
+            line_number,
+        }
+    }
+
+    fn call(name: &str, args: &[&str], line_number: usize) -> Item {
+        Item::Call(MacroCall {
+            name: name.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            location: loc(line_number),
+            line_content: format!("[CALL {name}]"),
+        })
+    }
+
+    fn line(line_number: usize, directive: Directive) -> Item {
+        Item::Line(ParsedLine::new(line_number, directive))
+    }
+
+    fn expand_ok(
+        items: Vec<Item>,
+        macros: &BTreeMap<String, MacroDef>,
+    ) -> (Vec<ParsedLine>, usize) {
+        let mut errors = Vec::new();
+        let result = expand(items, macros, &mut errors);
+        assert!(errors.is_empty(), "{errors:?}");
+        result
+    }
+
+    fn expand_err(items: Vec<Item>, macros: &BTreeMap<String, MacroDef>) -> ParseError {
+        let mut errors = Vec::new();
+        expand(items, macros, &mut errors);
+        errors
+            .into_iter()
+            .next()
+            .expect("expected an expansion error")
+    }
+
+    fn macros(defs: Vec<(&str, usize, Vec<Item>)>) -> BTreeMap<String, MacroDef> {
+        defs.into_iter()
+            .map(|(name, line_number, body)| {
+                let def = MacroDef {
+                    name: name.to_string(),
+                    location: loc(line_number),
+                    body,
+                };
+                (name.to_string(), def)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"run  "cargo test" --all "say \"hi\"""#).unwrap(),
+            vec!["run", "cargo test", "--all", "say \"hi\""]
+        );
+        assert!(split_args("").unwrap().is_empty());
+        assert!(split_args(r#""open"#).is_err());
+    }
+
+    #[test]
+    fn test_substitute_args() {
+        let args = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(substitute_args("${2}-${1}", &args).unwrap(), "b-a");
+        assert_eq!(
+            substitute_args("${name} $${1} ${1}", &args).unwrap(),
+            "${name} $${1} a"
+        );
+        assert!(substitute_args("${3}", &args).is_err());
+    }
+
+    #[test]
+    fn test_expand_records_both_locations() {
+        let macros = macros(vec![(
+            "run",
+            1,
+            vec![
+                line(2, Directive::Type("${1}".into())),
+                line(3, Directive::Run),
+            ],
+        )]);
+        let items = vec![call("run", &["ls"], 10)];
+        let (lines, expanded) = expand_ok(items, &macros);
+        assert_eq!(expanded, 1);
+        assert_eq!(lines.len(), 2);
+        // Key presses are split out once the script is finished; compare the text
+        assert_eq!(lines[0].directive.to_string(), "[TYPE] ls");
+        assert_eq!(lines[0].line_number, 2);
+        assert_eq!(lines[0].expanded_from, Some(loc(10)));
+    }
+
+    #[test]
+    fn test_expand_nested_uses_outer_args() {
+        let macros = macros(vec![
+            ("inner", 1, vec![line(2, Directive::Type("${1}".into()))]),
+            ("outer", 4, vec![call("inner", &["x ${1}"], 5)]),
+        ]);
+        let (lines, expanded) = expand_ok(vec![call("outer", &["y"], 9)], &macros);
+        assert_eq!(expanded, 2);
+        assert_eq!(lines[0].directive.to_string(), "[TYPE] x y");
+        assert_eq!(lines[0].expanded_from, Some(loc(9)));
+    }
+
+    #[test]
+    fn test_expand_rejects_recursion() {
+        let macros = macros(vec![
+            ("a", 1, vec![call("b", &[], 2)]),
+            ("b", 4, vec![call("a", &[], 5)]),
+        ]);
+        let err = expand_err(vec![call("a", &[], 9)], &macros);
+        assert!(err.message.contains("a -> b -> a"), "{err}");
+        assert_eq!(err.line_number, 5);
+    }
+
+    #[test]
+    fn test_expand_rejects_recursion_never_called() {
+        let mut errors = Vec::new();
+        let direct = macros(vec![("a", 1, vec![call("a", &[], 2)])]);
+        let (_, expanded) = expand(Vec::new(), &direct, &mut errors);
+        assert_eq!(expanded, 0);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("a -> a"), "{}", errors[0]);
+        assert_eq!(errors[0].line_number, 2);
+
+        let mut errors = Vec::new();
+        let mutual = macros(vec![
+            ("a", 1, vec![call("b", &[], 2)]),
+            ("b", 4, vec![line(5, Directive::Run), call("c", &[], 6)]),
+            ("c", 8, vec![call("a", &[], 9)]),
+            ("d", 11, vec![call("a", &[], 12)]),
+        ]);
+        expand(Vec::new(), &mutual, &mut errors);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(
+            errors[0].message.contains("a -> b -> c -> a"),
+            "{}",
+            errors[0]
+        );
+        assert_eq!(errors[0].line_number, 9);
+    }
+
+    #[test]
+    fn test_expand_unknown_macro() {
+        let items = vec![
+            call("nope", &[], 3),
+            line(4, Directive::Run),
+            call("other", &[], 5),
+        ];
+        let mut errors = Vec::new();
+        let (lines, _) = expand(items, &BTreeMap::new(), &mut errors);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line_number, 3);
+        assert!(errors[0].message.contains("nope"));
+        assert_eq!(errors[0].span, Some(Span { start: 6, end: 10 }));
+    }
+
+    #[test]
+    fn test_define_rejects_duplicate() {
+        let mut map = macros(vec![("a", 1, vec![])]);
+        let dup = MacroDef {
+            name: "a".into(),
+            location: loc(7),
+            body: vec![],
+        };
+        let err = define(&mut map, dup, "[DEFINE a]").unwrap_err();
+        assert_eq!(err.line_number, 7);
+        assert!(err.message.contains("line 1"));
+
+        let same = MacroDef {
+            name: "a".into(),
+            location: loc(1),
+            body: vec![],
+        };
+        assert!(define(&mut map, same, "[DEFINE a]").is_ok());
+    }
+}
@@ -1,31 +1,448 @@
+pub mod cst;
 pub mod front_matter;
+pub mod keys;
 pub mod lexer;
+pub mod macros;
+pub mod snippets;
 pub mod types;
+pub mod vars;
 
-use lexer::ParseError;
-use types::Script;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use lexer::{ParseError, Preprocessor};
+use macros::{Item, MacroCall, MacroDef};
+use types::{Directive, FrontMatter, Script, SourceLocation, TypeText};
+
+/// Settings supplied by the caller rather than the script itself.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Variables that take precedence over the front matter's `vars:` (from `--var`).
+    pub vars: BTreeMap<String, String>,
+    /// Treat warnings, such as unknown front matter keys, as errors (from `--strict`).
+    pub strict: bool,
+}
 
 pub fn parse_script(input: &str) -> Result<Script, ParseError> {
+    parse_script_all(input).map_err(first_error)
+}
+
+/// Like [`parse_script`], but keeps going after an error and returns all of them, sorted by
+/// file and line.
+pub fn parse_script_all(input: &str) -> Result<Script, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let source = parse_source(input, None, &mut Vec::new(), &mut errors);
+    let script = finish(source, &ParseOptions::default(), &mut errors);
+    into_result(script, errors)
+}
+
+/// Parse `input`, which was read from `path`.
+///
+/// `[INCLUDE]` paths are resolved relative to the directory containing `path`, and every
+/// parsed line and error records the file it came from.
+pub fn parse_script_at(
+    input: &str,
+    path: &Path,
+    options: &ParseOptions,
+) -> Result<Script, ParseError> {
+    parse_script_at_all(input, path, options).map_err(first_error)
+}
+
+/// Like [`parse_script_at`], but returns every error rather than just the first.
+pub fn parse_script_at_all(
+    input: &str,
+    path: &Path,
+    options: &ParseOptions,
+) -> Result<Script, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let mut stack: Vec<PathBuf> = path.canonicalize().into_iter().collect();
+    let source = parse_source(input, Some(path), &mut stack, &mut errors);
+    let script = finish(source, options, &mut errors);
+    into_result(script, errors)
+}
+
+fn into_result(script: Script, mut errors: Vec<ParseError>) -> Result<Script, Vec<ParseError>> {
+    if errors.is_empty() {
+        return Ok(script);
+    }
+    errors.sort_by(|a, b| (&a.file, a.line_number).cmp(&(&b.file, b.line_number)));
+    // A faulty macro body is reported once per call; keep one copy of each
+    errors.dedup_by(|a, b| {
+        a.file == b.file && a.line_number == b.line_number && a.message == b.message
+    });
+    Err(errors)
+}
+
+fn first_error(mut errors: Vec<ParseError>) -> ParseError {
+    errors.remove(0)
+}
+
+/// One file of the include tree (with its own includes spliced in), before macro
+/// expansion.
+struct Source {
+    front_matter: FrontMatter,
+    items: Vec<Item>,
+    macros: BTreeMap<String, MacroDef>,
+    includes: Vec<PathBuf>,
+    warnings: Vec<ParseError>,
+    /// Some front matter variables were invalid and left out.
+    vars_failed: bool,
+}
+
+/// Apply the whole-script passes that run once the include tree is assembled: macro
+/// expansion, variable substitution, splitting `[TYPE]` text into key presses, then
+/// resolving `[FOCUS]` app aliases.
+fn finish(source: Source, options: &ParseOptions, errors: &mut Vec<ParseError>) -> Script {
+    let (mut lines, macros_expanded) = macros::expand(source.items, &source.macros, errors);
+
+    let mut front_matter = source.front_matter;
+    front_matter.vars.extend(options.vars.clone());
+    if source.vars_failed {
+        // A variable set by an invalid entry is already reported there
+        vars::substitute_lines(&mut lines, &front_matter.vars, &mut Vec::new());
+    } else {
+        vars::substitute_lines(&mut lines, &front_matter.vars, errors);
+    }
+    segment_type_text(&mut lines, errors);
+    check_labels(&lines, errors);
+
+    for line in &mut lines {
+        if let Directive::Focus(app) = &mut line.directive
+            && let Some(name) = front_matter.apps.get(app.as_str())
+        {
+            *app = name.clone();
+        }
+    }
+
+    let mut warnings = source.warnings;
+    if options.strict {
+        errors.append(&mut warnings);
+    }
+
+    Script {
+        front_matter,
+        lines,
+        includes: source.includes,
+        macros_defined: source.macros.len(),
+        macros_expanded,
+        warnings,
+    }
+}
+
+/// Split the final text of each `[TYPE]`, after fences, files and variables are filled in,
+/// into the runs and key presses that executors type.
+fn segment_type_text(lines: &mut [types::ParsedLine], errors: &mut Vec<ParseError>) {
+    for line in lines {
+        let Directive::Type(typed) = &mut line.directive else {
+            continue;
+        };
+        match keys::parse_type_text(&typed.text) {
+            Ok(segments) => typed.segments = segments,
+            Err(e) => errors.push(
+                ParseError::new(line.line_number, &line.line_content, e.message)
+                    .at(&typed.text[e.range])
+                    .in_file(line.file.as_deref()),
+            ),
+        }
+    }
+}
+
+/// Report labels defined twice, and `[SKIP_TO]`s naming a label that does not exist.
+fn check_labels(lines: &[types::ParsedLine], errors: &mut Vec<ParseError>) {
+    let error = |line: &types::ParsedLine, message: String| {
+        ParseError::new(line.line_number, &line.line_content, message).in_file(line.file.as_deref())
+    };
+    let mut labels: BTreeMap<&str, &types::ParsedLine> = BTreeMap::new();
+    for line in lines {
+        if let Directive::Label(name) = &line.directive {
+            if let Some(first) = labels.get(name.as_str()) {
+                errors.push(
+                    error(line, format!("Label '{name}' is already defined"))
+                        .at(name)
+                        .with_help(format!(
+                            "the first [LABEL {name}] is at {}",
+                            first.location()
+                        )),
+                );
+            } else {
+                labels.insert(name, line);
+            }
+        }
+    }
+
+    let names: Vec<&str> = labels.keys().copied().collect();
+    for line in lines {
+        if let Directive::SkipTo(name) = &line.directive
+            && !labels.contains_key(name.as_str())
+        {
+            let help = match crate::diagnostics::closest_match(name, &names) {
+                Some(label) => format!("did you mean {label}?"),
+                None => format!("add [LABEL {name}] where the branch should land"),
+            };
+            errors.push(
+                error(line, format!("Unknown label '{name}'"))
+                    .at(name)
+                    .with_help(help),
+            );
+        }
+    }
+}
+
+/// Parse one file of the include tree, recovering from errors at line boundaries. `stack`
+/// holds the canonical paths of the files currently being parsed, outermost first, so that
+/// include cycles can be reported.
+fn parse_source(
+    input: &str,
+    file: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<ParseError>,
+) -> Source {
+    let mut file_errors = Vec::new();
+    let mut source = parse_lines(input, file, stack, &mut file_errors);
+    errors.extend(file_errors.into_iter().map(|e| e.in_file(file)));
+    source.warnings = std::mem::take(&mut source.warnings)
+        .into_iter()
+        .map(|w| w.in_file(file))
+        .collect();
+    source
+}
+
+fn parse_lines(
+    input: &str,
+    file: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<ParseError>,
+) -> Source {
     let lines: Vec<&str> = input.lines().collect();
-    let (front_matter, content_start) = front_matter::extract_front_matter(&lines)?;
+    let front_matter::ParsedFrontMatter {
+        front_matter,
+        content_start,
+        errors: front_matter_errors,
+        warnings,
+        vars_failed,
+    } = front_matter::parse_front_matter(&lines);
+    errors.extend(front_matter_errors);
+    let location = |idx: usize| SourceLocation {
+        file: file.map(Path::to_path_buf),
+        line_number: idx + 1,
+    };
+
+    let mut items = Vec::new();
+    let mut macros = BTreeMap::new();
+    let mut includes = Vec::new();
+    // The `[DEFINE]` being collected, with the index of its line
+    let mut defining: Option<(MacroDef, usize)> = None;
+    let mut idx = content_start;
+    while idx < lines.len() {
+        let target = match &mut defining {
+            Some((def, _)) => &mut def.body,
+            None => &mut items,
+        };
 
-    let mut parsed_lines = Vec::new();
-    for (idx, line) in lines[content_start..].iter().enumerate() {
-        let line_number = content_start + idx + 1;
-        if let Some(parsed) = lexer::parse_line(line, line_number)? {
-            parsed_lines.push(parsed);
+        let preprocessor = match lexer::parse_preprocessor_line(lines[idx], idx + 1) {
+            Ok(preprocessor) => preprocessor,
+            Err(e) => {
+                errors.push(e);
+                idx += 1;
+                continue;
+            }
+        };
+        if let Some(preprocessor) = preprocessor {
+            match preprocessor {
+                Preprocessor::Include(path) => {
+                    let Some(included) =
+                        load_include(&path, file, idx + 1, lines[idx], stack, errors)
+                    else {
+                        idx += 1;
+                        continue;
+                    };
+                    target.extend(included.items);
+                    for def in included.macros.into_values() {
+                        let line_content = format!("[DEFINE {}]", def.name);
+                        if let Err(e) = macros::define(&mut macros, def, &line_content) {
+                            errors.push(e);
+                        }
+                    }
+                    for path in included.includes {
+                        if !includes.contains(&path) {
+                            includes.push(path);
+                        }
+                    }
+                }
+                Preprocessor::Define(name) => {
+                    if defining.is_some() {
+                        errors.push(
+                            ParseError::new(
+                                idx + 1,
+                                lines[idx],
+                                "Macro definitions cannot be nested (missing [END]?)",
+                            )
+                            .with_help("close the previous [DEFINE] with [END] first"),
+                        );
+                    } else {
+                        let def = MacroDef {
+                            name,
+                            location: location(idx),
+                            body: Vec::new(),
+                        };
+                        defining = Some((def, idx));
+                    }
+                }
+                Preprocessor::End => match defining.take() {
+                    Some((def, define_idx)) => {
+                        if let Err(e) = macros::define(&mut macros, def, lines[define_idx]) {
+                            errors.push(e);
+                        }
+                    }
+                    None => errors.push(ParseError::new(
+                        idx + 1,
+                        lines[idx],
+                        "[END] without a matching [DEFINE]",
+                    )),
+                },
+                Preprocessor::Call { name, args } => {
+                    target.push(Item::Call(MacroCall {
+                        name,
+                        args,
+                        location: location(idx),
+                        line_content: lines[idx].to_string(),
+                    }));
+                }
+            }
+            idx += 1;
+            continue;
         }
+
+        let mut parsed = match lexer::parse_line(lines[idx], idx + 1) {
+            Ok(Some(parsed)) => parsed,
+            Ok(None) => {
+                idx += 1;
+                continue;
+            }
+            Err(e) => {
+                errors.push(e);
+                idx += 1;
+                continue;
+            }
+        };
+
+        idx = match &parsed.directive {
+            Directive::Type(typed) if let Some(source) = &typed.source => {
+                let base = file.and_then(Path::parent).unwrap_or(Path::new(""));
+                match snippets::read_source(source, base) {
+                    Ok(text) => {
+                        parsed.directive = Directive::Type(TypeText {
+                            text: keys::escape_literal(&text),
+                            ..typed.clone()
+                        });
+                    }
+                    Err(message) => {
+                        errors.push(ParseError::new(idx + 1, lines[idx], message).at(&source.path))
+                    }
+                }
+                idx + 1
+            }
+            Directive::Type(typed) => match lexer::parse_fenced_type(&typed.text, &lines, idx) {
+                Ok(Some((text, next))) => {
+                    parsed.directive = Directive::Type(TypeText {
+                        text,
+                        ..typed.clone()
+                    });
+                    next
+                }
+                Ok(None) => idx + 1,
+                Err(e) => {
+                    // An unclosed fence swallows the rest of the file
+                    errors.push(e);
+                    lines.len()
+                }
+            },
+            _ => idx + 1,
+        };
+        parsed.file = file.map(Path::to_path_buf);
+        target.push(Item::Line(parsed));
+    }
+
+    if let Some((_, define_idx)) = defining {
+        errors.push(
+            ParseError::new(
+                define_idx + 1,
+                lines[define_idx],
+                "[DEFINE] block is never closed with [END]",
+            )
+            .with_help("add [END] after the last line of the macro"),
+        );
     }
 
-    Ok(Script {
+    Source {
         front_matter,
-        lines: parsed_lines,
-    })
+        items,
+        macros,
+        includes,
+        warnings,
+        vars_failed,
+    }
+}
+
+/// Parse the script named by an `[INCLUDE]` on `line_number` of `from`. Front matter in the
+/// included file is ignored; settings always come from the top-level script. Returns `None`
+/// (after recording the error) if the file cannot be read or would form a cycle.
+fn load_include(
+    target: &str,
+    from: Option<&Path>,
+    line_number: usize,
+    line: &str,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<ParseError>,
+) -> Option<Source> {
+    let base = from.and_then(Path::parent).unwrap_or(Path::new(""));
+    let path = base.join(target);
+    let mut error = |message: String| {
+        errors.push(ParseError::new(line_number, line, message).at(target));
+    };
+
+    let canonical = match path.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(e) => {
+            error(format!(
+                "Cannot read included file '{}': {e}",
+                path.display()
+            ));
+            return None;
+        }
+    };
+    if stack.contains(&canonical) {
+        let chain: Vec<String> = stack
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|p| p.display().to_string())
+            .collect();
+        error(format!("Include cycle detected: {}", chain.join(" -> ")));
+        return None;
+    }
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            error(format!(
+                "Cannot read included file '{}': {e}",
+                path.display()
+            ));
+            return None;
+        }
+    };
+
+    stack.push(canonical);
+    let mut included = parse_source(&content, Some(&path), stack, errors);
+    stack.pop();
+
+    included.includes.insert(0, path);
+    Some(included)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TempDir;
     use types::FrontMatter;
 
     #[test]
@@ -59,6 +476,440 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_fenced_type_block() {
+        let input = "\
+[FOCUS] Terminal
+[TYPE]
+```rust
+fn main() {
+    # not a comment inside a fence
+}
+```
+[KEY] cmd+s
+";
+        let script = parse_script(input).unwrap();
+        assert_eq!(script.lines.len(), 3);
+        assert_eq!(
+            script.lines[1].directive,
+            Directive::Type("fn main() {\n    # not a comment inside a fence\n}".into())
+        );
+        assert_eq!(script.lines[1].line_number, 2);
+        assert_eq!(script.lines[2].line_number, 8);
+    }
+
+    #[test]
+    fn test_parse_unclosed_fence_reports_opening_line() {
+        let input = "[SAY] hi\n\n[TYPE] ```\nlet x = 1;\n[RUN]\n";
+        let err = parse_script(input).unwrap_err();
+        assert_eq!(err.line_number, 3);
+    }
+
+    #[test]
+    fn test_parse_include_splices_lines() {
+        let main = "[SAY] start\n[INCLUDE parts/setup.cm]\n[SAY] end\n";
+        let dir = TempDir::with_files(
+            "parser-include",
+            &[
+                ("main.cm", main),
+                ("parts/setup.cm", "# shared\n[FOCUS] Terminal\n[CLEAR]\n"),
+            ],
+        );
+        let script = parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap();
+        assert_eq!(script.lines.len(), 4);
+        assert_eq!(
+            script.lines[1].directive,
+            Directive::Focus("Terminal".into())
+        );
+        assert_eq!(script.lines[1].line_number, 2);
+        assert_eq!(
+            script.lines[1].file.as_deref(),
+            Some(dir.join("parts/setup.cm").as_path())
+        );
+        assert_eq!(
+            script.lines[3].file.as_deref(),
+            Some(dir.join("main.cm").as_path())
+        );
+        assert_eq!(script.includes, vec![dir.join("parts/setup.cm")]);
+    }
+
+    #[test]
+    fn test_parse_include_error_names_included_file() {
+        let main = "[INCLUDE bad.cm]\n";
+        let dir = TempDir::with_files("parser-include-error", &[("bad.cm", "[SAY] ok\n[BOGUS]\n")]);
+        let err =
+            parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.line_number, 2);
+        assert_eq!(err.file.as_deref(), Some(dir.join("bad.cm").as_path()));
+        assert!(err.to_string().contains("bad.cm"));
+    }
+
+    #[test]
+    fn test_parse_include_missing_file() {
+        let main = "[SAY] hi\n[INCLUDE nope.cm]\n";
+        let dir = TempDir::with_files("parser-include-missing", &[("main.cm", main)]);
+        let err =
+            parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap_err();
+        assert_eq!(err.line_number, 2);
+        assert!(err.message.contains("nope.cm"));
+    }
+
+    #[test]
+    fn test_parse_type_file() {
+        let main = "[TYPE file=src/lib.rs region=add]\n[TYPE file=src/lib.rs lines=1-2]\n";
+        let lib = "// cm:start add\npub fn add(a: u8) -> u8 {\n    a + ${one}\n}\n// cm:end\n";
+        let dir = TempDir::with_files(
+            "parser-type-file",
+            &[("main.cm", main), ("src/lib.rs", lib)],
+        );
+        let script = parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap();
+
+        let Directive::Type(typed) = &script.lines[0].directive else {
+            panic!("expected [TYPE]");
+        };
+        // Typed verbatim: no variable substitution, and braces are escaped
+        assert_eq!(
+            keys::unescape_literal(&typed.text),
+            "pub fn add(a: u8) -> u8 {\n    a + ${one}\n}"
+        );
+        assert_eq!(
+            typed.source.as_ref().unwrap().region.as_deref(),
+            Some("add")
+        );
+        let Directive::Type(typed) = &script.lines[1].directive else {
+            panic!("expected [TYPE]");
+        };
+        // The region marker on line 1 is left out
+        assert_eq!(typed.text, "pub fn add(a: u8) -> u8 {");
+    }
+
+    #[test]
+    fn test_parse_type_file_errors() {
+        let main = "[TYPE file=missing.rs]\n[TYPE file=lib.rs region=nope]\n[SAY] ok\n";
+        let dir = TempDir::with_files(
+            "parser-type-file-errors",
+            &[("main.cm", main), ("lib.rs", "x\n")],
+        );
+        let errors =
+            parse_script_at_all(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[0].message.starts_with("Cannot read file"),
+            "{}",
+            errors[0].message
+        );
+        assert_eq!(errors[0].span.unwrap().start, 11);
+        assert_eq!(errors[1].line_number, 2);
+        assert_eq!(errors[1].message, "Region 'nope' not found in 'lib.rs'");
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let script = parse_script("[LABEL live]\n[SKIP_TO backup]\n[RUN]\n[LABEL backup]").unwrap();
+        assert_eq!(script.lines[0].directive, Directive::Label("live".into()));
+
+        let errors =
+            parse_script_all("[LABEL a]\n[SKIP_TO bakcup]\n[LABEL backup]\n[LABEL a]\n[SKIP_TO x]")
+                .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Unknown label 'bakcup'",
+                "Label 'a' is already defined",
+                "Unknown label 'x'"
+            ]
+        );
+        assert_eq!(errors[0].help.as_deref(), Some("did you mean backup?"));
+        assert_eq!(
+            errors[1].help.as_deref(),
+            Some("the first [LABEL a] is at line 1")
+        );
+        assert_eq!(errors[2].span.unwrap().start, 9);
+
+        // Errors quote the line as written, not as the directive would print
+        let errors = parse_script_all("[label a]\n  [skip_to]   nowhere").unwrap_err();
+        assert_eq!(errors[0].line_content, "  [skip_to]   nowhere");
+        assert_eq!(errors[0].span.unwrap().start, 14);
+    }
+
+    #[test]
+    fn test_parse_include_cycle() {
+        let main = "[INCLUDE a.cm]\n";
+        let dir = TempDir::with_files(
+            "parser-include-cycle",
+            &[
+                ("main.cm", main),
+                ("a.cm", "[INCLUDE b.cm]\n"),
+                ("b.cm", "[SAY] b\n[INCLUDE a.cm]\n"),
+            ],
+        );
+        let err =
+            parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap_err();
+        assert!(err.message.contains("Include cycle"), "{err}");
+        assert_eq!(err.file.as_deref(), Some(dir.join("b.cm").as_path()));
+        assert_eq!(err.line_number, 2);
+    }
+
+    #[test]
+    fn test_parse_substitutes_front_matter_vars() {
+        let input = "---\nvars:\n  project: my-demo\n---\n[SAY] Building ${project}\n[TYPE] cd ${project}\n";
+        let script = parse_script(input).unwrap();
+        assert_eq!(
+            script.lines[0].directive,
+            Directive::Say("Building my-demo".into())
+        );
+        assert_eq!(
+            script.lines[1].directive,
+            Directive::Type("cd my-demo".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_undefined_var_reports_line() {
+        let input = "---\nvars:\n  a: 1\n---\n\n[EXEC] open ${url}\n";
+        let err = parse_script(input).unwrap_err();
+        assert_eq!(err.line_number, 6);
+        assert!(err.message.contains("url"));
+    }
+
+    #[test]
+    fn test_parse_invalid_var_not_reported_again() {
+        let input = "---\nvars:\n  a: [1, 2]\n---\n[SAY] ${a}\n";
+        let errors = parse_script_all(input).unwrap_err();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].line_number, 3);
+        assert!(errors[0].message.contains("vars.a"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn test_parse_segments_type_text_once_complete() {
+        let input = "---\nvars:\n  key: \"{tab}\"\n---\n[TYPE] ls${key}\n[TYPE]\n```\nprintf(\"\\n{tab}\")\n```\n";
+        let script = parse_script(input).unwrap();
+        let Directive::Type(typed) = &script.lines[0].directive else {
+            panic!("{:?}", script.lines[0].directive);
+        };
+        assert_eq!(
+            typed.segments,
+            [
+                keys::TypeSegment::Text("ls".into()),
+                keys::TypeSegment::Key {
+                    combo: "tab".into(),
+                    repeat: 1
+                },
+            ]
+        );
+        // A fence is typed as written, escapes and all
+        let Directive::Type(typed) = &script.lines[1].directive else {
+            panic!("{:?}", script.lines[1].directive);
+        };
+        assert_eq!(
+            typed.segments,
+            [keys::TypeSegment::Text("printf(\"\\n{tab}\")".into())]
+        );
+
+        let input = "---\nvars:\n  key: \"{cmd+nope}\"\n---\n[TYPE] ls${key}\n";
+        let err = parse_script(input).unwrap_err();
+        assert_eq!(err.line_number, 5);
+        assert!(err.message.contains("nope"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_focus_resolves_app_alias() {
+        let input = "---\napps:\n  editor: Visual Studio Code\nvars:\n  app: editor\n---\n\
+                     [FOCUS] editor\n[FOCUS] ${app}\n[FOCUS] Terminal";
+        let script = parse_script(input).unwrap();
+        let apps: Vec<&Directive> = script.lines.iter().map(|l| &l.directive).collect();
+        assert_eq!(
+            apps,
+            vec![
+                &Directive::Focus("Visual Studio Code".into()),
+                &Directive::Focus("Visual Studio Code".into()),
+                &Directive::Focus("Terminal".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_front_matter_key_is_a_warning_unless_strict() {
+        let input = "---\ntitel: Demo\n---\n[SAY] hi";
+        let script = parse_script(input).unwrap();
+        assert_eq!(script.warnings.len(), 1);
+        assert_eq!(script.warnings[0].line_number, 2);
+
+        let options = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let dir = TempDir::with_files("parser-strict", &[]);
+        let errors = parse_script_at_all(input, &dir.join("main.cm"), &options).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("titel"), "{}", errors[0]);
+        assert_eq!(errors[0].file, Some(dir.join("main.cm")));
+    }
+
+    #[test]
+    fn test_parse_option_vars_override_front_matter() {
+        let main =
+            "---\nvars:\n  port: 8080\n---\n[TYPE] curl localhost:${port}\n[INCLUDE more.cm]\n";
+        let dir = TempDir::with_files("parser-vars-override", &[("more.cm", "[FOCUS] ${app}\n")]);
+        let options = ParseOptions {
+            vars: [("port", "9090"), ("app", "Terminal")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        let script = parse_script_at(main, &dir.join("main.cm"), &options).unwrap();
+        assert_eq!(
+            script.lines[0].directive,
+            Directive::Type("curl localhost:9090".into())
+        );
+        assert_eq!(
+            script.lines[1].directive,
+            Directive::Focus("Terminal".into())
+        );
+        assert_eq!(script.front_matter.vars["port"], "9090");
+    }
+
+    #[test]
+    fn test_parse_macro_define_and_call() {
+        let input = "\
+[CALL run \"cargo test\"]
+[SAY] between
+[DEFINE run]
+[FOCUS] Terminal
+[TYPE] ${1}
+[RUN]
+[END]
+[CALL run ls]
+";
+        let script = parse_script(input).unwrap();
+        assert_eq!(script.macros_defined, 1);
+        assert_eq!(script.macros_expanded, 2);
+        assert_eq!(script.lines.len(), 7);
+        assert_eq!(
+            script.lines[1].directive,
+            Directive::Type("cargo test".into())
+        );
+        assert_eq!(script.lines[1].line_number, 5);
+        assert_eq!(
+            script.lines[1].expanded_from.as_ref().unwrap().line_number,
+            1
+        );
+        assert_eq!(script.lines[5].directive, Directive::Type("ls".into()));
+        assert_eq!(
+            script.lines[5].expanded_from.as_ref().unwrap().line_number,
+            8
+        );
+    }
+
+    #[test]
+    fn test_parse_macro_body_uses_vars() {
+        let input = "---\nvars:\n  app: Terminal\n---\n[DEFINE go]\n[FOCUS] ${app}\n[TYPE] ${1}\n[END]\n[CALL go pwd]\n";
+        let script = parse_script(input).unwrap();
+        assert_eq!(
+            script.lines[0].directive,
+            Directive::Focus("Terminal".into())
+        );
+        assert_eq!(script.lines[1].directive, Directive::Type("pwd".into()));
+    }
+
+    #[test]
+    fn test_parse_recursive_macro_rejected() {
+        let input = "[DEFINE a]\n[CALL b]\n[END]\n[DEFINE b]\n[CALL a]\n[END]\n\n[CALL a]\n";
+        let err = parse_script(input).unwrap_err();
+        assert!(err.message.contains("Recursive"), "{err}");
+        assert_eq!(err.line_number, 5);
+
+        // Rejected even when nothing calls it
+        let err = parse_script("[DEFINE a]\n[CALL a]\n[END]\n").unwrap_err();
+        assert!(err.message.contains("a -> a"), "{err}");
+        assert_eq!(err.line_number, 2);
+    }
+
+    #[test]
+    fn test_parse_unclosed_define() {
+        let input = "[SAY] hi\n[DEFINE a]\n[RUN]\n";
+        let err = parse_script(input).unwrap_err();
+        assert_eq!(err.line_number, 2);
+        let err = parse_script("[END]\n").unwrap_err();
+        assert!(err.message.contains("without a matching"));
+    }
+
+    #[test]
+    fn test_parse_macro_from_include() {
+        let main = "[INCLUDE lib.cm]\n[INCLUDE lib.cm]\n[CALL hello]\n";
+        let dir = TempDir::with_files(
+            "parser-macro-include",
+            &[("lib.cm", "[DEFINE hello]\n[SAY] hello\n[END]\n")],
+        );
+        let script = parse_script_at(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap();
+        assert_eq!(script.lines.len(), 1);
+        assert_eq!(
+            script.lines[0].file.as_deref(),
+            Some(dir.join("lib.cm").as_path())
+        );
+        assert_eq!(script.lines[0].line_number, 2);
+        let call_site = script.lines[0].expanded_from.as_ref().unwrap();
+        assert_eq!(
+            call_site.file.as_deref(),
+            Some(dir.join("main.cm").as_path())
+        );
+        assert_eq!(call_site.line_number, 3);
+    }
+
+    #[test]
+    fn test_parse_all_collects_every_error() {
+        let input = "\
+---
+typing_speed: fast
+---
+[SAY] ok
+[BOGUS]
+[PAUSE soon]
+not a directive
+[CALL missing]
+[TYPE] ${undefined}
+";
+        let errors = parse_script_all(input).unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.line_number).collect();
+        assert_eq!(lines, vec![2, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_parse_all_sorts_and_reports_included_files() {
+        let main = "[BOGUS]\n[INCLUDE part.cm]\n[BOGUS]\n[INCLUDE missing.cm]\n";
+        let dir = TempDir::with_files("parser-all-errors", &[("part.cm", "[SAY] ok\n[NOPE]\n")]);
+        let errors =
+            parse_script_at_all(main, &dir.join("main.cm"), &ParseOptions::default()).unwrap_err();
+        let found: Vec<(String, usize)> = errors
+            .iter()
+            .map(|e| {
+                let name = e.file.as_ref().unwrap().file_name().unwrap();
+                (name.to_string_lossy().into_owned(), e.line_number)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("main.cm".to_string(), 1),
+                ("main.cm".to_string(), 3),
+                ("main.cm".to_string(), 4),
+                ("part.cm".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_all_reports_macro_body_error_once() {
+        let input =
+            "[DEFINE a]\n[CALL b]\n[CALL b]\n[END]\n[DEFINE b]\n[TYPE] ${1}\n[END]\n[CALL a]\n";
+        let errors = parse_script_all(input).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line_number, 8);
+    }
+
     #[test]
     fn test_parse_full_script_roundtrip() {
         let input = "\
@@ -0,0 +1,224 @@
+use std::path::Path;
+
+use super::types::TypeSource;
+
+/// Opens a named region in a source file, as in `// cm:start setup`. Any comment syntax
+/// works: only the text from the marker on is looked at, and the marker must follow
+/// whitespace or comment punctuation.
+pub const REGION_START: &str = "cm:start";
+/// Closes the innermost open region.
+pub const REGION_END: &str = "cm:end";
+
+/// Read the text a `[TYPE file=...]` types: the whole file, a range of lines or a named
+/// region, with lines joined by `\n`. A relative path is resolved against `base`, the
+/// directory of the script. Region marker lines are never typed. Reading a region needs
+/// every `cm:start` in the file to have a name, so the regions nest as written.
+pub fn read_source(source: &TypeSource, base: &Path) -> Result<String, String> {
+    let path = base.join(&source.path);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read file '{}': {e}", path.display()))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let without_markers = |lines: &[&str]| {
+        lines
+            .iter()
+            .filter(|line| marker(line).is_none())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    if let Some((start, end)) = source.lines {
+        if end > lines.len() {
+            return Err(format!(
+                "lines={start}-{end} is past the end of '{}', which has {} lines",
+                source.path,
+                lines.len()
+            ));
+        }
+        return Ok(without_markers(&lines[start - 1..end]));
+    }
+    match &source.region {
+        Some(name) => {
+            if let Some(idx) = lines
+                .iter()
+                .position(|line| marker(line) == Some(Marker::Start(None)))
+            {
+                return Err(format!(
+                    "{REGION_START} on line {} of '{}' has no region name",
+                    idx + 1,
+                    source.path
+                ));
+            }
+            region(&lines, name)
+                .map(|body| body.join("\n"))
+                .map_err(|e| format!("{e} in '{}'", source.path))
+        }
+        None => Ok(without_markers(&lines)),
+    }
+}
+
+/// The lines of region `name`, without the markers of any regions nested inside it.
+fn region<'a>(lines: &[&'a str], name: &str) -> Result<Vec<&'a str>, String> {
+    let start = lines
+        .iter()
+        .position(|line| marker(line) == Some(Marker::Start(Some(name))))
+        .ok_or_else(|| format!("Region '{name}' not found"))?;
+
+    let mut body = Vec::new();
+    let mut depth = 0;
+    for line in &lines[start + 1..] {
+        match marker(line) {
+            Some(Marker::Start(_)) => depth += 1,
+            Some(Marker::End) if depth == 0 => return Ok(body),
+            Some(Marker::End) => depth -= 1,
+            None => body.push(*line),
+        }
+    }
+    Err(format!("Region '{name}' is never closed with {REGION_END}"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Marker<'a> {
+    /// A region start and its name, which is `None` when it was left out.
+    Start(Option<&'a str>),
+    End,
+}
+
+/// The region marker on `line`, if any. Earlier `cm:` text that is not a marker, as in a
+/// string, is skipped, as is `cm:` at the end of a word such as `scm:start`.
+fn marker(line: &str) -> Option<Marker<'_>> {
+    line.match_indices("cm:").find_map(|(pos, _)| {
+        let before = line[..pos].chars().next_back();
+        if before.is_some_and(|c| !c.is_whitespace() && !"/#-*;%!<({".contains(c)) {
+            return None;
+        }
+        let mut words = line[pos..].split_whitespace();
+        match words.next()? {
+            REGION_START => Some(Marker::Start(words.next())),
+            REGION_END => Some(Marker::End),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const SOURCE: &str = "use std::io;\n\
+                          // cm:start main\n\
+                          fn main() {\n\
+                          \x20   // cm:start body\n\
+                          \x20   println!(\"hi\");\n\
+                          \x20   // cm:end\n\
+                          }\n\
+                          // cm:end\n";
+
+    fn read(
+        name: &str,
+        lines: Option<(usize, usize)>,
+        region: Option<&str>,
+    ) -> Result<String, String> {
+        let dir = TempDir::with_files(&format!("snippets-{name}"), &[("main.rs", SOURCE)]);
+        let source = TypeSource {
+            path: "main.rs".into(),
+            lines,
+            region: region.map(str::to_string),
+        };
+        read_source(&source, &dir)
+    }
+
+    #[test]
+    fn test_read_whole_file_skips_markers() {
+        assert_eq!(
+            read("whole", None, None).unwrap(),
+            "use std::io;\nfn main() {\n    println!(\"hi\");\n}"
+        );
+    }
+
+    #[test]
+    fn test_read_lines() {
+        assert_eq!(read("lines", Some((1, 1)), None).unwrap(), "use std::io;");
+        assert_eq!(
+            read("marked", Some((1, 3)), None).unwrap(),
+            "use std::io;\nfn main() {"
+        );
+        assert_eq!(
+            read("past", Some((7, 9)), None).unwrap_err(),
+            "lines=7-9 is past the end of 'main.rs', which has 8 lines"
+        );
+    }
+
+    #[test]
+    fn test_read_regions() {
+        assert_eq!(
+            read("outer", None, Some("main")).unwrap(),
+            "fn main() {\n    println!(\"hi\");\n}"
+        );
+        assert_eq!(
+            read("inner", None, Some("body")).unwrap(),
+            "    println!(\"hi\");"
+        );
+        assert_eq!(
+            read("missing", None, Some("teardown")).unwrap_err(),
+            "Region 'teardown' not found in 'main.rs'"
+        );
+    }
+
+    #[test]
+    fn test_read_missing_file() {
+        let source = TypeSource {
+            path: "nope.rs".into(),
+            lines: None,
+            region: None,
+        };
+        let err = read_source(&source, Path::new("/nonexistent")).unwrap_err();
+        assert!(
+            err.starts_with("Cannot read file '/nonexistent/nope.rs'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_marker() {
+        assert_eq!(
+            marker("# cm:start setup"),
+            Some(Marker::Start(Some("setup")))
+        );
+        assert_eq!(marker("  -- cm:end"), Some(Marker::End));
+        assert_eq!(marker("// cm:start"), Some(Marker::Start(None)));
+        assert_eq!(marker("let x = \"cm:x\"; // cm:end"), Some(Marker::End));
+        assert_eq!(marker("let x = 1;"), None);
+        assert_eq!(marker("#cm:end"), Some(Marker::End));
+        assert_eq!(marker("/* cm:start a */"), Some(Marker::Start(Some("a"))));
+        assert_eq!(marker("url = \"git+scm:start\""), None);
+        assert_eq!(marker("xcm:end"), None);
+    }
+
+    #[test]
+    fn test_read_rejects_unnamed_region() {
+        let dir = TempDir::with_files(
+            "snippets-unnamed",
+            &[("main.rs", "// cm:start\nfn main() {}\n// cm:end\n")],
+        );
+        let source = |lines, region: Option<&str>| TypeSource {
+            path: "main.rs".into(),
+            lines,
+            region: region.map(str::to_string),
+        };
+        assert_eq!(
+            read_source(&source(None, Some("main")), &dir).unwrap_err(),
+            "cm:start on line 1 of 'main.rs' has no region name"
+        );
+        // Reads that do not look for a region only leave the markers out
+        assert_eq!(
+            read_source(&source(None, None), &dir).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(
+            read_source(&source(Some((1, 2)), None), &dir).unwrap(),
+            "fn main() {}"
+        );
+    }
+}
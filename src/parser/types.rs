@@ -1,5 +1,13 @@
-use serde::{Deserialize, Serialize};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::keys::{TypeSegment, parse_type_text, unescape_literal};
+use super::lexer::ParseError;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SlideAction {
@@ -8,10 +16,161 @@ pub enum SlideAction {
     GoTo(u32),
 }
 
+/// The text of a `[TYPE]` and its per-directive typing settings.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TypeText {
+    pub text: String,
+    /// Milliseconds per keystroke, overriding the front matter and any `[SPEED]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<u64>,
+    /// Random jitter added to `speed`, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variance: Option<u64>,
+    /// For `[TYPE file=...]`, the file `text` was read from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<TypeSource>,
+    /// `text` split into typed runs and key presses by the parser. Executors type these
+    /// rather than reading `text`, so escapes and key tokens mean the same to the presenter
+    /// and the agent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<TypeSegment>,
+}
+
+/// The file, and optionally the part of it, that a `[TYPE file=...]` types.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TypeSource {
+    /// The path as written, relative to the script.
+    pub path: String,
+    /// 1-based, inclusive range of lines, from `lines=a-b`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<(usize, usize)>,
+    /// Name of a region between `cm:start name` and `cm:end` marker lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl fmt::Display for TypeSource {
+    /// The attributes that select the text, as in `file=src/main.rs lines=3-9`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file={}", self.path)?;
+        match (self.lines, &self.region) {
+            (Some((start, end)), _) if start == end => write!(f, " lines={start}"),
+            (Some((start, end)), _) => write!(f, " lines={start}-{end}"),
+            (None, Some(region)) => write!(f, " region={region}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl From<&str> for TypeText {
+    fn from(text: &str) -> Self {
+        String::from(text).into()
+    }
+}
+
+impl From<String> for TypeText {
+    /// Text as a script would write it. A malformed key token is typed as written.
+    fn from(text: String) -> Self {
+        let segments =
+            parse_type_text(&text).unwrap_or_else(|_| vec![TypeSegment::Text(text.clone())]);
+        Self {
+            text,
+            segments,
+            ..Default::default()
+        }
+    }
+}
+
+/// The command of an `[EXEC]` and how long to wait for it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExecCommand {
+    pub command: String,
+    /// Wait at most this many seconds for the command to finish. Without a timeout the
+    /// command is started in the background and not waited for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl From<&str> for ExecCommand {
+    fn from(command: &str) -> Self {
+        String::from(command).into()
+    }
+}
+
+impl From<String> for ExecCommand {
+    fn from(command: String) -> Self {
+        Self {
+            command,
+            timeout: None,
+        }
+    }
+}
+
+/// How deep a heading sits in the outline of a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum HeadingLevel {
+    /// `# Chapter: name`
+    Chapter,
+    /// `## Section: name`
+    Section,
+    /// `### Step: name`
+    Step,
+}
+
+impl HeadingLevel {
+    pub const ALL: [HeadingLevel; 3] = [Self::Chapter, Self::Section, Self::Step];
+
+    /// The word after the `#`s, as in `Chapter`.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Self::Chapter => "Chapter",
+            Self::Section => "Section",
+            Self::Step => "Step",
+        }
+    }
+
+    /// How many `#`s the heading starts with.
+    pub fn depth(self) -> usize {
+        self as usize + 1
+    }
+
+    /// The start of a heading line, as in `## Section:`.
+    pub fn marker(self) -> String {
+        format!("{} {}:", "#".repeat(self.depth()), self.keyword())
+    }
+}
+
+/// A chapter, section or step heading.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Heading {
+    pub level: HeadingLevel,
+    pub name: String,
+}
+
+impl fmt::Display for Heading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.level.marker(), self.name)
+    }
+}
+
+/// Narration or a note in one language, as in `[SAY:de] Hallo`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Localized {
+    /// Language code, in lower case.
+    pub lang: String,
+    pub text: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Directive {
     Say(String),
-    Type(String),
+    /// `[SAY:lang] text`: the narration of the block in another language.
+    SayIn(Localized),
+    /// `[NOTE] text`: a private reminder for the presenter, never spoken.
+    Note(String),
+    /// `[NOTE:lang] text`
+    NoteIn(Localized),
+    Type(TypeText),
     Run,
     Pause(Option<u64>),
     Focus(String),
@@ -19,15 +178,89 @@ pub enum Directive {
     Key(String),
     Clear,
     Wait(u64),
-    Exec(String),
+    Exec(ExecCommand),
+    /// `[SPEED n]`: default milliseconds per keystroke for the rest of the section.
+    Speed(u64),
+    /// `# Chapter: name`
+    Chapter(String),
+    /// `## Section: name`
     Section(String),
+    /// `### Step: name`
+    Step(String),
+    /// `[LABEL name]`: a place the presenter can jump to.
+    Label(String),
+    /// `[SKIP_TO name]`: a branch to a label that the presenter may take instead of
+    /// running the block.
+    SkipTo(String),
+}
+
+impl Directive {
+    /// The heading a `# Chapter:`, `## Section:` or `### Step:` line makes.
+    pub fn heading(&self) -> Option<Heading> {
+        let (level, name) = match self {
+            Directive::Chapter(name) => (HeadingLevel::Chapter, name),
+            Directive::Section(name) => (HeadingLevel::Section, name),
+            Directive::Step(name) => (HeadingLevel::Step, name),
+            _ => return None,
+        };
+        Some(Heading {
+            level,
+            name: name.clone(),
+        })
+    }
+
+    /// Whether only the presenter acts on this, so executors have nothing to perform:
+    /// narration, notes, pauses, speeds, headings and labels.
+    pub fn is_client_side(&self) -> bool {
+        match self {
+            Directive::Say(_)
+            | Directive::SayIn(_)
+            | Directive::Note(_)
+            | Directive::NoteIn(_)
+            | Directive::Pause(_)
+            | Directive::Speed(_)
+            | Directive::Chapter(_)
+            | Directive::Section(_)
+            | Directive::Step(_)
+            | Directive::Label(_)
+            | Directive::SkipTo(_) => true,
+            Directive::Type(_)
+            | Directive::Run
+            | Directive::Focus(_)
+            | Directive::Slide(_)
+            | Directive::Key(_)
+            | Directive::Clear
+            | Directive::Wait(_)
+            | Directive::Exec(_) => false,
+        }
+    }
 }
 
 impl fmt::Display for Directive {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Directive::Say(text) => write!(f, "[SAY] {text}"),
-            Directive::Type(text) => write!(f, "[TYPE] {text}"),
+            Directive::SayIn(Localized { lang, text }) => write!(f, "[SAY:{lang}] {text}"),
+            Directive::Note(text) => write!(f, "[NOTE] {text}"),
+            Directive::NoteIn(Localized { lang, text }) => write!(f, "[NOTE:{lang}] {text}"),
+            Directive::Type(typed) => {
+                let mut settings = String::new();
+                if let Some(speed) = typed.speed {
+                    settings.push_str(&format!(" speed={speed}"));
+                }
+                if let Some(variance) = typed.variance {
+                    settings.push_str(&format!(" variance={variance}"));
+                }
+                if let Some(source) = &typed.source {
+                    return write!(f, "[TYPE{settings} {source}]");
+                }
+                if typed.text.contains('\n') {
+                    let text = unescape_literal(&typed.text);
+                    write!(f, "[TYPE{settings}]\n```\n{text}\n```")
+                } else {
+                    write!(f, "[TYPE{settings}] {}", typed.text)
+                }
+            }
             Directive::Run => write!(f, "[RUN]"),
             Directive::Pause(None) => write!(f, "[PAUSE]"),
             Directive::Pause(Some(secs)) => write!(f, "[PAUSE {secs}]"),
@@ -38,18 +271,141 @@ impl fmt::Display for Directive {
             Directive::Key(combo) => write!(f, "[KEY {combo}]"),
             Directive::Clear => write!(f, "[CLEAR]"),
             Directive::Wait(secs) => write!(f, "[WAIT {secs}]"),
-            Directive::Exec(cmd) => write!(f, "[EXEC {cmd}]"),
+            Directive::Exec(ExecCommand {
+                command,
+                timeout: Some(secs),
+            }) => write!(f, "[EXEC timeout={secs} {command}]"),
+            Directive::Exec(exec) => write!(f, "[EXEC {}]", exec.command),
+            Directive::Speed(ms) => write!(f, "[SPEED {ms}]"),
+            Directive::Chapter(name) => write!(f, "# Chapter: {name}"),
             Directive::Section(name) => write!(f, "## Section: {name}"),
+            Directive::Step(name) => write!(f, "### Step: {name}"),
+            Directive::Label(name) => write!(f, "[LABEL {name}]"),
+            Directive::SkipTo(name) => write!(f, "[SKIP_TO {name}]"),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FrontMatter {
     pub title: Option<String>,
     pub typing_speed: u64,
     pub typing_variance: u64,
     pub agent_port: u16,
+    /// Values substituted for `${name}` references in directive arguments.
+    pub vars: BTreeMap<String, String>,
+    /// Named agents, so `present --agent demo` can stand in for an address.
+    pub agents: BTreeMap<String, AgentConfig>,
+    /// Short names for `[FOCUS]`, e.g. `editor: Visual Studio Code`.
+    pub apps: BTreeMap<String, String>,
+    pub executor: ExecutorOptions,
+    /// Number of slides in the deck, checked by the `slide-order` lint.
+    pub slide_count: Option<u32>,
+    /// Planned length of the talk in seconds, checked by `code-monkey estimate`.
+    #[serde(deserialize_with = "super::front_matter::deserialize_duration")]
+    pub duration: Option<u64>,
+    /// Levels for lint rules, overriding their defaults.
+    pub lint: BTreeMap<String, Level>,
+    /// Keys the front matter does not know, reported as warnings.
+    #[serde(flatten, skip_serializing)]
+    pub(crate) extra: BTreeMap<String, IgnoredAny>,
+}
+
+/// How a lint rule is reported, as set in the front matter `lint:` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Warning,
+    Error,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Level::Off),
+            "warning" => Ok(Level::Warning),
+            "error" => Ok(Level::Error),
+            _ => Err(format!(
+                "Invalid lint level '{s}' (expected off, warning or error)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Off => "off",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An entry under `agents:` in the front matter: a table, or just the address.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct AgentConfig {
+    /// `ip:port` of the agent.
+    pub address: String,
+    /// Keys an agent does not know, reported as warnings.
+    #[serde(skip)]
+    pub(crate) extra: BTreeMap<String, IgnoredAny>,
+}
+
+impl<'de> Deserialize<'de> for AgentConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AgentVisitor;
+
+        impl<'de> Visitor<'de> for AgentVisitor {
+            type Value = AgentConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an address, or a table with an address")
+            }
+
+            fn visit_str<E: de::Error>(self, address: &str) -> Result<AgentConfig, E> {
+                Ok(AgentConfig {
+                    address: address.to_string(),
+                    extra: BTreeMap::new(),
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<AgentConfig, A::Error> {
+                #[derive(Deserialize)]
+                struct Table {
+                    #[serde(default)]
+                    address: String,
+                    #[serde(flatten)]
+                    extra: BTreeMap<String, IgnoredAny>,
+                }
+                let Table { address, extra } = Table::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(AgentConfig { address, extra })
+            }
+        }
+
+        deserializer.deserialize_any(AgentVisitor)
+    }
+}
+
+/// The `executor:` table of the front matter, read by the agent.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutorOptions {
+    /// Which executor performs the actions: `applescript`, `linux`, `xdotool`, `ydotool`,
+    /// `tmux`, `pty` or `dry-run`.
+    pub kind: Option<String>,
+    /// Shell that runs `[EXEC]` commands, `sh` when unset.
+    pub shell: Option<String>,
+    /// The tmux session, window or pane for the `tmux` executor, e.g. `demo:shell.1`.
+    pub target: Option<String>,
+    /// Keys the executor does not know, reported as warnings.
+    #[serde(flatten, skip_serializing)]
+    pub(crate) extra: BTreeMap<String, IgnoredAny>,
 }
 
 impl Default for FrontMatter {
@@ -59,20 +415,89 @@ impl Default for FrontMatter {
             typing_speed: 40,
             typing_variance: 15,
             agent_port: 9876,
+            vars: BTreeMap::new(),
+            agents: BTreeMap::new(),
+            apps: BTreeMap::new(),
+            executor: ExecutorOptions::default(),
+            slide_count: None,
+            duration: None,
+            lint: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// A line in a script file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: Option<PathBuf>,
+    pub line_number: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file.display(), self.line_number),
+            None => write!(f, "line {}", self.line_number),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ParsedLine {
     pub line_number: usize,
     pub directive: Directive,
+    /// File the line was read from; `None` when parsing a string with no known origin.
+    pub file: Option<PathBuf>,
+    /// For lines produced by a macro, the outermost `[CALL]` they were expanded from.
+    /// `line_number` and `file` then point into the `[DEFINE]` body.
+    pub expanded_from: Option<SourceLocation>,
+    /// The line as written, for errors found after the file is read. A fenced `[TYPE]`
+    /// keeps the line that opens the fence.
+    #[serde(skip)]
+    pub line_content: String,
 }
 
-#[derive(Debug, Clone)]
+impl ParsedLine {
+    pub fn new(line_number: usize, directive: Directive) -> Self {
+        Self {
+            line_number,
+            directive,
+            file: None,
+            expanded_from: None,
+            line_content: String::new(),
+        }
+    }
+
+    pub fn location(&self) -> SourceLocation {
+        SourceLocation {
+            file: self.file.clone(),
+            line_number: self.line_number,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Script {
     pub front_matter: FrontMatter,
     pub lines: Vec<ParsedLine>,
+    /// Every file pulled in through `[INCLUDE]`, in the order first encountered.
+    pub includes: Vec<PathBuf>,
+    /// Number of `[DEFINE]` blocks in the include tree.
+    pub macros_defined: usize,
+    /// Number of `[CALL]`s expanded, including calls made from inside other macros.
+    pub macros_expanded: usize,
+    /// Problems that do not stop the script from running, such as unknown front matter
+    /// keys. With [`ParseOptions::strict`](super::ParseOptions::strict) they are errors.
+    pub warnings: Vec<ParseError>,
+}
+
+impl Script {
+    /// Drop every `[NOTE]` and `[NOTE:lang]`, for `present --hide-notes`.
+    pub fn remove_notes(&mut self) {
+        self.lines
+            .retain(|line| !matches!(line.directive, Directive::Note(_) | Directive::NoteIn(_)));
+    }
 }
 
 #[cfg(test)]
@@ -86,6 +511,10 @@ mod tests {
             Directive::Type("cargo build".into()).to_string(),
             "[TYPE] cargo build"
         );
+        assert_eq!(
+            Directive::Type("a\n  b".into()).to_string(),
+            "[TYPE]\n```\na\n  b\n```"
+        );
         assert_eq!(Directive::Run.to_string(), "[RUN]");
         assert_eq!(Directive::Pause(None).to_string(), "[PAUSE]");
         assert_eq!(Directive::Pause(Some(3)).to_string(), "[PAUSE 3]");
@@ -112,10 +541,73 @@ mod tests {
             Directive::Exec("cargo build".into()).to_string(),
             "[EXEC cargo build]"
         );
+        assert_eq!(
+            Directive::Type(TypeText {
+                speed: Some(80),
+                variance: Some(0),
+                ..TypeText::from("ls")
+            })
+            .to_string(),
+            "[TYPE speed=80 variance=0] ls"
+        );
+        assert_eq!(
+            Directive::Type(TypeText {
+                text: "fn main() {}".into(),
+                speed: Some(20),
+                variance: None,
+                source: Some(TypeSource {
+                    path: "src/main.rs".into(),
+                    lines: Some((3, 9)),
+                    region: None,
+                }),
+                ..Default::default()
+            })
+            .to_string(),
+            "[TYPE speed=20 file=src/main.rs lines=3-9]"
+        );
+        assert_eq!(
+            Directive::Exec(ExecCommand {
+                command: "make".into(),
+                timeout: Some(5),
+            })
+            .to_string(),
+            "[EXEC timeout=5 make]"
+        );
+        assert_eq!(Directive::Speed(80).to_string(), "[SPEED 80]");
+        assert_eq!(Directive::Label("a".into()).to_string(), "[LABEL a]");
+        assert_eq!(
+            Directive::Note("slow down".into()).to_string(),
+            "[NOTE] slow down"
+        );
+        assert_eq!(
+            Directive::SayIn(Localized {
+                lang: "de".into(),
+                text: "Hallo".into()
+            })
+            .to_string(),
+            "[SAY:de] Hallo"
+        );
+        assert_eq!(Directive::SkipTo("a".into()).to_string(), "[SKIP_TO a]");
         assert_eq!(
             Directive::Section("Intro".into()).to_string(),
             "## Section: Intro"
         );
+        assert_eq!(
+            Directive::Chapter("Basics".into()).to_string(),
+            "# Chapter: Basics"
+        );
+        assert_eq!(
+            Directive::Step("Install".into()).to_string(),
+            "### Step: Install"
+        );
+        assert_eq!(
+            Directive::Step("Install".into()).heading(),
+            Some(Heading {
+                level: HeadingLevel::Step,
+                name: "Install".into()
+            })
+        );
+        assert_eq!(Directive::Run.heading(), None);
     }
 
     #[test]
@@ -125,5 +617,6 @@ mod tests {
         assert_eq!(fm.typing_speed, 40);
         assert_eq!(fm.typing_variance, 15);
         assert_eq!(fm.agent_port, 9876);
+        assert!(fm.vars.is_empty());
     }
 }
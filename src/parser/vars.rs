@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use super::lexer::ParseError;
+use super::types::{Directive, ParsedLine};
+
+/// An undefined or unterminated `${...}` reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubstitutionError {
+    pub message: String,
+    /// The reference as written, e.g. `${name}`.
+    pub reference: String,
+}
+
+/// Replace every `${name}` in `text` with its value from `vars`.
+///
+/// `$${` is an escape for a literal `${`, which keeps shell syntax such as `$${HOME}` usable
+/// in `[EXEC]` and `[TYPE]`. Fails on the first undefined or unterminated reference.
+pub fn substitute(
+    text: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<String, SubstitutionError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find("${") {
+        if rest[..pos].ends_with('$') {
+            // `$${` — drop the escaping `$` and keep the reference as written
+            out.push_str(&rest[..pos - 1]);
+            out.push_str("${");
+            rest = &rest[pos + 2..];
+            continue;
+        }
+
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let end = after.find('}').ok_or_else(|| SubstitutionError {
+            message: format!("Unterminated variable reference '${{{after}'"),
+            reference: format!("${{{after}"),
+        })?;
+        let name = after[..end].trim();
+        let value = vars.get(name).ok_or_else(|| SubstitutionError {
+            message: format!(
+                "Undefined variable '{name}' (declare it under 'vars:' or pass --var {name}=...)"
+            ),
+            reference: format!("${{{}}}", &after[..end]),
+        })?;
+        out.push_str(value);
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The argument of `[SAY]`, `[NOTE]`, `[TYPE]`, `[FOCUS]` and `[EXEC]`, the directives
+/// whose text takes `${...}` references, in any language.
+pub fn substitutable_text(directive: &mut Directive) -> Option<&mut String> {
+    match directive {
+        Directive::Say(text) | Directive::Note(text) | Directive::Focus(text) => Some(text),
+        Directive::SayIn(localized) | Directive::NoteIn(localized) => Some(&mut localized.text),
+        // Text read from a file is typed exactly as it is there
+        Directive::Type(typed) if typed.source.is_some() => None,
+        Directive::Type(typed) => Some(&mut typed.text),
+        Directive::Exec(exec) => Some(&mut exec.command),
+        _ => None,
+    }
+}
+
+/// Substitute variables into the arguments of `[SAY]`, `[NOTE]`, `[TYPE]`, `[FOCUS]` and
+/// `[EXEC]`.
+/// Lines with a bad reference are left as written and reported in `errors`.
+pub fn substitute_lines(
+    lines: &mut [ParsedLine],
+    vars: &BTreeMap<String, String>,
+    errors: &mut Vec<ParseError>,
+) {
+    for line in lines {
+        let Some(text) = substitutable_text(&mut line.directive) else {
+            continue;
+        };
+        match substitute(text, vars) {
+            Ok(substituted) => *text = substituted,
+            Err(e) => errors.push(
+                ParseError::new(line.line_number, &line.line_content, e.message)
+                    .at(&e.reference)
+                    .in_file(line.file.as_deref()),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lexer::{Span, parse_line};
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_substitute_basic() {
+        let vars = vars(&[("project", "my-demo"), ("port", "8080")]);
+        assert_eq!(
+            substitute("cd ${project} && serve --port ${port}", &vars).unwrap(),
+            "cd my-demo && serve --port 8080"
+        );
+    }
+
+    #[test]
+    fn test_substitute_no_references() {
+        assert_eq!(
+            substitute("echo $HOME {}", &vars(&[])).unwrap(),
+            "echo $HOME {}"
+        );
+    }
+
+    #[test]
+    fn test_substitute_escaped() {
+        let vars = vars(&[("x", "1")]);
+        assert_eq!(
+            substitute("echo $${HOME} ${x}", &vars).unwrap(),
+            "echo ${HOME} 1"
+        );
+    }
+
+    #[test]
+    fn test_substitute_undefined() {
+        let err = substitute("cd ${missing}", &vars(&[])).unwrap_err();
+        assert!(err.message.contains("missing"));
+        assert_eq!(err.reference, "${missing}");
+    }
+
+    #[test]
+    fn test_substitute_unterminated() {
+        let err = substitute("cd ${project", &vars(&[("project", "p")])).unwrap_err();
+        assert!(err.message.contains("Unterminated"));
+    }
+
+    #[test]
+    fn test_substitute_lines_reports_every_line() {
+        let mut lines: Vec<ParsedLine> = [
+            (3, "[SAY] Welcome to ${name}"),
+            (7, "[EXEC] open ${url}"),
+            (9, "[FOCUS] ${app}"),
+        ]
+        .into_iter()
+        .map(|(number, line)| parse_line(line, number).unwrap().unwrap())
+        .collect();
+        let mut errors = Vec::new();
+        substitute_lines(&mut lines, &vars(&[("name", "Rust")]), &mut errors);
+        let numbers: Vec<usize> = errors.iter().map(|e| e.line_number).collect();
+        assert_eq!(numbers, vec![7, 9]);
+        assert_eq!(errors[0].span, Some(Span { start: 12, end: 18 }));
+        assert_eq!(lines[0].directive, Directive::Say("Welcome to Rust".into()));
+    }
+}
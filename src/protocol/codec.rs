@@ -49,6 +49,7 @@ mod tests {
         let msg = Message::Ack {
             status: AckStatus::Ok,
             message: None,
+            failed_action: None,
         };
         let encoded = encode_message(&msg).unwrap();
         let (decoded, consumed) = decode_message(&encoded).unwrap().unwrap();
@@ -72,7 +73,7 @@ mod tests {
     fn test_roundtrip_large_message() {
         let long_text = "x".repeat(10000);
         let msg = Message::Execute {
-            actions: vec![Directive::Type(long_text.clone())],
+            actions: vec![Directive::Type(long_text.clone().into())],
             typing_speed: 40,
             typing_variance: 15,
         };
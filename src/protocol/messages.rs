@@ -13,6 +13,9 @@ pub enum Message {
     Ack {
         status: AckStatus,
         message: Option<String>,
+        /// Index into the `Execute` actions of the one that failed, when known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        failed_action: Option<usize>,
     },
     Ping,
     Pong,
@@ -27,7 +30,7 @@ pub enum AckStatus {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::types::SlideAction;
+    use crate::parser::types::{ExecCommand, SlideAction, TypeText};
 
     #[test]
     fn test_serialize_execute() {
@@ -64,6 +67,7 @@ mod tests {
         let msg = Message::Ack {
             status: AckStatus::Ok,
             message: None,
+            failed_action: None,
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"Ok\""));
@@ -74,6 +78,7 @@ mod tests {
         let msg = Message::Ack {
             status: AckStatus::Error,
             message: Some("no accessibility".into()),
+            failed_action: None,
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"Error\""));
@@ -85,12 +90,81 @@ mod tests {
         let msg = Message::Ack {
             status: AckStatus::Ok,
             message: None,
+            failed_action: None,
         };
         let json = serde_json::to_string(&msg).unwrap();
         let roundtrip: Message = serde_json::from_str(&json).unwrap();
         assert_eq!(roundtrip, msg);
     }
 
+    #[test]
+    fn test_ack_failed_action_is_optional() {
+        let msg: Message =
+            serde_json::from_str(r#"{"type":"Ack","status":"Error","message":"boom"}"#).unwrap();
+        assert_eq!(
+            msg,
+            Message::Ack {
+                status: AckStatus::Error,
+                message: Some("boom".into()),
+                failed_action: None,
+            }
+        );
+
+        let msg = Message::Ack {
+            status: AckStatus::Error,
+            message: Some("boom".into()),
+            failed_action: Some(2),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"failed_action\":2"));
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_execute_carries_per_action_settings() {
+        let msg = Message::Execute {
+            actions: vec![
+                Directive::Type(TypeText {
+                    speed: Some(80),
+                    variance: Some(0),
+                    ..TypeText::from("ls")
+                }),
+                Directive::Exec(ExecCommand {
+                    command: "make".into(),
+                    timeout: Some(10),
+                }),
+                Directive::Type("pwd".into()),
+            ],
+            typing_speed: 40,
+            typing_variance: 15,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"speed\":80"), "{json}");
+        assert!(json.contains("\"timeout\":10"), "{json}");
+        // Unset settings are left out, so the agent falls back to the message defaults
+        assert!(
+            json.contains("{\"Type\":{\"text\":\"pwd\",\"segments\":[{\"Text\":\"pwd\"}]}}"),
+            "{json}"
+        );
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_execute_carries_parsed_key_presses() {
+        let msg = Message::Execute {
+            actions: vec![Directive::Type("ls\\n".into())],
+            typing_speed: 40,
+            typing_variance: 15,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        // The escape arrives as a key press, not as text for the agent to interpret
+        assert!(
+            json.contains("[{\"Text\":\"ls\"},{\"Key\":{\"combo\":\"return\",\"repeat\":1}}]"),
+            "{json}"
+        );
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), msg);
+    }
+
     #[test]
     fn test_serialize_ping_pong() {
         let ping_json = serde_json::to_string(&Message::Ping).unwrap();
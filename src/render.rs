@@ -0,0 +1,351 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::agent::pty::{Pacing, PtySession, execute_pty_action};
+use crate::agent::{perform_action, run_exec_with};
+use crate::grouper::{ActionBlock, BlockType};
+use crate::parser::types::{Directive, FrontMatter};
+
+/// How long the terminal must be quiet after a keystroke before the next one, so its echo
+/// lands at the keystroke's time.
+const KEYSTROKE_QUIET: Duration = Duration::from_millis(5);
+
+/// The longest to wait for a keystroke's echo.
+const KEYSTROKE_LIMIT: Duration = Duration::from_millis(50);
+
+/// The longest to wait for a command to go quiet before the next action, so one that never
+/// stops printing cannot hang the render.
+const SETTLE_LIMIT: Duration = Duration::from_secs(60);
+
+/// Settings for [`render`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub cols: u16,
+    pub rows: u16,
+    /// How long a `[PAUSE]` without a timeout lasts in the recording.
+    pub pause: Duration,
+    /// How long the terminal must be quiet after `[RUN]`, `[KEY]` or `[CLEAR]` before the
+    /// next action starts.
+    pub settle: Duration,
+    /// Command to run in the terminal with `sh -c`, or `None` for the user's login shell.
+    pub shell: Option<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            cols: 80,
+            rows: 24,
+            pause: Duration::from_secs(2),
+            settle: Duration::from_millis(500),
+            shell: None,
+        }
+    }
+}
+
+/// One line of an asciicast after the header: output (`o`) or a marker (`m`) at `time`
+/// seconds into the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub time: f64,
+    pub code: &'static str,
+    pub data: String,
+}
+
+/// An asciinema recording, written as asciicast v2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub width: u16,
+    pub height: u16,
+    pub title: Option<String>,
+    pub events: Vec<Event>,
+}
+
+impl Cast {
+    /// The time of the last event, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |event| event.time)
+    }
+
+    /// Write the header line, then one line per event.
+    pub fn write_to(&self, mut out: impl Write) -> Result<()> {
+        let mut header = serde_json::json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+            "env": {"TERM": "xterm-256color"},
+        });
+        if let Some(title) = &self.title {
+            header["title"] = title.as_str().into();
+        }
+        writeln!(out, "{header}")?;
+        for event in &self.events {
+            let line = serde_json::json!([event.time, event.code, event.data]);
+            writeln!(out, "{line}")?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// The events so far, timed on a clock that jumps over typing delays and pauses instead of
+/// sleeping through them, but runs in real time while commands print.
+struct Recording {
+    started: Instant,
+    skipped: Duration,
+    last_output: Instant,
+    /// The start of a UTF-8 character split across reads.
+    partial: Vec<u8>,
+    events: Vec<Event>,
+}
+
+impl Recording {
+    fn push(&mut self, code: &'static str, data: String) {
+        let time = (self.started.elapsed() + self.skipped).as_secs_f64();
+        self.events.push(Event {
+            time: (time * 1e6).round() / 1e6,
+            code,
+            data,
+        });
+    }
+}
+
+/// The terminal output, shared between the session's reader thread and the renderer.
+#[derive(Clone)]
+struct Recorder(Arc<Mutex<Recording>>);
+
+impl Recorder {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self(Arc::new(Mutex::new(Recording {
+            started: now,
+            skipped: Duration::ZERO,
+            last_output: now,
+            partial: Vec::new(),
+            events: Vec::new(),
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Recording> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn marker(&self, label: &str) {
+        self.lock().push("m", label.to_string());
+    }
+
+    /// Move the clock forward without waiting.
+    fn skip(&self, gap: Duration) {
+        self.lock().skipped += gap;
+    }
+
+    /// Wait until nothing has been printed for `quiet`, or `limit` passes. Returns how long
+    /// that took.
+    fn settle(&self, quiet: Duration, limit: Duration) -> Duration {
+        let started = Instant::now();
+        while started.elapsed() < limit {
+            let last_output = self.lock().last_output.max(started);
+            if last_output.elapsed() >= quiet {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        started.elapsed()
+    }
+
+    fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut self.lock().events)
+    }
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut recording = self.lock();
+        recording.last_output = Instant::now();
+        recording.partial.extend_from_slice(buf);
+        // Hold back a character cut off at the end of the read until the rest arrives
+        let complete = match std::str::from_utf8(&recording.partial) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => recording.partial.len(),
+        };
+        if complete > 0 {
+            let bytes: Vec<u8> = recording.partial.drain(..complete).collect();
+            recording.push("o", String::from_utf8_lossy(&bytes).into_owned());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run `blocks` in a shell in a pseudo-terminal and record what it shows. Typing takes the
+/// typewriter delays, `[PAUSE n]` takes `n` seconds and `[PAUSE]` takes `options.pause`;
+/// `[WAIT]` and command output take as long as they really do. Each `[SAY]` becomes a
+/// marker. `[FOCUS]` and `[SLIDE]` have nothing to act on and are skipped.
+///
+/// `[EXEC]` runs as it does on the agent: in the background, or waited for when it has a
+/// timeout. Its output is thrown away and is not part of the recording.
+pub fn render(
+    blocks: &[ActionBlock],
+    front_matter: &FrontMatter,
+    options: &RenderOptions,
+) -> Result<Cast> {
+    let recorder = Recorder::new();
+    let mut session = PtySession::spawn(
+        options.shell.as_deref(),
+        options.cols,
+        options.rows,
+        recorder.clone(),
+    )?;
+    recorder.settle(options.settle, SETTLE_LIMIT);
+
+    let exec_shell = front_matter.executor.shell.as_deref().unwrap_or("sh");
+    for block in blocks {
+        if let Some(narration) = &block.narration {
+            recorder.marker(narration);
+        }
+        if let BlockType::Pause(timeout) = block.block_type {
+            recorder.skip(timeout.map_or(options.pause, Duration::from_secs));
+        }
+        for (action, location) in block.actions.iter().zip(&block.action_lines) {
+            perform(
+                action,
+                &mut session,
+                &recorder,
+                front_matter,
+                exec_shell,
+                options,
+            )
+            .map_err(|e| anyhow::anyhow!("{location}: {action} failed: {e}"))?;
+        }
+    }
+    recorder.settle(options.settle, SETTLE_LIMIT);
+    // Leave out whatever the shell prints as it is killed
+    let events = recorder.take_events();
+    drop(session);
+
+    Ok(Cast {
+        width: options.cols,
+        height: options.rows,
+        title: front_matter.title.clone(),
+        events,
+    })
+}
+
+/// The recording's clock: a keystroke waits for its echo and skips the rest of its delay,
+/// and an action that may print waits for the terminal to go quiet.
+struct Recorded<'a> {
+    recorder: &'a Recorder,
+    settle: Duration,
+}
+
+impl Pacing for Recorded<'_> {
+    fn keystroke(&mut self, delay: Duration) {
+        let echoed = self.recorder.settle(KEYSTROKE_QUIET, KEYSTROKE_LIMIT);
+        self.recorder.skip(delay.saturating_sub(echoed));
+    }
+
+    fn settle(&mut self) {
+        self.recorder.settle(self.settle, SETTLE_LIMIT);
+    }
+}
+
+fn perform(
+    action: &Directive,
+    session: &mut PtySession,
+    recorder: &Recorder,
+    front_matter: &FrontMatter,
+    exec_shell: &str,
+    options: &RenderOptions,
+) -> Result<()> {
+    let mut pacing = Recorded {
+        recorder,
+        settle: options.settle,
+    };
+    if let Directive::Exec(exec) = action {
+        // On stage the output goes to the agent's terminal, not the demo's
+        let mut shell = Command::new(exec_shell);
+        shell.stdout(Stdio::null()).stderr(Stdio::null());
+        return run_exec_with(exec, &mut shell);
+    }
+    perform_action(action, |action| match action {
+        // A recording has no slides to change
+        Directive::Slide(_) => Ok(()),
+        action => execute_pty_action(
+            action,
+            session,
+            front_matter.typing_speed,
+            front_matter.typing_variance,
+            &mut pacing,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_cast() {
+        let cast = Cast {
+            width: 80,
+            height: 24,
+            title: Some("Demo".into()),
+            events: vec![
+                Event {
+                    time: 0.0,
+                    code: "m",
+                    data: "Intro".into(),
+                },
+                Event {
+                    time: 1.25,
+                    code: "o",
+                    data: "$ ls\r\n".into(),
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        cast.write_to(&mut out).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["title"], "Demo");
+        assert_eq!(lines[1], serde_json::json!([0.0, "m", "Intro"]));
+        assert_eq!(lines[2], serde_json::json!([1.25, "o", "$ ls\r\n"]));
+        assert_eq!(cast.duration(), 1.25);
+    }
+
+    #[test]
+    fn test_recorder_keeps_characters_whole() {
+        let mut recorder = Recorder::new();
+        let bytes = "né".as_bytes();
+        recorder.write_all(&bytes[..2]).unwrap();
+        recorder.write_all(&bytes[2..]).unwrap();
+        let data: Vec<String> = recorder
+            .take_events()
+            .into_iter()
+            .map(|event| event.data)
+            .collect();
+        assert_eq!(data, ["n", "é"]);
+    }
+
+    #[test]
+    fn test_recorder_skips_time() {
+        let mut recorder = Recorder::new();
+        recorder.skip(Duration::from_secs(3));
+        recorder.write_all(b"x").unwrap();
+        let events = recorder.take_events();
+        assert!(events[0].time >= 3.0 && events[0].time < 4.0, "{events:?}");
+    }
+}
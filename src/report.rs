@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::diagnostics::Diagnostic;
+use crate::grouper::ActionBlock;
+use crate::parser::types::{FrontMatter, ParsedLine, Script};
+
+/// What `check --format json` and `present --dry-run --format json` print: the parsed
+/// script, its blocks and every diagnostic, for editors and CI.
+#[derive(Debug, Serialize)]
+pub struct ScriptReport<'a> {
+    pub file: &'a Path,
+    /// False if the script has parse errors or lint errors.
+    pub valid: bool,
+    /// `None` when the script could not be parsed.
+    pub front_matter: Option<&'a FrontMatter>,
+    pub directives: &'a [ParsedLine],
+    pub blocks: Vec<BlockReport<'a>>,
+    pub includes: &'a [PathBuf],
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// An [`ActionBlock`] with its 1-based position in the script, as shown by the dry run.
+#[derive(Debug, Serialize)]
+pub struct BlockReport<'a> {
+    pub number: usize,
+    #[serde(flatten)]
+    pub block: &'a ActionBlock,
+}
+
+impl<'a> ScriptReport<'a> {
+    /// A report on a script that parsed. Blocks before index `start` are left out, as in a
+    /// dry run with `--from-line`.
+    pub fn new(
+        file: &'a Path,
+        script: &'a Script,
+        blocks: &'a [ActionBlock],
+        start: usize,
+    ) -> Self {
+        let mut report = Self {
+            file,
+            valid: true,
+            front_matter: Some(&script.front_matter),
+            directives: &script.lines,
+            blocks: blocks
+                .iter()
+                .enumerate()
+                .skip(start)
+                .map(|(i, block)| BlockReport {
+                    number: i + 1,
+                    block,
+                })
+                .collect(),
+            includes: &script.includes,
+            diagnostics: Vec::new(),
+        };
+        report.add_diagnostics(script.warnings.iter().map(Diagnostic::warning));
+        report
+    }
+
+    /// A report on a script that failed to parse.
+    pub fn failed(file: &'a Path, diagnostics: impl IntoIterator<Item = Diagnostic>) -> Self {
+        let mut report = Self {
+            file,
+            valid: false,
+            front_matter: None,
+            directives: &[],
+            blocks: Vec::new(),
+            includes: &[],
+            diagnostics: Vec::new(),
+        };
+        report.add_diagnostics(diagnostics);
+        report
+    }
+
+    /// Add diagnostics, attributing any without a file to the script itself. An error
+    /// makes the report invalid.
+    pub fn add_diagnostics(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        for mut diagnostic in diagnostics {
+            diagnostic
+                .file
+                .get_or_insert_with(|| self.file.to_path_buf());
+            if diagnostic.severity == crate::diagnostics::Severity::Error {
+                self.valid = false;
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouper::group_into_blocks;
+    use crate::parser::{parse_script, parse_script_all};
+
+    #[test]
+    fn test_report_json() {
+        let script =
+            parse_script("---\ntitle: Demo\ncolour: red\n---\n[SAY] hi\n[TYPE] ls\n[RUN]").unwrap();
+        let blocks = group_into_blocks(&script);
+        let report = ScriptReport::new(Path::new("demo.cm"), &script, &blocks, 0);
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json["valid"], true);
+        assert_eq!(json["front_matter"]["title"], "Demo");
+        assert_eq!(json["front_matter"]["typing_speed"], 40);
+        assert_eq!(json["directives"][1]["line_number"], 6);
+        assert_eq!(json["directives"][1]["directive"]["Type"]["text"], "ls");
+        assert_eq!(json["blocks"][0]["number"], 1);
+        assert_eq!(json["blocks"][0]["narration"], "hi");
+        assert_eq!(json["blocks"][0]["lines"]["start"], 5);
+        // Unknown front matter keys are warnings, which leave the script valid
+        assert_eq!(json["diagnostics"][0]["severity"], "warning");
+        assert_eq!(json["diagnostics"][0]["file"], "demo.cm");
+    }
+
+    #[test]
+    fn test_report_skips_blocks_before_start() {
+        let script = parse_script("[SAY] a\n[PAUSE]\n[SAY] b\n[PAUSE]").unwrap();
+        let blocks = group_into_blocks(&script);
+        let report = ScriptReport::new(Path::new("x.cm"), &script, &blocks, 1);
+        assert_eq!(report.blocks.len(), 1);
+        assert_eq!(report.blocks[0].number, 2);
+    }
+
+    #[test]
+    fn test_failed_report() {
+        let errors = parse_script_all("[SLDIE]\n[WAIT x]").unwrap_err();
+        let report = ScriptReport::failed(Path::new("bad.cm"), errors.iter().map(Diagnostic::from));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["valid"], false);
+        assert!(json["front_matter"].is_null());
+        assert_eq!(json["diagnostics"].as_array().unwrap().len(), 2);
+        assert_eq!(json["diagnostics"][1]["line"], 2);
+    }
+}
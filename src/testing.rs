@@ -0,0 +1,42 @@
+//! Helpers shared by the unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A directory under the temp directory, removed with everything in it when the test ends.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// An empty directory named after the test, so tests running at once keep apart.
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("code-monkey-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// A directory holding `files`, given as paths relative to it and their contents.
+    pub(crate) fn with_files(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = Self::new(name);
+        for (rel, content) in files {
+            let path = dir.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
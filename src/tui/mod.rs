@@ -1,3 +1,5 @@
+mod picker;
+
 use std::io;
 use std::time::Duration;
 
@@ -8,9 +10,11 @@ use crossterm::terminal::{
     EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
 };
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
 
 use crate::client::{Presenter, StepResult};
+use crate::grouper::JumpKind;
+use picker::Picker;
 
 const MAX_AUTO_RECONNECT_ATTEMPTS: u32 = 3;
 
@@ -27,6 +31,8 @@ pub struct App {
     status_message: Option<String>,
     connection_state: ConnectionState,
     finished: bool,
+    /// The "go to" overlay, while it is open.
+    picker: Option<Picker>,
 }
 
 impl App {
@@ -42,6 +48,7 @@ impl App {
             status_message: None,
             connection_state,
             finished: false,
+            picker: None,
         }
     }
 }
@@ -66,120 +73,140 @@ pub fn run_tui(app: &mut App) -> Result<()> {
         terminal.draw(|frame| ui(frame, app))?;
 
         // Poll with timeout for responsive updates
-        if event::poll(Duration::from_millis(250))? {
-            if let Event::Key(key) = event::read()? {
+        if event::poll(Duration::from_millis(250))?
+            && let Event::Key(key) = event::read()?
+        {
+            if let Some(picker) = &mut app.picker {
                 match key.code {
-                    KeyCode::Char('q') => {
-                        app.should_quit = true;
+                    KeyCode::Esc => app.picker = None,
+                    KeyCode::Enter => {
+                        if let Some(target) = picker.selection().cloned() {
+                            app.presenter.jump_to(&target);
+                            app.status_message = Some(format!("Jumped to {}", target.name));
+                            app.finished = false;
+                        }
+                        app.picker = None;
                     }
-                    KeyCode::Char('b') => {
-                        app.presenter.go_back();
-                        app.status_message = None;
+                    KeyCode::Up => picker.up(),
+                    KeyCode::Down => picker.down(),
+                    KeyCode::Backspace => picker.pop(),
+                    KeyCode::Char(ch) => picker.push(ch),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') => {
+                    app.should_quit = true;
+                }
+                KeyCode::Char('b') => {
+                    app.presenter.go_back();
+                    app.status_message = None;
+                    app.finished = false;
+                }
+                KeyCode::Char('s') => {
+                    // Skip current block (useful when agent is not responding)
+                    app.presenter.skip();
+                    app.status_message = None;
+                }
+                KeyCode::Char('g') => {
+                    app.picker = Some(Picker::new(app.presenter.jump_targets()));
+                }
+                KeyCode::Char('j') => {
+                    // Take the [SKIP_TO] branch instead of running the block
+                    if let Some(label) = app.presenter.take_branch() {
+                        app.status_message = Some(format!("Skipped to {label}"));
                         app.finished = false;
                     }
-                    KeyCode::Char('s') => {
-                        // Skip current block (useful when agent is not responding)
-                        app.presenter.skip();
-                        app.status_message = None;
+                }
+                KeyCode::Enter => {
+                    if app.finished {
+                        app.should_quit = true;
+                        continue;
                     }
-                    KeyCode::Enter => {
-                        if app.finished {
-                            app.should_quit = true;
-                            continue;
-                        }
 
-                        if app.connection_state != ConnectionState::Connected {
-                            // Try reconnecting
-                            match app.presenter.connect() {
-                                Ok(()) => {
-                                    app.connection_state = ConnectionState::Connected;
-                                    app.status_message = Some("Reconnected!".into());
-                                }
-                                Err(e) => {
-                                    app.status_message =
-                                        Some(format!("Reconnection failed: {e}"));
-                                    continue;
-                                }
+                    if app.connection_state != ConnectionState::Connected {
+                        // Try reconnecting
+                        match app.presenter.connect() {
+                            Ok(()) => {
+                                app.connection_state = ConnectionState::Connected;
+                                app.status_message = Some("Reconnected!".into());
+                            }
+                            Err(e) => {
+                                app.status_message = Some(format!("Reconnection failed: {e}"));
+                                continue;
                             }
                         }
+                    }
 
-                        app.status_message = Some("Executing...".into());
-                        terminal.draw(|frame| ui(frame, app))?;
+                    app.status_message = Some("Executing...".into());
+                    terminal.draw(|frame| ui(frame, app))?;
 
-                        match app.presenter.step() {
-                            Ok(StepResult::Executed) => {
-                                app.status_message = None;
-                            }
-                            Ok(StepResult::NarrationOnly) => {
-                                app.status_message = None;
-                            }
-                            Ok(StepResult::Paused(None)) => {
-                                app.status_message = None;
-                                // Just advance — the next Enter will handle the next block
+                    match app.presenter.step() {
+                        Ok(StepResult::Executed) => {
+                            app.status_message = None;
+                        }
+                        Ok(StepResult::NarrationOnly) => {
+                            app.status_message = None;
+                        }
+                        Ok(StepResult::Paused(None)) => {
+                            app.status_message = None;
+                            // Just advance — the next Enter will handle the next block
+                        }
+                        Ok(StepResult::Paused(Some(secs))) => {
+                            app.status_message = Some(format!("Waiting {secs} seconds..."));
+                            terminal.draw(|frame| ui(frame, app))?;
+                            // Wait with interruptible polling
+                            let deadline = std::time::Instant::now() + Duration::from_secs(secs);
+                            while std::time::Instant::now() < deadline {
+                                if event::poll(Duration::from_millis(100))?
+                                    && let Event::Key(k) = event::read()?
+                                    && (k.code == KeyCode::Enter || k.code == KeyCode::Char('q'))
+                                {
+                                    break;
+                                }
                             }
-                            Ok(StepResult::Paused(Some(secs))) => {
-                                app.status_message =
-                                    Some(format!("Waiting {secs} seconds..."));
+                            app.status_message = None;
+                        }
+                        Ok(StepResult::Finished) => {
+                            app.finished = true;
+                            app.status_message =
+                                Some("Presentation complete! Press Enter or q to exit.".into());
+                        }
+                        Ok(StepResult::AgentError(msg)) => {
+                            app.status_message =
+                                Some(format!("Agent error: {msg} (Enter=retry, s=skip)"));
+                        }
+                        Ok(StepResult::ConnectionLost) => {
+                            // Auto-reconnect loop
+                            let mut reconnected = false;
+                            for attempt in 1..=MAX_AUTO_RECONNECT_ATTEMPTS {
+                                app.connection_state = ConnectionState::Reconnecting(attempt);
+                                app.status_message = Some(format!(
+                                    "Connection lost. Reconnecting ({attempt}/{MAX_AUTO_RECONNECT_ATTEMPTS})..."
+                                ));
                                 terminal.draw(|frame| ui(frame, app))?;
-                                // Wait with interruptible polling
-                                let deadline =
-                                    std::time::Instant::now() + Duration::from_secs(secs);
-                                while std::time::Instant::now() < deadline {
-                                    if event::poll(Duration::from_millis(100))? {
-                                        if let Event::Key(k) = event::read()? {
-                                            if k.code == KeyCode::Enter
-                                                || k.code == KeyCode::Char('q')
-                                            {
-                                                break;
-                                            }
-                                        }
-                                    }
+                                std::thread::sleep(Duration::from_secs(1));
+                                if app.presenter.connect().is_ok() {
+                                    app.connection_state = ConnectionState::Connected;
+                                    app.status_message = Some("Reconnected!".into());
+                                    reconnected = true;
+                                    break;
                                 }
-                                app.status_message = None;
-                            }
-                            Ok(StepResult::Finished) => {
-                                app.finished = true;
-                                app.status_message = Some(
-                                    "Presentation complete! Press Enter or q to exit.".into(),
-                                );
                             }
-                            Ok(StepResult::AgentError(msg)) => {
+                            if !reconnected {
+                                app.connection_state = ConnectionState::Disconnected;
                                 app.status_message =
-                                    Some(format!("Agent error: {msg} (Enter=retry, s=skip)"));
-                            }
-                            Ok(StepResult::ConnectionLost) => {
-                                // Auto-reconnect loop
-                                let mut reconnected = false;
-                                for attempt in 1..=MAX_AUTO_RECONNECT_ATTEMPTS {
-                                    app.connection_state =
-                                        ConnectionState::Reconnecting(attempt);
-                                    app.status_message = Some(format!(
-                                        "Connection lost. Reconnecting ({attempt}/{MAX_AUTO_RECONNECT_ATTEMPTS})..."
-                                    ));
-                                    terminal.draw(|frame| ui(frame, app))?;
-                                    std::thread::sleep(Duration::from_secs(1));
-                                    if app.presenter.connect().is_ok() {
-                                        app.connection_state = ConnectionState::Connected;
-                                        app.status_message =
-                                            Some("Reconnected!".into());
-                                        reconnected = true;
-                                        break;
-                                    }
-                                }
-                                if !reconnected {
-                                    app.connection_state = ConnectionState::Disconnected;
-                                    app.status_message = Some(
-                                        "Connection lost. Press Enter to reconnect.".into(),
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                app.status_message = Some(format!("Error: {e}"));
+                                    Some("Connection lost. Press Enter to reconnect.".into());
                             }
                         }
+                        Err(e) => {
+                            app.status_message = Some(format!("Error: {e}"));
+                        }
                     }
-                    _ => {}
                 }
+                _ => {}
             }
         }
     }
@@ -194,29 +221,38 @@ pub fn run_tui(app: &mut App) -> Result<()> {
 fn ui(frame: &mut Frame, app: &App) {
     let area = frame.area();
 
-    // Layout: title, connection, narration, actions, status, footer
+    let (current, total) = app.presenter.progress();
+    let block = app.presenter.current_block();
+    let notes = block.map(|b| b.notes.as_slice()).unwrap_or_default();
+    // The notes pane only takes room when the block has notes
+    let notes_height = if notes.is_empty() {
+        0
+    } else {
+        notes.len() as u16 + 2
+    };
+
+    // Layout: title, connection, narration, notes, actions, status, footer
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3), // title + connection
-            Constraint::Min(5),    // narration
-            Constraint::Length(8), // actions
-            Constraint::Length(3), // status
-            Constraint::Length(1), // footer
+            Constraint::Length(3),            // title + connection
+            Constraint::Min(5),               // narration
+            Constraint::Length(notes_height), // notes
+            Constraint::Length(8),            // actions
+            Constraint::Length(3),            // status
+            Constraint::Length(1),            // footer
         ])
         .split(area);
 
     // Title bar with progress
-    let (current, total) = app.presenter.progress();
-    let block = app.presenter.current_block();
-    let section = block.and_then(|b| b.section.as_deref()).unwrap_or("");
+    let breadcrumb = block.map(|b| b.breadcrumb()).unwrap_or_default();
 
     let title_text = format!(
         "  {}   [{} / {}]   {}",
         "Code Monkey",
         current + 1,
         total,
-        section
+        breadcrumb
     );
 
     let reconnecting_label;
@@ -250,6 +286,24 @@ fn ui(frame: &mut Frame, app: &App) {
         );
     frame.render_widget(narration, chunks[1]);
 
+    // Notes pane, dim so it never reads as something to say aloud
+    if !notes.is_empty() {
+        let notes = Paragraph::new(notes.join("\n"))
+            .style(
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::DIM),
+            )
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .title(" NOTES ")
+                    .title_style(Style::default().fg(Color::DarkGray))
+                    .borders(Borders::ALL),
+            );
+        frame.render_widget(notes, chunks[2]);
+    }
+
     // Actions pane
     let actions_text = if let Some(block) = block {
         match &block.block_type {
@@ -269,15 +323,24 @@ fn ui(frame: &mut Frame, app: &App) {
         "(end of presentation)".into()
     };
 
+    let actions_text = match block.and_then(|b| b.skip_to.as_deref()) {
+        Some(label) => format!("{actions_text}\n  ↪ [SKIP_TO {label}] (j = take this branch)"),
+        None => actions_text,
+    };
+
+    let actions_title = match block.and_then(|b| b.lines) {
+        Some(lines) => format!(" NEXT ACTION ({lines}) "),
+        None => " NEXT ACTION ".to_string(),
+    };
     let actions = Paragraph::new(actions_text)
         .style(Style::default().fg(Color::Cyan))
         .block(
             Block::default()
-                .title(" NEXT ACTION ")
+                .title(actions_title)
                 .title_style(Style::default().fg(Color::Yellow))
                 .borders(Borders::ALL),
         );
-    frame.render_widget(actions, chunks[2]);
+    frame.render_widget(actions, chunks[3]);
 
     // Status bar
     let status_text = app.status_message.as_deref().unwrap_or("");
@@ -292,10 +355,52 @@ fn ui(frame: &mut Frame, app: &App) {
     let status = Paragraph::new(status_text)
         .style(status_style)
         .block(Block::default().borders(Borders::ALL));
-    frame.render_widget(status, chunks[3]);
+    frame.render_widget(status, chunks[4]);
 
     // Footer
-    let footer_text = "  Enter = execute  │  b = back  │  s = skip  │  q = quit";
+    let footer_text = "  Enter = execute  │  b = back  │  s = skip  │  g = go to  │  q = quit";
     let footer = Paragraph::new(footer_text).style(Style::default().fg(Color::DarkGray));
-    frame.render_widget(footer, chunks[4]);
+    frame.render_widget(footer, chunks[5]);
+
+    if let Some(picker) = &app.picker {
+        render_picker(frame, picker);
+    }
+}
+
+/// The "go to" overlay, centred over the narration and actions.
+fn render_picker(frame: &mut Frame, picker: &Picker) {
+    let area = frame.area();
+    let width = area.width.saturating_sub(8).min(60);
+    let height = area.height.saturating_sub(4).min(16);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let items: Vec<ListItem> = picker
+        .matches()
+        .iter()
+        .map(|target| {
+            let kind = match target.kind {
+                JumpKind::Label => "label",
+                JumpKind::Heading(level) => level.keyword(),
+            };
+            ListItem::new(format!("{:<8} {}", kind.to_lowercase(), target.name))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan))
+        .block(
+            Block::default()
+                .title(format!(" GO TO: {}_ ", picker.query()))
+                .title_style(Style::default().fg(Color::Yellow))
+                .title_bottom(" Enter = jump  │  Esc = cancel ")
+                .borders(Borders::ALL),
+        );
+    let mut state = ListState::default().with_selected(Some(picker.selected()));
+
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(list, popup, &mut state);
 }
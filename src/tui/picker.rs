@@ -0,0 +1,134 @@
+use crate::grouper::JumpTarget;
+
+/// The "go to" overlay: labels and sections filtered by a fuzzy query.
+pub struct Picker {
+    query: String,
+    targets: Vec<JumpTarget>,
+    /// Index into [`Picker::matches`].
+    selected: usize,
+}
+
+impl Picker {
+    pub fn new(targets: Vec<JumpTarget>) -> Self {
+        Self {
+            query: String::new(),
+            targets,
+            selected: 0,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.query.push(ch);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.matches().len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Targets matching the query, best first. Equally good matches keep script order.
+    pub fn matches(&self) -> Vec<&JumpTarget> {
+        let mut scored: Vec<(usize, &JumpTarget)> = self
+            .targets
+            .iter()
+            .filter_map(|t| Some((fuzzy_score(&self.query, &t.name)?, t)))
+            .collect();
+        scored.sort_by_key(|(score, _)| *score);
+        scored.into_iter().map(|(_, t)| t).collect()
+    }
+
+    pub fn selection(&self) -> Option<&JumpTarget> {
+        self.matches().get(self.selected).copied()
+    }
+}
+
+/// How well `query` matches `candidate`, lower being better, or `None` if the characters
+/// of `query` do not all appear in order. Case is ignored. The score is how late the match
+/// starts plus the gaps inside it, so prefixes and unbroken runs rank first.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    for (i, ch) in query.to_lowercase().chars().enumerate() {
+        let found = candidate[pos..].iter().position(|&c| c == ch)?;
+        // Skipping ahead to the first character costs as much as a gap
+        score += if i == 0 { found } else { found * 2 };
+        pos += found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouper::JumpKind;
+
+    fn picker(names: &[&str]) -> Picker {
+        Picker::new(
+            names
+                .iter()
+                .enumerate()
+                .map(|(block, name)| JumpTarget {
+                    kind: JumpKind::Label,
+                    name: name.to_string(),
+                    block,
+                })
+                .collect(),
+        )
+    }
+
+    fn names(picker: &Picker) -> Vec<&str> {
+        picker.matches().iter().map(|t| t.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("back", "backup"), Some(0));
+        // Gaps of two and one after the first character
+        assert_eq!(fuzzy_score("BKP", "backup"), Some(2 * 2 + 2));
+        assert_eq!(fuzzy_score("up", "backup"), Some(4));
+        assert_eq!(fuzzy_score("pu", "backup"), None);
+    }
+
+    #[test]
+    fn test_picker_filters_and_ranks() {
+        let mut picker = picker(&["intro", "build", "backup-build", "rebuild"]);
+        assert_eq!(names(&picker).len(), 4);
+
+        for ch in "bu".chars() {
+            picker.push(ch);
+        }
+        assert_eq!(names(&picker), ["build", "rebuild", "backup-build"]);
+        picker.push('p');
+        assert_eq!(names(&picker), ["backup-build"]);
+        picker.down();
+        assert_eq!(picker.selected(), 0);
+
+        picker.pop();
+        picker.down();
+        picker.down();
+        assert_eq!(picker.selection().unwrap().name, "backup-build");
+        picker.up();
+        assert_eq!(picker.selection().unwrap().block, 3);
+    }
+}
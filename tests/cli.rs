@@ -1,3 +1,4 @@
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn cargo_bin() -> Command {
@@ -6,6 +7,38 @@ fn cargo_bin() -> Command {
     cmd
 }
 
+/// A file in the temp directory, removed when the test ends.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("code-monkey-cli-{}-{name}", std::process::id())))
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Write `contents` to `name.cm` in the temp directory.
+fn script(name: &str, contents: &str) -> TempFile {
+    let file = TempFile::new(&format!("{name}.cm"));
+    std::fs::write(&file, contents).unwrap();
+    file
+}
+
 #[test]
 fn test_cli_no_subcommand_shows_help() {
     let output = cargo_bin().output().unwrap();
@@ -31,6 +64,350 @@ fn test_cli_check_validates_script() {
     assert!(stdout.contains("action blocks"));
 }
 
+#[test]
+fn test_cli_check_resolves_includes() {
+    let output = cargo_bin()
+        .args(["check", "examples/composed.cm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("is valid"));
+    assert!(stdout.contains("reset-terminal.cm"));
+}
+
+#[test]
+fn test_cli_dry_run_applies_var_overrides() {
+    let output = cargo_bin()
+        .args([
+            "present",
+            "--dry-run",
+            "--var",
+            "project=other-demo",
+            "examples/vars.cm",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("cargo new other-demo"), "got: {stdout}");
+    assert!(stdout.contains("Visual Studio Code"));
+}
+
+#[test]
+fn test_cli_check_reports_macros() {
+    let output = cargo_bin()
+        .args(["check", "examples/macros.cm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Macros: 1 defined, 2 expanded"),
+        "got: {stdout}"
+    );
+}
+
+#[test]
+fn test_cli_check_reports_all_errors() {
+    let path = script(
+        "errors",
+        "[SAY] ok\n[BOGUS]\n[WAIT soon]\n[SLIDE sideways]\n",
+    );
+    let output = cargo_bin().args(["check", path.path()]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line2 = stderr.find("errors.cm:2:").expect("line 2 reported");
+    let line3 = stderr.find("errors.cm:3:").expect("line 3 reported");
+    let line4 = stderr.find("errors.cm:4:").expect("line 4 reported");
+    assert!(
+        line2 < line3 && line3 < line4,
+        "errors out of order: {stderr}"
+    );
+    assert!(stderr.contains("3 parse error(s)"));
+}
+
+#[test]
+fn test_cli_check_renders_diagnostics() {
+    let path = script("typo", "[SAY] ok\n[SLDIE] next\n");
+    let output = cargo_bin().args(["check", path.path()]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Not a terminal, so no colour codes
+    assert!(!stderr.contains('\u{1b}'), "{stderr}");
+    assert!(stderr.contains("error: Unknown directive: [SLDIE]"));
+    assert!(stderr.contains("2 | [SLDIE] next"));
+    assert!(stderr.contains("  |  ^^^^^"));
+    assert!(stderr.contains("= help: did you mean SLIDE?"));
+}
+
+#[test]
+fn test_cli_check_strict_front_matter() {
+    let path = script(
+        "strict",
+        "---\ntitle: C# Tips\ncolour: red\n---\n[SAY] hi\n",
+    );
+
+    let output = cargo_bin().args(["check", path.path()]).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("Title: C# Tips"), "{stdout}");
+    assert!(
+        stderr.contains("warning: Unknown front matter key 'colour'"),
+        "{stderr}"
+    );
+
+    let output = cargo_bin()
+        .args(["check", "--strict", path.path()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: Unknown front matter key 'colour'"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_check_lint() {
+    let path = script(
+        "lint",
+        "[FOCUS] Terminal\n[RUN]\n# cm-lint: allow(dangerous-exec)\n[EXEC] sudo true\n",
+    );
+    let output = cargo_bin()
+        .args(["check", "--lint", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("Lint: 1 warning(s), 0 error(s)"),
+        "{stdout}"
+    );
+    assert!(stderr.contains("warning[run-after-focus]"), "{stderr}");
+    assert!(stderr.contains("lint.cm:2:1"), "{stderr}");
+
+    std::fs::write(&path, "[EXEC] sudo true\n").unwrap();
+    let output = cargo_bin()
+        .args(["check", "--lint", path.path()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error[dangerous-exec]"), "{stderr}");
+}
+
+#[test]
+fn test_cli_fmt() {
+    let path = script("fmt", "# intro\n[say]  hi\n\n\n[RUN]\n");
+
+    let output = cargo_bin()
+        .args(["fmt", "--check", path.path()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("fmt.cm:2: not formatted"), "{stdout}");
+
+    let output = cargo_bin().args(["fmt", path.path()]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "# intro\n[SAY] hi\n\n[RUN]\n"
+    );
+
+    let output = cargo_bin()
+        .args(["fmt", "--check", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_cli_examples_are_formatted() {
+    let output = cargo_bin()
+        .args([
+            "fmt",
+            "--check",
+            "examples/demo.cm",
+            "examples/macros.cm",
+            "examples/vars.cm",
+            "examples/composed.cm",
+            "examples/shared/reset-terminal.cm",
+            "examples/applescript-actions.cm",
+            "examples/snippets.cm",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn test_cli_dry_run_from_line() {
+    let output = cargo_bin()
+        .args([
+            "present",
+            "--dry-run",
+            "--from-line",
+            "18",
+            "examples/macros.cm",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("--- Block 2 "), "{stdout}");
+    assert!(stdout.contains("--- Block 3 (lines 21-23) ---"), "{stdout}");
+
+    let output = cargo_bin()
+        .args([
+            "present",
+            "--dry-run",
+            "--from-line",
+            "9999",
+            "examples/macros.cm",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_check_json() {
+    let output = cargo_bin()
+        .args(["check", "--format", "json", "examples/macros.cm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["valid"], true);
+    assert_eq!(report["front_matter"]["title"], "Macros Demo");
+    assert_eq!(report["directives"][0]["line_number"], 12);
+    assert!(report["blocks"].as_array().unwrap().len() > 1);
+
+    let path = script("json", "[SAY] ok\n[SLDIE] next\n");
+    let output = cargo_bin()
+        .args(["check", "--format", "json", path.path()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["valid"], false);
+    let diagnostic = &report["diagnostics"][0];
+    assert_eq!(diagnostic["severity"], "error");
+    assert_eq!(diagnostic["line"], 2);
+    assert_eq!(diagnostic["help"], "did you mean SLIDE?");
+    // Nothing human-readable goes to stderr beyond the final error line
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("[SLDIE] next"), "{stderr}");
+}
+
+#[test]
+fn test_cli_dry_run_json() {
+    let output = cargo_bin()
+        .args([
+            "present",
+            "--dry-run",
+            "--format",
+            "json",
+            "--from-line",
+            "18",
+            "examples/macros.cm",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["blocks"][0]["number"], 3);
+    assert_eq!(report["blocks"][0]["lines"]["start"], 21);
+
+    let output = cargo_bin()
+        .args(["present", "--format", "json", "examples/macros.cm"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_lsp_handshake() {
+    use std::io::Write;
+
+    let mut child = cargo_bin()
+        .arg("lsp")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        write!(stdin, "Content-Length: {}\r\n\r\n{message}", message.len()).unwrap();
+    }
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"hoverProvider\":true"), "{stdout}");
+    assert!(stdout.contains("\"definitionProvider\":true"), "{stdout}");
+}
+
+#[test]
+fn test_cli_estimate() {
+    let output = cargo_bin()
+        .args(["estimate", "examples/macros.cm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Section "), "{stdout}");
+    assert!(stdout.contains("\nBuild "), "{stdout}");
+    assert!(stdout.contains("\nTotal "), "{stdout}");
+
+    let path = script("estimate", "---\nduration: 1m\n---\n[WAIT 75]\n");
+    let output = cargo_bin()
+        .args(["estimate", "--format", "json", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let estimate: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(estimate["total_ms"], 75_000);
+    assert_eq!(estimate["over_duration_ms"], 15_000);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: estimated 1:15 is 0:15 over the planned duration of 1:00"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_check_type_file() {
+    let output = cargo_bin()
+        .args(["check", "examples/snippets.cm"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let path = script("type-file", "[TYPE file=no-such-file.rs region=setup]\n");
+    let output = cargo_bin().args(["check", path.path()]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cannot read file"), "{stderr}");
+    assert!(stderr.contains("no-such-file.rs"), "{stderr}");
+}
+
 #[test]
 fn test_cli_check_missing_file_errors() {
     let output = cargo_bin()
@@ -52,6 +429,115 @@ fn test_cli_present_dry_run() {
     assert!(stdout.contains("Block"));
 }
 
+#[test]
+fn test_cli_dry_run_notes() {
+    let path = script("notes", "[SAY] Build it\n[NOTE] it is slow\n[EXEC make]\n");
+    let dry_run = |extra: &[&str]| {
+        let output = cargo_bin()
+            .args(["present", "--dry-run", path.path()])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let stdout = dry_run(&[]);
+    assert!(
+        stdout.contains("[SAY] Build it\n  [NOTE] it is slow\n  [EXEC make]"),
+        "{stdout}"
+    );
+    let stdout = dry_run(&["--hide-notes"]);
+    assert!(!stdout.contains("[NOTE]"), "{stdout}");
+    let stdout = dry_run(&["--hide-notes", "--format", "json"]);
+    assert!(!stdout.contains("it is slow"), "{stdout}");
+}
+
+#[test]
+fn test_cli_lang() {
+    let path = script(
+        "lang",
+        "[SAY] Hello\n[SAY:de] Hallo\n[RUN]\n[SAY] Bye\n[NOTE] wave\n[RUN]\n",
+    );
+    let output = cargo_bin()
+        .args(["present", "--dry-run", "--lang", "DE", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[SAY] Hallo\n"), "{stdout}");
+    assert!(stdout.contains("[SAY] Bye\n  [NOTE] wave"), "{stdout}");
+
+    let output = cargo_bin()
+        .args(["check", "--lang", "de", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: Block 2 has no [SAY:de] or [NOTE:de]"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("Block 1"), "{stderr}");
+
+    let output = cargo_bin()
+        .args(["check", "--lang", "de", "--strict", path.path()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_dry_run_labels() {
+    let path = script(
+        "labels",
+        "[SAY] live\n[SKIP_TO prebuilt]\n[RUN]\n[LABEL prebuilt]\n[SAY] backup\n",
+    );
+    let output = cargo_bin()
+        .args(["present", "--dry-run", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("[SKIP_TO prebuilt] (presenter may take this branch)"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("[LABEL prebuilt]\n  [SAY] backup"),
+        "{stdout}"
+    );
+
+    std::fs::write(&path, "[SKIP_TO nowhere]\n[RUN]\n").unwrap();
+    let output = cargo_bin().args(["check", path.path()]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown label 'nowhere'"), "{stderr}");
+}
+
+#[test]
+fn test_cli_dry_run_headings() {
+    let path = script(
+        "headings",
+        "# Chapter: One\n## Section: Build\n[RUN]\n[PAUSE]\n### Step: Test\n[RUN]\n",
+    );
+    let output = cargo_bin()
+        .args(["present", "--dry-run", path.path()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("# Chapter: One\n\n## Section: Build\n\n--- Block 1"),
+        "{stdout}"
+    );
+    // Only the step changes before the third block
+    assert!(
+        stdout.contains("(wait for Enter)\n\n### Step: Test\n\n--- Block 3"),
+        "{stdout}"
+    );
+}
+
 #[test]
 fn test_cli_present_requires_agent_without_dry_run() {
     let output = cargo_bin()
@@ -79,3 +565,144 @@ fn test_cli_present_invalid_agent_address() {
         "Expected address parse error, got: {stderr}"
     );
 }
+
+#[test]
+fn test_cli_agent_unknown_executor() {
+    let output = cargo_bin()
+        .args(["agent", "--executor", "osascript", "examples/demo.cm"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Unknown executor 'osascript' (expected one of applescript, linux"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_render() {
+    let script = script(
+        "render",
+        "---\ntitle: Render\ntyping_speed: 10\n---\n\
+         [SAY] Some arithmetic\n[TYPE] echo $((6*7))\n[RUN]\n[PAUSE]\n[SAY] Done\n",
+    );
+    let cast = TempFile::new("render.cast");
+    let output = cargo_bin()
+        .args(["render", script.path(), "-o", cast.path()])
+        .args(["--pause", "5", "--settle", "200", "--cols", "100"])
+        .args(["--shell", "env PS1='$ ' bash --norc --noprofile"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Rendered "), "{stdout}");
+
+    let text = std::fs::read_to_string(&cast).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines[0]["version"], 2);
+    assert_eq!(lines[0]["width"], 100);
+    assert_eq!(lines[0]["title"], "Render");
+    let events = &lines[1..];
+    let times: Vec<f64> = events.iter().map(|e| e[0].as_f64().unwrap()).collect();
+    assert!(times.windows(2).all(|w| w[0] <= w[1]), "{text}");
+
+    let marker = |label: &str| {
+        events
+            .iter()
+            .find(|e| e[1] == "m" && e[2] == label)
+            .unwrap_or_else(|| panic!("no marker {label}: {text}"))[0]
+            .as_f64()
+            .unwrap()
+    };
+    let output: String = events
+        .iter()
+        .filter(|e| e[1] == "o")
+        .map(|e| e[2].as_str().unwrap())
+        .collect();
+    assert!(output.contains("echo $((6*7))\r\n"), "{text}");
+    assert!(output.contains("42\r\n"), "{text}");
+    // The [PAUSE] is a five second gap, with no real wait behind it
+    assert!(marker("Done") - marker("Some arithmetic") >= 5.0, "{text}");
+}
+
+#[test]
+fn test_cli_render_exec() {
+    let script = script(
+        "render-exec",
+        "[SAY] Start\n[EXEC] echo started\n[EXEC timeout=5] sleep 1; echo waited\n[SAY] Done\n",
+    );
+    let cast = TempFile::new("render-exec.cast");
+    let output = cargo_bin()
+        .args(["render", script.path(), "-o", cast.path(), "--settle", "50"])
+        .args(["--shell", "env PS1='$ ' bash --norc --noprofile"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    // The commands' output is neither recorded nor printed
+    let text = std::fs::read_to_string(&cast).unwrap();
+    for printed in ["started", "waited"] {
+        assert!(!text.contains(printed), "{text}");
+        assert!(!stdout.contains(printed), "{stdout}");
+        assert!(!stderr.contains(printed), "{stderr}");
+    }
+
+    // The command with a timeout is waited for
+    let marker = |label: &str| {
+        text.lines()
+            .skip(1)
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|e| e[1] == "m" && e[2] == label)
+            .unwrap_or_else(|| panic!("no marker {label}: {text}"))[0]
+            .as_f64()
+            .unwrap()
+    };
+    assert!(marker("Done") - marker("Start") >= 1.0, "{text}");
+}
+
+#[test]
+fn test_cli_agent_log_actions_with_pty() {
+    let script = script("log-pty", "---\nexecutor:\n  kind: pty\n---\n[TYPE] ls\n");
+    for args in [
+        vec![
+            "agent",
+            "--executor",
+            "pty",
+            "--log-actions",
+            "examples/demo.cm",
+        ],
+        vec!["agent", "--log-actions", script.path()],
+    ] {
+        let output = cargo_bin().args(&args).output().unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("--log-actions would print over the pty executor's terminal"),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn test_cli_agent_record_unwritable() {
+    let output = cargo_bin()
+        .args(["agent", "--executor", "dry-run", "--log-actions"])
+        .args(["--record", "/nonexistent/actions.jsonl", "examples/demo.cm"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Cannot write /nonexistent/actions.jsonl"),
+        "{stderr}"
+    );
+}
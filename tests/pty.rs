@@ -0,0 +1,95 @@
+//! The pty executor against bash in a pseudo-terminal, checked through its output.
+
+use std::time::{Duration, Instant};
+
+use code_monkey::agent::pty::PtySession;
+use code_monkey::agent::{ActionExecutor, PtyExecutor, SharedBuffer};
+use code_monkey::parser::types::Directive;
+
+/// bash without the user's settings, and with a prompt the tests can look for.
+const SHELL: &str = "env PS1='$ ' bash --norc --noprofile";
+
+/// Wait for `output` to contain `expected`, returning it either way.
+fn wait_for(output: &SharedBuffer, expected: &str) -> String {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let text = output.text();
+        if text.contains(expected) || Instant::now() > deadline {
+            return text;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn start() -> (PtyExecutor, SharedBuffer) {
+    let output = SharedBuffer::default();
+    let session = PtySession::spawn(Some(SHELL), 80, 24, output.clone()).unwrap();
+    wait_for(&output, "$ ");
+    (PtyExecutor::new(session), output)
+}
+
+#[test]
+fn test_pty_types_and_runs() {
+    let (executor, output) = start();
+    executor
+        .execute(
+            &[
+                Directive::Type("echo hello-$((6*7)); echo done".into()),
+                Directive::Run,
+            ],
+            0,
+            0,
+        )
+        .unwrap();
+    let text = wait_for(&output, "done\r\n");
+    assert!(text.contains("echo hello-$((6*7)); echo done"), "{text}");
+    assert!(text.contains("hello-42\r\ndone\r\n"), "{text}");
+}
+
+#[test]
+fn test_pty_keys() {
+    let (executor, output) = start();
+    let run = |actions: &[Directive], expected: &str| {
+        executor.execute(actions, 5, 0).unwrap();
+        let text = wait_for(&output, expected);
+        assert!(text.contains(expected), "{text}");
+    };
+    run(
+        &[Directive::Type("sleep 30{return}".into())],
+        "sleep 30\r\n",
+    );
+    run(&[Directive::Key("ctrl+c".into())], "^C");
+    run(&[Directive::Type("echo one\n".into())], "one\r\n");
+    run(
+        &[
+            Directive::Key("up".into()),
+            Directive::Key("backspace".into()),
+            Directive::Type("e-again\n".into()),
+        ],
+        "one-again\r\n",
+    );
+}
+
+#[test]
+fn test_pty_reports_the_failing_action() {
+    let (executor, _output) = start();
+    let err = executor
+        .execute(
+            &[
+                Directive::Focus("Terminal".into()),
+                Directive::Key("cmd+k".into()),
+            ],
+            0,
+            0,
+        )
+        .unwrap_err();
+    let failed = err
+        .downcast_ref::<code_monkey::agent::ActionFailed>()
+        .unwrap();
+    assert_eq!(failed.index, 1);
+    assert!(
+        failed.message.contains("no Command key"),
+        "{}",
+        failed.message
+    );
+}
@@ -0,0 +1,181 @@
+//! The tmux executor against a private tmux server, checked with `capture-pane`. These need
+//! tmux, so they only run with `cargo test -- --ignored`.
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use code_monkey::agent::linux::run_command;
+use code_monkey::agent::tmux::Tmux;
+use code_monkey::agent::{ActionExecutor, TmuxExecutor};
+use code_monkey::parser::types::Directive;
+
+/// bash without the user's settings, and with a prompt the tests can look for.
+const SHELL: &str = "env PS1='$ ' bash --norc --noprofile";
+
+/// A tmux server on its own socket, with a `demo` session running bash. Killed on drop.
+struct Server {
+    socket: String,
+}
+
+impl Server {
+    fn start(name: &str) -> Self {
+        Command::new("tmux")
+            .arg("-V")
+            .output()
+            .expect("these tests need tmux installed");
+        let socket = format!("code-monkey-test-{}-{name}", std::process::id());
+        let status = Command::new("tmux")
+            .args(["-L", &socket, "-f", "/dev/null", "new-session", "-d"])
+            .args(["-s", "demo", "-x", "80", "-y", "24", SHELL])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        Self { socket }
+    }
+
+    fn tmux(&self, target: &str) -> Tmux {
+        Tmux::new(target).with_socket(&self.socket)
+    }
+
+    fn executor(&self) -> TmuxExecutor {
+        TmuxExecutor::new(self.tmux("demo"))
+    }
+
+    /// Wait for the pane to show `expected`, returning its contents either way.
+    fn wait_for(&self, target: &str, expected: &str) -> String {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let pane = run_command(&self.tmux(target).capture_pane_command()).unwrap();
+            if pane.contains(expected) || Instant::now() > deadline {
+                return pane;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = Command::new("tmux")
+            .args(["-L", &self.socket, "kill-server"])
+            .output();
+    }
+}
+
+#[test]
+#[ignore = "needs tmux; run with --ignored"]
+fn test_tmux_types_and_runs() {
+    let server = Server::start("type");
+    server
+        .executor()
+        .execute(
+            &[
+                Directive::Type("echo hello-$((6*7)); echo done".into()),
+                Directive::Run,
+            ],
+            0,
+            0,
+        )
+        .unwrap();
+    let pane = server.wait_for("demo", "done\n");
+    assert!(
+        pane.contains("$ echo hello-$((6*7)); echo done\n"),
+        "{pane}"
+    );
+    assert!(pane.contains("hello-42\ndone\n"), "{pane}");
+}
+
+#[test]
+#[ignore = "needs tmux; run with --ignored"]
+fn test_tmux_key_and_clear() {
+    let server = Server::start("key");
+    let executor = server.executor();
+    executor
+        .execute(
+            &[
+                Directive::Type("sleep 30{return}".into()),
+                Directive::Key("ctrl+c".into()),
+                Directive::Type("echo interrupted\n".into()),
+            ],
+            0,
+            0,
+        )
+        .unwrap();
+    let pane = server.wait_for("demo", "interrupted\n$");
+    assert!(pane.contains("interrupted\n$"), "{pane}");
+
+    executor.execute(&[Directive::Clear], 0, 0).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut pane = String::new();
+    while Instant::now() < deadline {
+        pane = run_command(&server.tmux("demo").capture_pane_command()).unwrap();
+        if !pane.contains("interrupted") {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!pane.contains("interrupted"), "{pane}");
+}
+
+#[test]
+#[ignore = "needs tmux; run with --ignored"]
+fn test_tmux_focus_switches_window() {
+    let server = Server::start("focus");
+    let status = Command::new("tmux")
+        .args([
+            "-L",
+            &server.socket,
+            "new-window",
+            "-d",
+            "-t",
+            "demo",
+            "-n",
+            "logs",
+        ])
+        .arg(SHELL)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    server
+        .executor()
+        .execute(
+            &[
+                Directive::Focus("logs".into()),
+                Directive::Type("echo in-logs\n".into()),
+            ],
+            0,
+            0,
+        )
+        .unwrap();
+    let pane = server.wait_for("demo:logs", "in-logs\n");
+    assert!(pane.contains("in-logs\n"), "{pane}");
+    let first = run_command(&server.tmux("demo:0").capture_pane_command()).unwrap();
+    assert!(!first.contains("in-logs"), "{first}");
+
+    let active = Command::new("tmux")
+        .args(["-L", &server.socket, "display-message", "-p", "-t", "demo"])
+        .arg("#{window_name}")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&active.stdout).trim(), "logs");
+}
+
+#[test]
+#[ignore = "needs tmux; run with --ignored"]
+fn test_tmux_reports_the_failing_action() {
+    let server = Server::start("fail");
+    let err = server
+        .executor()
+        .execute(&[Directive::Run, Directive::Focus("nowhere".into())], 0, 0)
+        .unwrap_err();
+    let failed = err
+        .downcast_ref::<code_monkey::agent::ActionFailed>()
+        .unwrap();
+    assert_eq!(failed.index, 1);
+    assert!(
+        failed.message.starts_with("tmux error"),
+        "{}",
+        failed.message
+    );
+}